@@ -0,0 +1,456 @@
+use std::fmt;
+
+use crate::fairy::{MoveComponent, MoveMode, Movement, PieceDef, leaps};
+
+/// Reason a Betza string was rejected. Positions count characters from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BetzaError {
+    Empty,
+    UnknownModifier {
+        position: usize,
+        found: char,
+    },
+    UnknownAtom {
+        position: usize,
+        found: char,
+    },
+    /// Modifiers with no atom after them.
+    MissingAtom {
+        position: usize,
+    },
+    /// `p` and `g` on the same atom.
+    ConflictingModifiers {
+        position: usize,
+    },
+    BadRange {
+        position: usize,
+        range: String,
+    },
+    /// The direction modifiers leave the atom without a single move.
+    NoMoves {
+        position: usize,
+    },
+}
+
+impl fmt::Display for BetzaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BetzaError::Empty => write!(f, "empty Betza notation"),
+            BetzaError::UnknownModifier { position, found } => {
+                write!(f, "unknown modifier '{found}' at position {position}")
+            }
+            BetzaError::UnknownAtom { position, found } => {
+                write!(f, "unknown atom '{found}' at position {position}")
+            }
+            BetzaError::MissingAtom { position } => {
+                write!(f, "expected an atom at position {position}")
+            }
+            BetzaError::ConflictingModifiers { position } => {
+                write!(f, "'p' and 'g' on the same atom at position {position}")
+            }
+            BetzaError::BadRange { position, range } => {
+                write!(f, "invalid range '{range}' at position {position}")
+            }
+            BetzaError::NoMoves { position } => {
+                write!(
+                    f,
+                    "directions leave no moves for the atom at position {position}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BetzaError {}
+
+/// The basic leaper of an atom letter, as (file, rank) distances.
+fn leaper(atom: char) -> Option<(i32, i32)> {
+    Some(match atom {
+        'W' => (0, 1),
+        'F' => (1, 1),
+        'D' => (0, 2),
+        'N' => (1, 2),
+        'A' => (2, 2),
+        'H' => (0, 3),
+        'C' => (1, 3),
+        'Z' => (2, 3),
+        'G' => (3, 3),
+        _ => return None,
+    })
+}
+
+/// The leapers an atom stands for and whether it rides them by default.
+fn expand(atom: char) -> Option<(Vec<(i32, i32)>, bool)> {
+    Some(match atom {
+        'K' => (vec![(0, 1), (1, 1)], false),
+        'R' => (vec![(0, 1)], true),
+        'B' => (vec![(1, 1)], true),
+        'Q' => (vec![(0, 1), (1, 1)], true),
+        _ => (vec![leaper(atom)?], false),
+    })
+}
+
+/// A run of direction modifiers: a single letter, a doubled one (`ff`), or
+/// a vertical and a horizontal letter read together (`fr`, `fs`, `rf`...).
+#[derive(Clone, Copy, Debug)]
+struct Direction {
+    vertical: Option<char>,
+    horizontal: Option<char>,
+    /// Whether the vertical part of the move must be at least as long as the
+    /// horizontal one, or the other way round. `None` takes either.
+    vertical_dominant: Option<bool>,
+}
+
+impl Direction {
+    /// `f b l r` alone take every move with a step their way, so `fN` is
+    /// all four forward knight moves. `v` and `s` take the moves long that
+    /// way.
+    fn single(c: char) -> Direction {
+        let vertical = is_vertical(c);
+        Direction {
+            vertical: vertical.then_some(c),
+            horizontal: (!vertical).then_some(c),
+            vertical_dominant: match c {
+                'v' => Some(true),
+                's' => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    /// The moves of `c` long its way, for a doubled letter or one paired
+    /// with `v`/`s` on its own axis: `ffN` and `fvN` are the two narrow
+    /// forward knight moves.
+    fn narrow(c: char) -> Direction {
+        Direction {
+            vertical_dominant: Some(is_vertical(c)),
+            ..Direction::single(c)
+        }
+    }
+
+    fn pair(first: char, second: char) -> Direction {
+        let first_vertical = is_vertical(first);
+        let (vertical, horizontal) = if first_vertical {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        // `s` and `v` say which way the move is long, otherwise the first letter does
+        let vertical_dominant = match (vertical, horizontal) {
+            (_, 's') => false,
+            ('v', _) => true,
+            _ => first_vertical,
+        };
+        Direction {
+            vertical: Some(vertical),
+            horizontal: Some(horizontal),
+            vertical_dominant: Some(vertical_dominant),
+        }
+    }
+
+    fn allows(self, (df, dr): (i32, i32)) -> bool {
+        let vertical_ok = match self.vertical {
+            Some('f') => dr > 0,
+            Some('b') => dr < 0,
+            Some(_) => dr != 0,
+            None => true,
+        };
+        let horizontal_ok = match self.horizontal {
+            Some('r') => df > 0,
+            Some('l') => df < 0,
+            Some(_) => df != 0,
+            None => true,
+        };
+        let dominance_ok = match self.vertical_dominant {
+            Some(true) => dr.abs() >= df.abs(),
+            Some(false) => df.abs() >= dr.abs(),
+            None => true,
+        };
+        vertical_ok && horizontal_ok && dominance_ok
+    }
+}
+
+fn is_vertical(c: char) -> bool {
+    matches!(c, 'f' | 'b' | 'v')
+}
+
+fn is_direction(c: char) -> bool {
+    matches!(c, 'f' | 'b' | 'v' | 'l' | 'r' | 's')
+}
+
+/// Groups direction letters into `Direction`s: two letters on different
+/// axes read as one, and so do a doubled letter and a letter with the `v`
+/// or `s` of its axis. Anything else goes letter by letter.
+fn directions(letters: &[char]) -> Vec<Direction> {
+    let mut directions = Vec::new();
+    let mut i = 0;
+    while i < letters.len() {
+        let c = letters[i];
+        match letters.get(i + 1) {
+            Some(&next) if is_vertical(c) != is_vertical(next) => {
+                directions.push(Direction::pair(c, next));
+                i += 2;
+            }
+            Some(&next) if next == c || matches!(next, 'v' | 's') => {
+                directions.push(Direction::narrow(c));
+                i += 2;
+            }
+            Some(&next) if matches!(c, 'v' | 's') => {
+                directions.push(Direction::narrow(next));
+                i += 2;
+            }
+            _ => {
+                directions.push(Direction::single(letters[i]));
+                i += 1;
+            }
+        }
+    }
+    directions
+}
+
+/// Parses Betza funny notation, with the XBetza modifiers this engine can
+/// play, into move components.
+///
+/// Atoms are the leapers `W F D N A H C Z G` and the compounds `K`, and the
+/// riders `R B Q`. A doubled leaper rides (`NN`, the nightrider) and a
+/// number limits a ride (`R4`, `W2`; `0` for no limit). Each atom may be
+/// preceded by modifiers:
+///
+/// - `m` moves only, `c` captures only (`e`, capturing en passant, counts as
+///   `c`: en passant itself belongs to pawns),
+/// - `p` hops a screen like a cannon, `g` lands right behind it like a
+///   grasshopper,
+/// - `f b l r` pick the forward, backward, left or right moves, `v` and `s`
+///   the vertical or sideways ones, and pairs such as `fr` or `fs` the moves
+///   both ways, the `s`/`v` letter or else the first one saying which way the
+///   move is long. A doubled letter (`ff`) keeps the moves long its way.
+///   Directions are seen from White's side.
+pub fn parse(notation: &str) -> Result<Vec<MoveComponent>, BetzaError> {
+    let chars: Vec<char> = notation.chars().collect();
+    if chars.is_empty() {
+        return Err(BetzaError::Empty);
+    }
+
+    let mut components = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let (mut moves, mut captures, mut hop, mut grasshop) = (false, false, false, false);
+        let mut direction_letters = Vec::new();
+        while let Some(&c) = chars.get(i).filter(|c| c.is_ascii_lowercase()) {
+            match c {
+                'm' => moves = true,
+                'c' | 'e' => captures = true,
+                'p' => hop = true,
+                'g' => grasshop = true,
+                c if is_direction(c) => direction_letters.push(c),
+                found => {
+                    return Err(BetzaError::UnknownModifier { position: i, found });
+                }
+            }
+            i += 1;
+        }
+        if hop && grasshop {
+            return Err(BetzaError::ConflictingModifiers { position: start });
+        }
+
+        let Some(&atom) = chars.get(i) else {
+            return Err(BetzaError::MissingAtom { position: i });
+        };
+        let Some((bases, mut rides)) = expand(atom) else {
+            return Err(BetzaError::UnknownAtom {
+                position: i,
+                found: atom,
+            });
+        };
+        i += 1;
+
+        let mut range = None;
+        if leaper(atom).is_some() && chars.get(i) == Some(&atom) {
+            rides = true;
+            i += 1;
+        } else if chars.get(i).is_some_and(char::is_ascii_digit) {
+            let digits_start = i;
+            while chars.get(i).is_some_and(char::is_ascii_digit) {
+                i += 1;
+            }
+            let digits: String = chars[digits_start..i].iter().collect();
+            let limit: u8 = digits.parse().map_err(|_| BetzaError::BadRange {
+                position: digits_start,
+                range: digits.clone(),
+            })?;
+            rides = true;
+            range = (limit > 0).then_some(limit);
+        }
+
+        let movement = if hop {
+            Movement::Hop
+        } else if grasshop {
+            Movement::Grasshop
+        } else if rides {
+            Movement::Ride { range }
+        } else {
+            Movement::Leap
+        };
+        let mode = match (moves, captures) {
+            (true, false) => MoveMode::MoveOnly,
+            (false, true) => MoveMode::CaptureOnly,
+            _ => MoveMode::MoveOrCapture,
+        };
+
+        let directions = directions(&direction_letters);
+        let before = components.len();
+        for (a, b) in bases {
+            let offsets: Vec<(i32, i32)> = leaps(a, b)
+                .into_iter()
+                .filter(|&offset| {
+                    directions.is_empty() || directions.iter().any(|d| d.allows(offset))
+                })
+                .collect();
+            if !offsets.is_empty() {
+                components.push(MoveComponent {
+                    offsets,
+                    movement,
+                    mode,
+                });
+            }
+        }
+        if components.len() == before {
+            return Err(BetzaError::NoMoves { position: start });
+        }
+    }
+    Ok(components)
+}
+
+impl PieceDef {
+    /// A piece moving as `notation` says, neither royal nor a pawn.
+    pub fn from_betza(name: &str, letter: char, notation: &str) -> Result<PieceDef, BetzaError> {
+        Ok(PieceDef::new(name, letter, parse(notation)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::WideBitBoard,
+        fairy::CompiledPiece,
+        geometry::BoardGeometry,
+        rendering::{PieceColor, PieceType},
+    };
+
+    /// Whether two definitions go to the same squares from everywhere on a
+    /// crowded board, for both sides.
+    fn same_moves(a: &PieceDef, b: &PieceDef) -> bool {
+        let g = BoardGeometry::STANDARD;
+        let (a, b) = (CompiledPiece::new(a, g), CompiledPiece::new(b, g));
+        let occ = WideBitBoard(0x0042_1000_8124_2400);
+        PieceColor::ALL
+            .into_iter()
+            .all(|color| (0..64).all(|sq| a.reach(color, sq, occ) == b.reach(color, sq, occ)))
+    }
+
+    #[test]
+    fn parses_the_standard_pieces() {
+        for (notation, pt) in [
+            ("fmWfceF", PieceType::Pawn),
+            ("N", PieceType::Knight),
+            ("B", PieceType::Bishop),
+            ("R", PieceType::Rook),
+            ("FF", PieceType::Bishop),
+            ("RB", PieceType::Queen),
+            ("Q", PieceType::Queen),
+            ("K", PieceType::King),
+            ("WF", PieceType::King),
+        ] {
+            let def = PieceDef::from_betza("piece", 'X', notation).unwrap();
+            assert!(same_moves(&def, &PieceDef::standard(pt)), "{notation}");
+        }
+    }
+
+    #[test]
+    fn parses_fairy_pieces() {
+        for (notation, def) in [
+            ("BN", PieceDef::archbishop()),
+            ("RN", PieceDef::chancellor()),
+            ("mRcpR", PieceDef::cannon()),
+            ("BNN", PieceDef::unicorn()),
+            ("Z", PieceDef::zebra()),
+            ("FA", PieceDef::elephant()),
+            ("gQ", PieceDef::grasshopper()),
+        ] {
+            let parsed = PieceDef::from_betza(&def.name, def.letter, notation).unwrap();
+            assert!(same_moves(&parsed, &def), "{notation}");
+        }
+    }
+
+    #[test]
+    fn directions_pick_out_moves() {
+        let offsets = |notation: &str| -> Vec<(i32, i32)> {
+            let mut offsets: Vec<_> = parse(notation)
+                .unwrap()
+                .into_iter()
+                .flat_map(|c| c.offsets)
+                .collect();
+            offsets.sort();
+            offsets
+        };
+        assert_eq!(offsets("W").len(), 4);
+        // Steps any orthogonal way, captures diagonally forward
+        assert_eq!(offsets("WfceF").len(), 6);
+        assert_eq!(offsets("fN"), [(-2, 1), (-1, 2), (1, 2), (2, 1)]);
+        assert_eq!(offsets("ffN"), [(-1, 2), (1, 2)]);
+        assert_eq!(offsets("fvN"), [(-1, 2), (1, 2)]);
+        assert_eq!(offsets("lN"), [(-2, -1), (-2, 1), (-1, -2), (-1, 2)]);
+        assert_eq!(offsets("vN"), [(-1, -2), (-1, 2), (1, -2), (1, 2)]);
+        assert_eq!(offsets("fsN"), [(-2, 1), (2, 1)]);
+        assert_eq!(offsets("frN"), [(1, 2)]);
+        assert_eq!(offsets("rfN"), [(2, 1)]);
+        assert_eq!(offsets("vW"), [(0, -1), (0, 1)]);
+        assert_eq!(offsets("flF"), [(-1, 1)]);
+        assert_eq!(offsets("bK"), [(-1, -1), (0, -1), (1, -1)]);
+
+        let limited = parse("W2").unwrap();
+        assert_eq!(limited[0].movement, Movement::Ride { range: Some(2) });
+        assert_eq!(
+            parse("R0").unwrap()[0].movement,
+            Movement::Ride { range: None }
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_character() {
+        assert_eq!(parse(""), Err(BetzaError::Empty));
+        assert_eq!(
+            parse("BX"),
+            Err(BetzaError::UnknownAtom {
+                position: 1,
+                found: 'X'
+            })
+        );
+        assert_eq!(
+            parse("fmWqF"),
+            Err(BetzaError::UnknownModifier {
+                position: 3,
+                found: 'q'
+            })
+        );
+        assert_eq!(parse("Nfc"), Err(BetzaError::MissingAtom { position: 3 }));
+        assert_eq!(
+            parse("R300"),
+            Err(BetzaError::BadRange {
+                position: 1,
+                range: "300".to_string()
+            })
+        );
+        assert_eq!(parse("NfrW"), Err(BetzaError::NoMoves { position: 1 }));
+        assert_eq!(
+            parse("pgR"),
+            Err(BetzaError::ConflictingModifiers { position: 0 })
+        );
+        assert_eq!(
+            parse("B N").unwrap_err().to_string(),
+            "unknown atom ' ' at position 1"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Command-line UCI engine, for chess GUIs and tournament managers.
+//!
+//! `--variant <rules.ron>` loads a variant rule file and offers it as a
+//! value of the `UCI_Variant` option.
+
+use std::{env, io, process};
+
+use enhanced_chess::{uci, variant::Variant};
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let variant_path = args
+        .iter()
+        .position(|arg| arg == "--variant")
+        .and_then(|i| args.get(i + 1));
+
+    match variant_path {
+        Some(path) => {
+            let variant = Variant::load(path).unwrap_or_else(|err| {
+                eprintln!("{path}: {err}");
+                process::exit(1);
+            });
+            uci::run_variant(io::stdin().lock(), io::stdout(), variant)
+        }
+        None => uci::run(io::stdin().lock(), io::stdout()),
+    }
+}
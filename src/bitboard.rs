@@ -1,33 +1,33 @@
 use std::fmt;
 
 macro_rules! impl_unary_bit_ops {
-    ($trait:ident, $method:ident) => {
-        impl std::ops::$trait for BitBoard {
-            type Output = BitBoard;
-            #[inline] fn $method(self) -> BitBoard { BitBoard(self.0.$method()) }
+    ($bb:ident; $trait:ident, $method:ident) => {
+        impl std::ops::$trait for $bb {
+            type Output = $bb;
+            #[inline] fn $method(self) -> $bb { $bb(self.0.$method()) }
         }
 
-        impl std::ops::$trait for &BitBoard {
-            type Output = BitBoard;
-            #[inline] fn $method(self) -> BitBoard { BitBoard(self.0.$method()) }
+        impl std::ops::$trait for &$bb {
+            type Output = $bb;
+            #[inline] fn $method(self) -> $bb { $bb(self.0.$method()) }
         }
     };
 }
 
 macro_rules! impl_shift_ops {
-    ($(($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident)),*) => {
+    ($bb:ident; $(($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident)),*) => {
         $(
-            impl std::ops::$trait<usize> for BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: usize) -> BitBoard { BitBoard(self.0.$method(rhs)) }
+            impl std::ops::$trait<usize> for $bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: usize) -> $bb { $bb(self.0.$method(rhs)) }
             }
 
-            impl std::ops::$trait<usize> for &BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: usize) -> BitBoard { BitBoard(self.0.$method(rhs)) }
+            impl std::ops::$trait<usize> for &$bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: usize) -> $bb { $bb(self.0.$method(rhs)) }
             }
 
-            impl std::ops::$assign_trait<usize> for BitBoard {
+            impl std::ops::$assign_trait<usize> for $bb {
                 #[inline] fn $assign_method(&mut self, rhs: usize) { self.0.$assign_method(rhs); }
             }
         )*
@@ -35,87 +35,87 @@ macro_rules! impl_shift_ops {
 }
 
 macro_rules! impl_bit_ops {
-    ($(($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident)),*) => {
+    ($bb:ident, $inner:ty; $(($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident)),*) => {
         $(
             // --- Value and Reference Permutations ---
-            // BitBoard op BitBoard
-            impl std::ops::$trait<BitBoard> for BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: BitBoard) -> BitBoard { BitBoard(self.0.$method(rhs.0)) }
+            // $bb op $bb
+            impl std::ops::$trait<$bb> for $bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: $bb) -> $bb { $bb(self.0.$method(rhs.0)) }
             }
-            // &BitBoard op BitBoard
-            impl std::ops::$trait<BitBoard> for &BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: BitBoard) -> BitBoard { (*self).$method(rhs) }
+            // &$bb op $bb
+            impl std::ops::$trait<$bb> for &$bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: $bb) -> $bb { (*self).$method(rhs) }
             }
-            // BitBoard op &BitBoard
-            impl std::ops::$trait<&BitBoard> for BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: &BitBoard) -> BitBoard { self.$method(*rhs) }
+            // $bb op &$bb
+            impl std::ops::$trait<&$bb> for $bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: &$bb) -> $bb { self.$method(*rhs) }
             }
-            // &BitBoard op &BitBoard
-            impl std::ops::$trait<&BitBoard> for &BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: &BitBoard) -> BitBoard { (*self).$method(*rhs) }
+            // &$bb op &$bb
+            impl std::ops::$trait<&$bb> for &$bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: &$bb) -> $bb { (*self).$method(*rhs) }
             }
 
             // --- Assignment Permutations ---
-            // BitBoard op= BitBoard
-            impl std::ops::$assign_trait<BitBoard> for BitBoard {
-                #[inline] fn $assign_method(&mut self, rhs: BitBoard) { self.0.$assign_method(rhs.0); }
+            // $bb op= $bb
+            impl std::ops::$assign_trait<$bb> for $bb {
+                #[inline] fn $assign_method(&mut self, rhs: $bb) { self.0.$assign_method(rhs.0); }
             }
-            // BitBoard op= &BitBoard
-            impl std::ops::$assign_trait<&BitBoard> for BitBoard {
-                #[inline] fn $assign_method(&mut self, rhs: &BitBoard) { self.0.$assign_method(rhs.0); }
+            // $bb op= &$bb
+            impl std::ops::$assign_trait<&$bb> for $bb {
+                #[inline] fn $assign_method(&mut self, rhs: &$bb) { self.0.$assign_method(rhs.0); }
             }
 
-            impl_unsigned_bit_ops!($trait, $method, $assign_trait, $assign_method);
+            impl_unsigned_bit_ops!($bb, $inner; $trait, $method, $assign_trait, $assign_method);
         )*
     };
 }
 
 macro_rules! impl_unsigned_bit_ops {
-    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident) => {
-        impl_unsigned_bit_ops_for!($trait, $method, $assign_trait, $assign_method;
+    ($bb:ident, $inner:ty; $trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident) => {
+        impl_unsigned_bit_ops_for!($bb, $inner; $trait, $method, $assign_trait, $assign_method;
             u8, u16, u32, u64, u128, usize
         );
     };
 }
 
 macro_rules! impl_unsigned_bit_ops_for {
-    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident; $($t:ty),*) => {
+    ($bb:ident, $inner:ty; $trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident; $($t:ty),*) => {
         $(
-            // BitBoard op t
-            impl std::ops::$trait<$t> for BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: $t) -> BitBoard { BitBoard(self.0.$method(rhs as u64)) }
+            // $bb op t
+            impl std::ops::$trait<$t> for $bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: $t) -> $bb { $bb(self.0.$method(rhs as $inner)) }
             }
 
-            // &BitBoard op t
-            impl std::ops::$trait<$t> for &BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: $t) -> BitBoard { (*self).$method(rhs) }
+            // &$bb op t
+            impl std::ops::$trait<$t> for &$bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: $t) -> $bb { (*self).$method(rhs) }
             }
 
-            // BitBoard op &t
-            impl std::ops::$trait<&$t> for BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: &$t) -> BitBoard { self.$method(*rhs) }
+            // $bb op &t
+            impl std::ops::$trait<&$t> for $bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: &$t) -> $bb { self.$method(*rhs) }
             }
 
-            // &BitBoard op &t
-            impl std::ops::$trait<&$t> for &BitBoard {
-                type Output = BitBoard;
-                #[inline] fn $method(self, rhs: &$t) -> BitBoard { (*self).$method(*rhs) }
+            // &$bb op &t
+            impl std::ops::$trait<&$t> for &$bb {
+                type Output = $bb;
+                #[inline] fn $method(self, rhs: &$t) -> $bb { (*self).$method(*rhs) }
             }
 
-            // BitBoard op= t
-            impl std::ops::$assign_trait<$t> for BitBoard {
-                #[inline] fn $assign_method(&mut self, rhs: $t) { self.0.$assign_method(rhs as u64); }
+            // $bb op= t
+            impl std::ops::$assign_trait<$t> for $bb {
+                #[inline] fn $assign_method(&mut self, rhs: $t) { self.0.$assign_method(rhs as $inner); }
             }
 
-            // BitBoard op= &t
-            impl std::ops::$assign_trait<&$t> for BitBoard {
+            // $bb op= &t
+            impl std::ops::$assign_trait<&$t> for $bb {
                 #[inline] fn $assign_method(&mut self, rhs: &$t) { self.$assign_method(*rhs) }
             }
         )*
@@ -128,14 +128,14 @@ macro_rules! impl_unsigned_bit_ops_for {
 pub struct BitBoard(pub u64);
 
 // Yeah, this is overkill, but macros are fun !
-impl_unary_bit_ops!(Not, not);
+impl_unary_bit_ops!(BitBoard; Not, not);
 
-impl_shift_ops!(
+impl_shift_ops!(BitBoard;
     (Shl, shl, ShlAssign, shl_assign),
     (Shr, shr, ShrAssign, shr_assign)
 );
 
-impl_bit_ops!(
+impl_bit_ops!(BitBoard, u64;
     (BitAnd, bitand, BitAndAssign, bitand_assign),
     (BitOr, bitor, BitOrAssign, bitor_assign),
     (BitXor, bitxor, BitXorAssign, bitxor_assign),
@@ -163,17 +163,21 @@ impl BitBoard {
         BitBoard(1 << index)
     }
 
+    /// Squares of the set bits, lowest first. Prefer iterating the board
+    /// directly, which doesn't allocate.
     pub fn get_piece_positions(&self) -> Vec<u8> {
-        let mut positions = Vec::new();
-        let mut board = self.clone();
-        while board != BitBoard(0) {
-            // Get index of the lowest set bit (0-63)
-            let sq = board.0.trailing_zeros() as u8;
-            positions.push(sq);
-            // Clear the lowest set bit
-            board &= board - BitBoard(1);
+        self.into_iter().collect()
+    }
+
+    /// Clears the lowest set bit and returns its square.
+    #[inline]
+    pub fn pop_lsb(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
         }
-        positions
+        let sq = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(sq)
     }
 
     #[inline]
@@ -192,6 +196,121 @@ impl BitBoard {
     }
 }
 
+/// Iterator over the squares of a `BitBoard`, lowest first.
+pub struct Squares(BitBoard);
+
+impl Iterator for Squares {
+    type Item = u8;
+
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        self.0.pop_lsb()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Squares {}
+
+impl IntoIterator for BitBoard {
+    type Item = u8;
+    type IntoIter = Squares;
+
+    #[inline]
+    fn into_iter(self) -> Squares {
+        Squares(self)
+    }
+}
+
+/// Bitboard for boards of up to 128 squares, such as 10x8 (Capablanca), 10x10
+/// and 12x8 (Courier). Squares are numbered `row * width + col`, so unlike a
+/// `BitBoard` its shifts only mean something together with a `BoardGeometry`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WideBitBoard(pub u128);
+
+impl_unary_bit_ops!(WideBitBoard; Not, not);
+
+impl_shift_ops!(WideBitBoard;
+    (Shl, shl, ShlAssign, shl_assign),
+    (Shr, shr, ShrAssign, shr_assign)
+);
+
+impl_bit_ops!(WideBitBoard, u128;
+    (BitAnd, bitand, BitAndAssign, bitand_assign),
+    (BitOr, bitor, BitOrAssign, bitor_assign),
+    (BitXor, bitxor, BitXorAssign, bitxor_assign),
+    (Add, add, AddAssign, add_assign),
+    (Sub, sub, SubAssign, sub_assign),
+    (Mul, mul, MulAssign, mul_assign),
+    (Div, div, DivAssign, div_assign)
+);
+
+impl WideBitBoard {
+    pub const EMPTY: WideBitBoard = WideBitBoard(0);
+
+    pub fn from_index(index: u8) -> WideBitBoard {
+        WideBitBoard(1 << index)
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline]
+    pub fn contains(self, index: u8) -> bool {
+        self.0 >> index & 1 == 1
+    }
+
+    #[inline]
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Clears the lowest set bit and returns its square.
+    #[inline]
+    pub fn pop_lsb(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+        let sq = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+}
+
+/// Iterator over the squares of a `WideBitBoard`, lowest first.
+pub struct WideSquares(WideBitBoard);
+
+impl Iterator for WideSquares {
+    type Item = u8;
+
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        self.0.pop_lsb()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for WideSquares {}
+
+impl IntoIterator for WideBitBoard {
+    type Item = u8;
+    type IntoIter = WideSquares;
+
+    #[inline]
+    fn into_iter(self) -> WideSquares {
+        WideSquares(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -537,4 +656,26 @@ mod tests {
         let bbres = BitBoard(3);
         assert_eq!(bblhs, bbres);
     }
+
+    #[test]
+    fn iterates_squares() {
+        let mut bb = BitBoard(0x8000_0000_0000_0011);
+        assert_eq!(bb.into_iter().len(), 3);
+        assert_eq!(bb.into_iter().collect::<Vec<_>>(), [0, 4, 63]);
+        assert_eq!(bb.get_piece_positions(), [0, 4, 63]);
+
+        assert_eq!(bb.pop_lsb(), Some(0));
+        assert_eq!(bb, BitBoard(0x8000_0000_0000_0010));
+        assert_eq!(BitBoard(0).into_iter().next(), None);
+    }
+
+    #[test]
+    fn wide_bitboard_reaches_past_64_squares() {
+        let mut bb = WideBitBoard::from_index(99) | WideBitBoard::from_index(3);
+        assert!(bb.contains(99) && !bb.contains(64));
+        assert_eq!((bb << 1usize).into_iter().collect::<Vec<_>>(), [4, 100]);
+        assert_eq!(!WideBitBoard::EMPTY & bb, bb);
+        assert_eq!(bb.pop_lsb(), Some(3));
+        assert_eq!(bb.into_iter().len(), 1);
+    }
 }
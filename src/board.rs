@@ -6,9 +6,12 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
 };
 
-use crate::rendering::{ChessPiece, PieceType};
+use crate::{
+    four_player::{self, FourPlayerState},
+    geometry::BoardGeometry,
+    rendering::{FourPlayerPiece, PieceType, RosterPiece},
+};
 
-pub const BOARD_SIZE: u8 = 8;
 pub const SQUARE_SIZE: f32 = 50.0;
 
 #[derive(Component)]
@@ -18,11 +21,56 @@ pub struct BoardCoordinates {
 }
 
 impl BoardCoordinates {
+    /// Coordinates of a square of the standard 8x8 board.
     pub fn from_bit(bit: u8) -> Self {
+        Self::from_square(bit, BoardGeometry::STANDARD)
+    }
+
+    /// Index of the square on the standard 8x8 board.
+    pub fn to_bit(&self) -> u8 {
+        self.to_square(BoardGeometry::STANDARD)
+    }
+
+    pub fn from_square(sq: u8, geometry: BoardGeometry) -> Self {
         Self {
-            col: bit % 8,
-            row: bit / 8,
+            col: geometry.col(sq),
+            row: geometry.row(sq),
+        }
+    }
+
+    pub fn to_square(&self, geometry: BoardGeometry) -> u8 {
+        geometry.square(self.col, self.row)
+    }
+
+    /// Parses an `a1`-style square name of the standard board.
+    pub fn from_algebraic(s: &str) -> Option<Self> {
+        Self::from_algebraic_in(s, BoardGeometry::STANDARD)
+    }
+
+    /// Parses a square name on a board of any size, where ranks past the
+    /// ninth take two digits (`j10`).
+    pub fn from_algebraic_in(s: &str, geometry: BoardGeometry) -> Option<Self> {
+        let mut chars = s.chars();
+        let file = chars.next()?;
+        let rank = chars.as_str();
+        if !file.is_ascii_lowercase()
+            || rank.starts_with('0')
+            || !rank.bytes().all(|b| b.is_ascii_digit())
+        {
+            return None;
         }
+        let col = file as u8 - b'a';
+        let row = rank.parse::<u8>().ok()?.checked_sub(1)?;
+        (col < geometry.width && row < geometry.height).then_some(Self { col, row })
+    }
+
+    /// Where the centre of this square is drawn, with the board centred on
+    /// the origin.
+    pub fn translation(&self, geometry: BoardGeometry) -> Vec2 {
+        Vec2::new(
+            (self.col as f32 - geometry.width as f32 / 2.0) * SQUARE_SIZE,
+            (self.row as f32 - geometry.height as f32 / 2.0) * SQUARE_SIZE,
+        )
     }
 }
 
@@ -32,82 +80,145 @@ impl fmt::Display for BoardCoordinates {
     }
 }
 
-pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    let black_square = images.add(Image::new_fill(
-        Extent3d {
-            width: SQUARE_SIZE as u32,
-            height: SQUARE_SIZE as u32,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &LinearRgba::new(0.7, 0.7, 0.7, 1.0).to_u8_array(),
-        TextureFormat::Rgba8UnormSrgb,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    ));
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    geometry: Res<BoardGeometry>,
+) {
+    let black_square = square_image(&mut images, LinearRgba::new(0.7, 0.7, 0.7, 1.0));
+    let white_square = square_image(&mut images, LinearRgba::WHITE);
+
+    for row in 0..geometry.height {
+        for col in 0..geometry.width {
+            let is_dark = (row + col).is_multiple_of(2);
+            let image = if is_dark {
+                black_square.clone()
+            } else {
+                white_square.clone()
+            };
+            let coordinates = BoardCoordinates { col, row };
+            let translation = coordinates.translation(*geometry);
+            spawn_square(&mut commands, coordinates, translation, image);
+        }
+    }
+}
+
+/// Draws the cross-shaped four-player board and the four armies on it.
+pub fn setup_four_player(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    state: Res<FourPlayerState>,
+) {
+    commands.spawn(Camera2d);
+    let black_square = square_image(&mut images, LinearRgba::new(0.7, 0.7, 0.7, 1.0));
+    let white_square = square_image(&mut images, LinearRgba::WHITE);
+
+    for sq in four_player::squares() {
+        let coordinates = four_player::coordinates(sq);
+        let image = if (coordinates.row + coordinates.col).is_multiple_of(2) {
+            black_square.clone()
+        } else {
+            white_square.clone()
+        };
+        let translation = four_player::translation(&coordinates);
+        spawn_square(&mut commands, coordinates, translation, image);
+    }
+
+    for (sq, piece, player) in state.pieces() {
+        commands
+            .spawn(FourPlayerPiece::new(
+                piece,
+                player,
+                four_player::coordinates(sq),
+                &asset_server,
+            ))
+            .observe(on_click_piece)
+            .observe(on_drag_piece);
+    }
+}
+
+/// Lifts a piece to the pointer when it is pressed.
+pub fn on_click_piece(click: On<Pointer<Press>>, mut transforms: Query<&mut Transform>) {
+    if let Ok(mut transform) = transforms.get_mut(click.entity) {
+        transform.translation.x = click.hit.position.unwrap().x;
+        transform.translation.y = click.hit.position.unwrap().y;
+    }
+}
 
-    let white_square = images.add(Image::new_fill(
+/// Makes a piece follow the pointer while it is dragged.
+pub fn on_drag_piece(drag: On<Pointer<Drag>>, mut transforms: Query<&mut Transform>) {
+    if let Ok(mut transform) = transforms.get_mut(drag.entity) {
+        // Pointer<Drag> provides 'delta' in world space units for 2D sprites
+        transform.translation.x += drag.delta.x;
+        transform.translation.y -= drag.delta.y; // Y is often inverted in screen-to-world
+    }
+}
+
+fn square_image(images: &mut Assets<Image>, color: LinearRgba) -> Handle<Image> {
+    images.add(Image::new_fill(
         Extent3d {
             width: SQUARE_SIZE as u32,
             height: SQUARE_SIZE as u32,
             depth_or_array_layers: 1,
         },
         TextureDimension::D2,
-        &LinearRgba::WHITE.to_u8_array(),
+        &color.to_u8_array(),
         TextureFormat::Rgba8UnormSrgb,
         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    ));
-
-    for x in 0..BOARD_SIZE {
-        for y in 0..BOARD_SIZE {
-            let is_dark = (x + y) % 2 == 0;
-            let image = if is_dark {
-                black_square.clone()
-            } else {
-                white_square.clone()
-            };
+    ))
+}
 
-            commands
-                .spawn((
-                    Sprite::from_image(image),
-                    Transform::from_xyz(
-                        (x as f32 - BOARD_SIZE as f32 / 2.0) * SQUARE_SIZE,
-                        (y as f32 - BOARD_SIZE as f32 / 2.0) * SQUARE_SIZE,
-                        0.0,
-                    ),
-                    BoardCoordinates { row: x, col: y },
-                    Pickable {
-                        is_hoverable: true,        // Allows HoverMap to track it (hovering works)
-                        should_block_lower: false, // Essential: Allows the pointer to "pass through"
-                    },
-                ))
-                .observe(on_drop_piece)
-                .observe(
-                    |event: On<Pointer<Over>>,
-                     mut query: Query<&mut Sprite, With<BoardCoordinates>>| {
-                        if let Ok(mut sprite) = query.get_mut(event.entity) {
-                            sprite.color = Color::linear_rgb(1.0, 0.0, 0.0);
-                        }
-                    },
-                )
-                .observe(
-                    |event: On<Pointer<Out>>,
-                     mut query: Query<&mut Sprite, With<BoardCoordinates>>| {
-                        if let Ok(mut sprite) = query.get_mut(event.entity) {
-                            sprite.color = Color::WHITE;
-                        }
-                    },
-                );
-        }
-    }
+fn spawn_square(
+    commands: &mut Commands,
+    coordinates: BoardCoordinates,
+    translation: Vec2,
+    image: Handle<Image>,
+) {
+    commands
+        .spawn((
+            Sprite::from_image(image),
+            Transform::from_translation(translation.extend(0.0)),
+            coordinates,
+            Pickable {
+                is_hoverable: true,        // Allows HoverMap to track it (hovering works)
+                should_block_lower: false, // Essential: Allows the pointer to "pass through"
+            },
+        ))
+        .observe(on_drop_piece)
+        .observe(
+            |event: On<Pointer<Over>>, mut query: Query<&mut Sprite, With<BoardCoordinates>>| {
+                if let Ok(mut sprite) = query.get_mut(event.entity) {
+                    sprite.color = Color::linear_rgb(1.0, 0.0, 0.0);
+                }
+            },
+        )
+        .observe(
+            |event: On<Pointer<Out>>, mut query: Query<&mut Sprite, With<BoardCoordinates>>| {
+                if let Ok(mut sprite) = query.get_mut(event.entity) {
+                    sprite.color = Color::WHITE;
+                }
+            },
+        );
 }
 
+/// Pieces of either kind, built in or from a rule file.
+type PieceFilter = (
+    Or<(With<PieceType>, With<RosterPiece>)>,
+    Without<BoardCoordinates>,
+);
+/// Board squares, disjoint from `PieceFilter`.
+type TileFilter = (
+    With<BoardCoordinates>,
+    Without<PieceType>,
+    Without<RosterPiece>,
+);
+
 fn on_drop_piece(
     drop: On<Pointer<DragDrop>>,
-    mut piece_transforms: Query<&mut Transform, (With<PieceType>, Without<BoardCoordinates>)>,
-    tile_transforms: Query<&Transform, (With<BoardCoordinates>, Without<PieceType>)>,
+    mut piece_transforms: Query<&mut Transform, PieceFilter>,
+    tile_transforms: Query<&Transform, TileFilter>,
 ) {
-    println!("target {}", drop.event_target());
-    println!("dropped {}", drop.dropped);
     if let Ok(mut transform_dropped) = piece_transforms.get_mut(drop.dropped)
         && let Ok(transform_target) = tile_transforms.get(drop.event_target())
     {
@@ -0,0 +1,123 @@
+use crate::{fen::FenError, game::GameState, rendering::PieceType};
+
+/// Number of Chess960 starting positions.
+pub const POSITION_COUNT: u16 = 960;
+/// Index of the standard chess setup, RNBQKBNR.
+pub const STANDARD_POSITION: u16 = 518;
+
+/// The ten ways to place the two knights on the five squares left once the
+/// bishops and queen stand, in Scharnagl's order.
+#[rustfmt::skip]
+const KNIGHT_SQUARES: [(usize, usize); 10] = [
+    (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4),
+];
+
+/// White's back rank (a-file first) of Chess960 position `index`, following
+/// Scharnagl's numbering: the index picks, in turn, the light-squared bishop,
+/// the dark-squared bishop, the queen and the knights, leaving the king
+/// between the two rooks. `None` past the last position.
+pub fn back_rank_pieces(index: u16) -> Option<[PieceType; 8]> {
+    if index >= POSITION_COUNT {
+        return None;
+    }
+    let mut n = index as usize;
+    let mut rank: [Option<PieceType>; 8] = [None; 8];
+
+    rank[2 * (n % 4) + 1] = Some(PieceType::Bishop);
+    n /= 4;
+    rank[2 * (n % 4)] = Some(PieceType::Bishop);
+    n /= 4;
+
+    let empty = |rank: &[Option<PieceType>; 8]| -> Vec<usize> {
+        (0..8).filter(|&file| rank[file].is_none()).collect()
+    };
+    rank[empty(&rank)[n % 6]] = Some(PieceType::Queen);
+    n /= 6;
+
+    let free = empty(&rank);
+    let (first, second) = KNIGHT_SQUARES[n];
+    rank[free[first]] = Some(PieceType::Knight);
+    rank[free[second]] = Some(PieceType::Knight);
+
+    for (file, pt) in
+        empty(&rank)
+            .into_iter()
+            .zip([PieceType::Rook, PieceType::King, PieceType::Rook])
+    {
+        rank[file] = Some(pt);
+    }
+    Some(rank.map(|pt| pt.expect("every file is filled")))
+}
+
+/// FEN of Chess960 position `index`, castling rights given as X-FEN.
+pub fn start_fen(index: u16) -> Option<String> {
+    let white: String = back_rank_pieces(index)?
+        .iter()
+        .map(|pt| pt.to_char())
+        .collect();
+    let black = white.to_ascii_lowercase();
+    Some(format!(
+        "{black}/pppppppp/8/8/8/8/PPPPPPPP/{white} w KQkq - 0 1"
+    ))
+}
+
+impl GameState {
+    /// Starting position `index` (0 to 959) of Chess960.
+    pub fn chess960(index: u16) -> Option<GameState> {
+        let fen = start_fen(index)?;
+        Some(GameState::from_fen(&fen).unwrap_or_else(|err: FenError| panic!("{fen}: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{fen::START_FEN, movegen::perft};
+
+    #[test]
+    fn standard_position_is_518() {
+        assert_eq!(start_fen(STANDARD_POSITION).unwrap(), START_FEN);
+        assert_eq!(
+            start_fen(0).unwrap(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert!(back_rank_pieces(POSITION_COUNT).is_none());
+    }
+
+    #[test]
+    fn positions_are_distinct_and_valid() {
+        let mut seen = HashSet::new();
+        for index in 0..POSITION_COUNT {
+            let rank = back_rank_pieces(index).unwrap();
+            assert!(seen.insert(rank), "position {index} repeats");
+
+            let files = |pt| (0..8).filter(move |&f| rank[f] == pt);
+            let bishops: Vec<usize> = files(PieceType::Bishop).collect();
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(
+                bishops[0] % 2,
+                bishops[1] % 2,
+                "position {index}: same-colored bishops"
+            );
+            let rooks: Vec<usize> = files(PieceType::Rook).collect();
+            let king = files(PieceType::King).next().unwrap();
+            assert!(
+                rooks[0] < king && king < rooks[1],
+                "position {index}: king outside the rooks"
+            );
+        }
+    }
+
+    #[test]
+    fn every_position_round_trips_through_fen() {
+        for index in 0..POSITION_COUNT {
+            let state = GameState::chess960(index).unwrap();
+            assert_eq!(state.to_fen(), start_fen(index).unwrap());
+            // Pawns and knights, plus castling where the king on f1 and the
+            // rook on g1 only have to swap places
+            assert!((18..=21).contains(&perft(&state, 1)), "position {index}");
+        }
+    }
+}
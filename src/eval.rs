@@ -0,0 +1,523 @@
+use crate::{
+    bitboard::BitBoard,
+    game::GameState,
+    movegen::{
+        bishop_attacks, king_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks,
+    },
+    rendering::{PieceColor, PieceType},
+    wide::WideGameState,
+};
+
+// Tapered evaluation: every term has a middlegame and an endgame weight, and
+// the two totals are blended by how much non-pawn material is left. Material
+// and piece-square tables are Ronald Friederich's PeSTO values.
+
+/// Centipawn value of each piece, indexed by `PieceType as usize`. The king
+/// gets none: it is never traded. Used for move ordering, where a single
+/// figure per piece is enough.
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+#[inline]
+pub fn piece_value(pt: PieceType) -> i32 {
+    PIECE_VALUES[pt as usize]
+}
+
+const MG_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const EG_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+/// Contribution of each piece to the game phase; 24 is the starting position.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables from White's point of view, laid out as the board is
+// seen from White's side: the first row is the 8th rank.
+#[rustfmt::skip]
+const MG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const EG_PAWN: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const MG_KNIGHT: [i32; 64] = [
+   -167, -89, -34, -49,  61, -97, -15,-107,
+    -73, -41,  72,  36,  23,  62,   7, -17,
+    -47,  60,  37,  65,  84, 129,  73,  44,
+     -9,  17,  19,  53,  37,  69,  18,  22,
+    -13,   4,  16,  13,  28,  19,  21,  -8,
+    -23,  -9,  12,  10,  19,  17,  25, -16,
+    -29, -53, -12,  -3,  -1,  18, -14, -19,
+   -105, -21, -58, -33, -17, -28, -19, -23,
+];
+#[rustfmt::skip]
+const EG_KNIGHT: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+#[rustfmt::skip]
+const MG_BISHOP: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+#[rustfmt::skip]
+const EG_BISHOP: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+#[rustfmt::skip]
+const MG_ROOK: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+#[rustfmt::skip]
+const EG_ROOK: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+#[rustfmt::skip]
+const MG_QUEEN: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+#[rustfmt::skip]
+const EG_QUEEN: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+#[rustfmt::skip]
+const MG_KING: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+#[rustfmt::skip]
+const EG_KING: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+const MG_TABLES: [&[i32; 64]; 6] = [
+    &MG_PAWN, &MG_KNIGHT, &MG_BISHOP, &MG_ROOK, &MG_QUEEN, &MG_KING,
+];
+const EG_TABLES: [&[i32; 64]; 6] = [
+    &EG_PAWN, &EG_KNIGHT, &EG_BISHOP, &EG_ROOK, &EG_QUEEN, &EG_KING,
+];
+
+// Pawn structure, indexed by the pawn's rank from its own side
+const PASSED_PAWN: [Score; 8] = [
+    Score(0, 0),
+    Score(5, 10),
+    Score(10, 15),
+    Score(15, 30),
+    Score(25, 55),
+    Score(40, 90),
+    Score(60, 140),
+    Score(0, 0),
+];
+const DOUBLED_PAWN: Score = Score(-10, -25);
+const ISOLATED_PAWN: Score = Score(-10, -15);
+
+/// Per reachable square, for knights, bishops, rooks and queens. Counts are
+/// taken relative to a typical square count so that mobility stays centred.
+const MOBILITY: [(Score, i32); 4] = [
+    (Score(4, 4), 4),
+    (Score(5, 5), 6),
+    (Score(2, 4), 6),
+    (Score(1, 2), 12),
+];
+
+// King safety, middlegame only
+const PAWN_SHIELD: i32 = 12;
+/// Attack units per piece type hitting the squares around the enemy king.
+const KING_ATTACK_UNITS: [i32; 6] = [0, 2, 2, 3, 5, 0];
+
+const FILE_A: u64 = 0x0101010101010101;
+
+/// A middlegame / endgame pair of centipawn values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Score(i32, i32);
+
+impl std::ops::Add for Score {
+    type Output = Score;
+    fn add(self, rhs: Score) -> Score {
+        Score(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl std::ops::AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        *self = *self + rhs;
+    }
+}
+
+impl std::ops::Sub for Score {
+    type Output = Score;
+    fn sub(self, rhs: Score) -> Score {
+        Score(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl std::ops::Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, rhs: i32) -> Score {
+        Score(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+/// Static evaluation in centipawns, from the side to move's point of view.
+pub fn evaluate(state: &GameState) -> i32 {
+    let score = evaluate_white(state);
+    if state.side_to_move == PieceColor::White {
+        score
+    } else {
+        -score
+    }
+}
+
+/// Static evaluation in centipawns from White's point of view, as shown on an
+/// evaluation bar.
+pub fn evaluate_white(state: &GameState) -> i32 {
+    let white = evaluate_side(state, PieceColor::White);
+    let black = evaluate_side(state, PieceColor::Black);
+    let Score(mg, eg) = white - black;
+
+    let phase = game_phase(state);
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+/// Evaluation of a `WideGameState` from the side to move's point of view:
+/// the value of each piece in its roster, less a little for every half
+/// square it stands from the centre, and pawns gaining as they advance.
+/// Royal pieces are worth nothing, as in `PIECE_VALUES`.
+pub fn evaluate_wide(state: &WideGameState) -> i32 {
+    let g = state.geometry();
+    let roster = state.roster();
+    let mut score = 0;
+    for id in roster.ids() {
+        let def = roster.get(id);
+        if def.royal {
+            continue;
+        }
+        for (color, sign) in [(PieceColor::White, 1), (PieceColor::Black, -1)] {
+            for sq in state.piece_bb(id, color) {
+                let (col, row) = (g.col(sq) as i32, g.row(sq) as i32);
+                let position = if def.pawn {
+                    let advanced = match color {
+                        PieceColor::White => row,
+                        PieceColor::Black => g.height as i32 - 1 - row,
+                    };
+                    4 * advanced
+                } else {
+                    -(2 * col - (g.width as i32 - 1)).abs()
+                        - (2 * row - (g.height as i32 - 1)).abs()
+                };
+                score += sign * (def.value + position);
+            }
+        }
+    }
+    if state.side_to_move == PieceColor::White {
+        score
+    } else {
+        -score
+    }
+}
+
+/// 24 with all pieces on the board down to 0 with only kings and pawns left.
+/// Early promotions can push the raw count past 24, hence the clamp.
+fn game_phase(state: &GameState) -> i32 {
+    let phase: i32 = state
+        .bitboards()
+        .map(|(pt, _, bb)| PHASE_WEIGHTS[pt as usize] * bb.0.count_ones() as i32)
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+fn evaluate_side(state: &GameState, color: PieceColor) -> Score {
+    let own = state.pieces(color);
+    let occ = state.occupancy();
+    let mut score = Score::default();
+
+    for pt in PieceType::ALL {
+        for sq in state.piece_bb(pt, color) {
+            // Tables are drawn with a8 first; flip the rank for White
+            let index = match color {
+                PieceColor::White => sq ^ 56,
+                PieceColor::Black => sq,
+            } as usize;
+            score += Score(
+                MG_VALUES[pt as usize] + MG_TABLES[pt as usize][index],
+                EG_VALUES[pt as usize] + EG_TABLES[pt as usize][index],
+            );
+
+            let square = BitBoard::from_index(sq);
+            let attacks = match pt {
+                PieceType::Knight => knight_attacks(square),
+                PieceType::Bishop => bishop_attacks(square, occ),
+                PieceType::Rook => rook_attacks(square, occ),
+                PieceType::Queen => queen_attacks(square, occ),
+                PieceType::Pawn | PieceType::King => continue,
+            };
+            let (weight, typical) = MOBILITY[pt as usize - 1];
+            score += weight * ((attacks & !own).0.count_ones() as i32 - typical);
+        }
+    }
+
+    score + pawn_structure(state, color) + Score(king_safety(state, color), 0)
+}
+
+fn pawn_structure(state: &GameState, color: PieceColor) -> Score {
+    let pawns = state.piece_bb(PieceType::Pawn, color).0;
+    let enemy_pawns = state.piece_bb(PieceType::Pawn, color.opponent()).0;
+    let mut score = Score::default();
+
+    for file in 0..8 {
+        let on_file = (pawns & (FILE_A << file)).count_ones() as i32;
+        if on_file > 1 {
+            score += DOUBLED_PAWN * (on_file - 1);
+        }
+        if on_file > 0 && pawns & adjacent_files(file) == 0 {
+            score += ISOLATED_PAWN * on_file;
+        }
+    }
+
+    for sq in BitBoard(pawns) {
+        let file = sq % 8;
+        let rank = sq / 8;
+        let relative_rank = match color {
+            PieceColor::White => rank,
+            PieceColor::Black => 7 - rank,
+        };
+        // Passed: no enemy pawn in front of it on its own or a neighbouring file
+        let files = (FILE_A << file) | adjacent_files(file);
+        let ahead = match color {
+            PieceColor::White => files & (u64::MAX << 8 << (rank * 8)),
+            PieceColor::Black => files & !(u64::MAX << (rank * 8)),
+        };
+        if enemy_pawns & ahead == 0 {
+            score += PASSED_PAWN[relative_rank as usize];
+        }
+    }
+    score
+}
+
+fn adjacent_files(file: u8) -> u64 {
+    let mut mask = 0;
+    if file > 0 {
+        mask |= FILE_A << (file - 1);
+    }
+    if file < 7 {
+        mask |= FILE_A << (file + 1);
+    }
+    mask
+}
+
+/// Pawn shield in front of the king minus a penalty growing quadratically
+/// with the enemy pieces bearing down on the squares around it.
+fn king_safety(state: &GameState, color: PieceColor) -> i32 {
+    let king = state.piece_bb(PieceType::King, color);
+    if king == BitBoard(0) {
+        return 0;
+    }
+    let zone = king_attacks(king) | king;
+    let occ = state.occupancy();
+
+    let pawns = state.piece_bb(PieceType::Pawn, color);
+    let shield = match color {
+        PieceColor::White => zone << 8usize,
+        PieceColor::Black => zone >> 8usize,
+    };
+    let mut score = PAWN_SHIELD * (pawns & shield & !king).0.count_ones() as i32;
+
+    let enemy = color.opponent();
+    let mut units = 0;
+    for pt in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ] {
+        for sq in state.piece_bb(pt, enemy) {
+            let square = BitBoard::from_index(sq);
+            let attacks = match pt {
+                PieceType::Knight => knight_attacks(square),
+                PieceType::Bishop => bishop_attacks(square, occ),
+                PieceType::Rook => rook_attacks(square, occ),
+                _ => queen_attacks(square, occ),
+            };
+            if attacks & zone != BitBoard(0) {
+                units += KING_ATTACK_UNITS[pt as usize];
+            }
+        }
+    }
+    // Enemy pawns storming the king zone count too
+    for sq in state.piece_bb(PieceType::Pawn, enemy) {
+        if pawn_attacks(BitBoard::from_index(sq), enemy) & zone != BitBoard(0) {
+            units += 1;
+        }
+    }
+    score -= (units * units * 2).min(400);
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    /// Same position with colours swapped and the board flipped vertically.
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let placement: Vec<String> = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| rank.chars().map(swap_case).collect())
+            .collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let castling: String = fields[2].chars().map(swap_case).collect();
+        let ep = match fields[3] {
+            "-" => "-".to_string(),
+            sq => {
+                let rank = if sq.ends_with('3') { '6' } else { '3' };
+                format!("{}{rank}", &sq[..1])
+            }
+        };
+        format!("{} {side} {castling} {ep} 0 1", placement.join("/"))
+    }
+
+    fn swap_case(c: char) -> char {
+        if c.is_ascii_uppercase() {
+            c.to_ascii_lowercase()
+        } else {
+            c.to_ascii_uppercase()
+        }
+    }
+
+    fn eval(fen: &str) -> i32 {
+        evaluate(&GameState::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn start_position_is_balanced() {
+        assert_eq!(eval(START_FEN), 0);
+        assert_eq!(game_phase(&GameState::default()), MAX_PHASE);
+    }
+
+    #[test]
+    fn evaluation_is_colour_symmetric() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        ] {
+            assert_eq!(eval(fen), eval(&mirror(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn counts_material() {
+        // White is a queen up: good for White, bad for Black to move
+        let fen = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(eval(fen) > 800);
+        assert_eq!(eval(&fen.replace(" w ", " b ")), -eval(fen));
+    }
+
+    #[test]
+    fn pawn_structure_terms() {
+        let healthy = GameState::from_fen("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1").unwrap();
+        let doubled = GameState::from_fen("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1").unwrap();
+        assert!(
+            pawn_structure(&doubled, PieceColor::White).1
+                < pawn_structure(&healthy, PieceColor::White).1
+        );
+
+        // The c-pawn is passed, the f-pawn is held up by the g-pawn
+        let passed = GameState::from_fen("4k3/6p1/8/8/2P5/8/8/4K3 w - - 0 1").unwrap();
+        let blocked = GameState::from_fen("4k3/6p1/8/8/5P2/8/8/4K3 w - - 0 1").unwrap();
+        assert!(
+            pawn_structure(&passed, PieceColor::White).1
+                > pawn_structure(&blocked, PieceColor::White).1
+        );
+    }
+
+    #[test]
+    fn king_prefers_shelter_and_centre_by_phase() {
+        // Middlegame: a castled king behind its pawns beats one wandering out
+        let sheltered = "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1";
+        let exposed = "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2NK1/PPPP1PPP/R1BQ1R2 w - - 0 1";
+        assert!(eval(sheltered) > eval(exposed));
+
+        // Pawn endgame: the king belongs in the centre
+        let central = "8/5k2/8/8/4K3/8/4P3/8 w - - 0 1";
+        let cornered = "8/5k2/8/8/8/8/4P3/K7 w - - 0 1";
+        assert!(eval(central) > eval(cornered));
+    }
+}
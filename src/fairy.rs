@@ -0,0 +1,590 @@
+use crate::{
+    bitboard::WideBitBoard,
+    eval::piece_value,
+    geometry::BoardGeometry,
+    movegen::{BISHOP_DIRECTIONS, KING_MOVES, KNIGHT_MOVES, ROOK_DIRECTIONS},
+    rendering::{PieceColor, PieceType},
+};
+
+/// Index of a piece definition in a `Roster`.
+pub type PieceId = u8;
+
+/// What a move component may do on the square it reaches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MoveMode {
+    MoveOrCapture,
+    MoveOnly,
+    CaptureOnly,
+}
+
+impl MoveMode {
+    fn moves(self) -> bool {
+        self != MoveMode::CaptureOnly
+    }
+
+    fn captures(self) -> bool {
+        self != MoveMode::MoveOnly
+    }
+}
+
+/// How a move component uses its offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Movement {
+    /// Jumps straight to the square an offset away, over anything between.
+    Leap,
+    /// Repeats an offset until the first piece, at most `range` times if set.
+    Ride { range: Option<u8> },
+    /// Rides up to a first piece, the screen, jumps it and rides on beyond,
+    /// like the xiangqi cannon.
+    Hop,
+    /// Lands on the square right behind the first piece along the line.
+    Grasshop,
+}
+
+/// One way a piece moves: a set of (file, rank) offsets, seen from White's
+/// side of the board, and what it does with them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MoveComponent {
+    pub offsets: Vec<(i32, i32)>,
+    pub movement: Movement,
+    pub mode: MoveMode,
+}
+
+impl MoveComponent {
+    pub fn leap(offsets: &[(i32, i32)]) -> MoveComponent {
+        MoveComponent {
+            offsets: offsets.to_vec(),
+            movement: Movement::Leap,
+            mode: MoveMode::MoveOrCapture,
+        }
+    }
+
+    pub fn ride(offsets: &[(i32, i32)]) -> MoveComponent {
+        MoveComponent {
+            movement: Movement::Ride { range: None },
+            ..MoveComponent::leap(offsets)
+        }
+    }
+
+    pub fn hop(offsets: &[(i32, i32)]) -> MoveComponent {
+        MoveComponent {
+            movement: Movement::Hop,
+            ..MoveComponent::leap(offsets)
+        }
+    }
+
+    pub fn move_only(self) -> MoveComponent {
+        MoveComponent {
+            mode: MoveMode::MoveOnly,
+            ..self
+        }
+    }
+
+    pub fn capture_only(self) -> MoveComponent {
+        MoveComponent {
+            mode: MoveMode::CaptureOnly,
+            ..self
+        }
+    }
+}
+
+/// Every offset of an `(a, b)` leaper: all sign changes and the swap of its
+/// two coordinates, without repeats. `leaps(1, 2)` are the knight's moves.
+pub fn leaps(a: i32, b: i32) -> Vec<(i32, i32)> {
+    let mut offsets = Vec::new();
+    for (x, y) in [(a, b), (b, a)] {
+        for (sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let offset = (x * sx, y * sy);
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+    }
+    offsets
+}
+
+/// A kind of piece described by its moves rather than by a `PieceType`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PieceDef {
+    pub name: String,
+    /// Uppercase letter standing for the piece in FEN.
+    pub letter: char,
+    pub components: Vec<MoveComponent>,
+    /// Losing it loses the game: it may not be left in check, and it is the
+    /// piece that castles.
+    pub royal: bool,
+    /// On top of its components it steps two squares straight ahead from
+    /// its second rank, captures en passant and promotes on the last rank.
+    pub pawn: bool,
+    /// Worth in centipawns, for searching positions it plays in.
+    pub value: i32,
+}
+
+impl PieceDef {
+    /// A piece valued by `estimate_value`.
+    pub fn new(name: &str, letter: char, components: Vec<MoveComponent>) -> PieceDef {
+        let mut def = PieceDef {
+            name: name.to_string(),
+            letter: letter.to_ascii_uppercase(),
+            components,
+            royal: false,
+            pawn: false,
+            value: 0,
+        };
+        def.value = def.estimate_value();
+        def
+    }
+
+    /// A rough worth for pieces nobody priced, from how many squares they
+    /// reach on average from each square of an empty 8x8 board. Past about
+    /// six squares each one adds 35; below that worth falls twice as fast,
+    /// which puts a pawn near 100, a knight near 300, a rook near 610 and a
+    /// queen near 920.
+    pub fn estimate_value(&self) -> i32 {
+        let g = BoardGeometry::STANDARD;
+        let piece = CompiledPiece::new(self, g);
+        let reached: u32 = (0..g.squares() as u8)
+            .map(|sq| {
+                let reach = piece.reach(PieceColor::White, sq, WideBitBoard::EMPTY);
+                (reach.moves | reach.captures).count()
+            })
+            .sum();
+        // Average squares reached, in hundredths
+        let mobility = 100 * reached as i32 / g.squares() as i32;
+        (120 + 35 * mobility / 100)
+            .min(70 * (mobility - 100) / 100)
+            .max(0)
+    }
+
+    /// One of the six standard pieces.
+    pub fn standard(pt: PieceType) -> PieceDef {
+        let name = format!("{pt:?}").to_lowercase();
+        let components = match pt {
+            PieceType::Pawn => vec![
+                MoveComponent::leap(&[(0, 1)]).move_only(),
+                MoveComponent::leap(&[(-1, 1), (1, 1)]).capture_only(),
+            ],
+            PieceType::Knight => vec![MoveComponent::leap(&KNIGHT_MOVES)],
+            PieceType::Bishop => vec![MoveComponent::ride(&BISHOP_DIRECTIONS)],
+            PieceType::Rook => vec![MoveComponent::ride(&ROOK_DIRECTIONS)],
+            PieceType::Queen => vec![
+                MoveComponent::ride(&ROOK_DIRECTIONS),
+                MoveComponent::ride(&BISHOP_DIRECTIONS),
+            ],
+            PieceType::King => vec![MoveComponent::leap(&KING_MOVES)],
+        };
+        PieceDef {
+            royal: pt == PieceType::King,
+            pawn: pt == PieceType::Pawn,
+            value: piece_value(pt),
+            ..PieceDef::new(&name, pt.to_char(), components)
+        }
+    }
+
+    /// Bishop and knight.
+    pub fn archbishop() -> PieceDef {
+        PieceDef {
+            value: 825,
+            ..PieceDef::new(
+                "archbishop",
+                'A',
+                vec![
+                    MoveComponent::ride(&BISHOP_DIRECTIONS),
+                    MoveComponent::leap(&KNIGHT_MOVES),
+                ],
+            )
+        }
+    }
+
+    /// Rook and knight.
+    pub fn chancellor() -> PieceDef {
+        PieceDef {
+            value: 875,
+            ..PieceDef::new(
+                "chancellor",
+                'C',
+                vec![
+                    MoveComponent::ride(&ROOK_DIRECTIONS),
+                    MoveComponent::leap(&KNIGHT_MOVES),
+                ],
+            )
+        }
+    }
+
+    /// Moves as a rook, but captures only by hopping a screen.
+    pub fn cannon() -> PieceDef {
+        PieceDef::new(
+            "cannon",
+            'O',
+            vec![
+                MoveComponent::ride(&ROOK_DIRECTIONS).move_only(),
+                MoveComponent::hop(&ROOK_DIRECTIONS).capture_only(),
+            ],
+        )
+    }
+
+    /// Bishop and nightrider: a knight repeating its jump in a straight line.
+    pub fn unicorn() -> PieceDef {
+        PieceDef::new(
+            "unicorn",
+            'U',
+            vec![
+                MoveComponent::ride(&BISHOP_DIRECTIONS),
+                MoveComponent::ride(&KNIGHT_MOVES),
+            ],
+        )
+    }
+
+    /// The (2, 3) leaper.
+    pub fn zebra() -> PieceDef {
+        PieceDef::new("zebra", 'Z', vec![MoveComponent::leap(&leaps(2, 3))])
+    }
+
+    /// The (1, 4) leaper.
+    pub fn giraffe() -> PieceDef {
+        PieceDef::new("giraffe", 'G', vec![MoveComponent::leap(&leaps(1, 4))])
+    }
+
+    /// Ferz and alfil: one or two squares diagonally, jumping.
+    pub fn elephant() -> PieceDef {
+        PieceDef::new(
+            "elephant",
+            'E',
+            vec![MoveComponent::leap(&[leaps(1, 1), leaps(2, 2)].concat())],
+        )
+    }
+
+    /// Lands right behind the first piece along any queen line.
+    pub fn grasshopper() -> PieceDef {
+        PieceDef::new(
+            "grasshopper",
+            'H',
+            vec![MoveComponent {
+                movement: Movement::Grasshop,
+                ..MoveComponent::leap(&KING_MOVES)
+            }],
+        )
+    }
+}
+
+/// The pieces a game is played with. A `PieceId` is a position in the list
+/// and letters are unique.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Roster {
+    pieces: Vec<PieceDef>,
+}
+
+impl Roster {
+    /// The six standard pieces, each at the id `PieceType as PieceId`.
+    pub fn standard() -> Roster {
+        Roster {
+            pieces: PieceType::ALL.into_iter().map(PieceDef::standard).collect(),
+        }
+    }
+
+    /// The standard pieces followed by the archbishop and chancellor.
+    pub fn capablanca() -> Roster {
+        let mut roster = Roster::standard();
+        roster
+            .add(PieceDef::archbishop())
+            .expect("distinct letters");
+        roster
+            .add(PieceDef::chancellor())
+            .expect("distinct letters");
+        roster
+    }
+
+    /// Appends `def`, returning its id, or `None` when its letter is taken.
+    pub fn add(&mut self, def: PieceDef) -> Option<PieceId> {
+        if self.id_of(def.letter).is_some() || self.pieces.len() > PieceId::MAX as usize {
+            return None;
+        }
+        self.pieces.push(def);
+        Some(self.pieces.len() as PieceId - 1)
+    }
+
+    #[inline]
+    pub fn get(&self, id: PieceId) -> &PieceDef {
+        &self.pieces[id as usize]
+    }
+
+    /// The piece lettered `letter`, in either case.
+    pub fn id_of(&self, letter: char) -> Option<PieceId> {
+        let letter = letter.to_ascii_uppercase();
+        self.pieces
+            .iter()
+            .position(|def| def.letter == letter)
+            .map(|id| id as PieceId)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = PieceId> + use<> {
+        0..self.pieces.len() as PieceId
+    }
+
+    /// What a pawn may promote to: every piece neither a pawn nor royal.
+    pub fn promotions(&self) -> Vec<PieceId> {
+        self.ids()
+            .filter(|&id| !self.get(id).pawn && !self.get(id).royal)
+            .collect()
+    }
+}
+
+/// Squares a piece reaches from where it stands: those it may move to if
+/// they are empty, and those it may capture on if an enemy stands there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Reach {
+    pub moves: WideBitBoard,
+    pub captures: WideBitBoard,
+}
+
+#[derive(Clone, Debug)]
+enum CompiledMovement {
+    /// Indexed by `[PieceColor as usize][square]`, from `leaper_table`.
+    Leap([Vec<WideBitBoard>; 2]),
+    /// Rides, hops and grasshops walk their offsets, indexed by `PieceColor as usize`.
+    Line {
+        offsets: [Vec<(i32, i32)>; 2],
+        movement: Movement,
+    },
+}
+
+/// A `PieceDef` made ready for one board: leaps are looked up in tables
+/// generated the way `generate_table` does for the standard board, lines
+/// are walked square by square.
+#[derive(Clone, Debug)]
+pub struct CompiledPiece {
+    geometry: BoardGeometry,
+    components: Vec<(CompiledMovement, MoveMode)>,
+}
+
+impl CompiledPiece {
+    pub fn new(def: &PieceDef, geometry: BoardGeometry) -> CompiledPiece {
+        let components = def
+            .components
+            .iter()
+            .map(|component| {
+                // Black sees the board upside down
+                let black: Vec<(i32, i32)> = component
+                    .offsets
+                    .iter()
+                    .map(|&(df, dr)| (df, -dr))
+                    .collect();
+                let movement = match component.movement {
+                    Movement::Leap => CompiledMovement::Leap([
+                        geometry.leaper_table(&component.offsets),
+                        geometry.leaper_table(&black),
+                    ]),
+                    movement => CompiledMovement::Line {
+                        offsets: [component.offsets.clone(), black],
+                        movement,
+                    },
+                };
+                (movement, component.mode)
+            })
+            .collect();
+        CompiledPiece {
+            geometry,
+            components,
+        }
+    }
+
+    /// Where a piece of `color` on `sq` can go with `occ` occupied. Nothing
+    /// is filtered by occupancy, so captures include empty squares (where a
+    /// pawn could take en passant) and moves may land on pieces.
+    pub fn reach(&self, color: PieceColor, sq: u8, occ: WideBitBoard) -> Reach {
+        let mut reach = Reach::default();
+        for (movement, mode) in &self.components {
+            let squares = match movement {
+                CompiledMovement::Leap(tables) => tables[color as usize][sq as usize],
+                CompiledMovement::Line { offsets, movement } => offsets[color as usize]
+                    .iter()
+                    .fold(WideBitBoard::EMPTY, |bb, &offset| {
+                        bb | self.walk(sq, offset, *movement, occ)
+                    }),
+            };
+            if mode.moves() {
+                reach.moves |= squares;
+            }
+            if mode.captures() {
+                reach.captures |= squares;
+            }
+        }
+        reach
+    }
+
+    /// Squares reached along one line from `sq`.
+    fn walk(
+        &self,
+        sq: u8,
+        (df, dr): (i32, i32),
+        movement: Movement,
+        occ: WideBitBoard,
+    ) -> WideBitBoard {
+        let g = self.geometry;
+        let mut squares = WideBitBoard::EMPTY;
+        let mut current = sq;
+        let mut steps = 0;
+        let mut screened = false;
+        while let Some(next) = g.offset(current, df, dr) {
+            current = next;
+            steps += 1;
+            match movement {
+                Movement::Ride { range } => {
+                    squares |= WideBitBoard::from_index(next);
+                    if occ.contains(next) || range.is_some_and(|r| steps >= r as u32) {
+                        break;
+                    }
+                }
+                Movement::Hop => {
+                    if screened {
+                        squares |= WideBitBoard::from_index(next);
+                        if occ.contains(next) {
+                            break;
+                        }
+                    } else {
+                        screened = occ.contains(next);
+                    }
+                }
+                Movement::Grasshop => {
+                    if screened {
+                        squares |= WideBitBoard::from_index(next);
+                        break;
+                    }
+                    screened = occ.contains(next);
+                }
+                Movement::Leap => unreachable!("leaps are looked up in tables"),
+            }
+        }
+        squares
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::BitBoard,
+        movegen::{king_attacks, knight_attacks, queen_attacks},
+    };
+
+    fn squares(bb: WideBitBoard) -> u64 {
+        bb.0 as u64
+    }
+
+    #[test]
+    fn standard_definitions_match_the_8x8_tables() {
+        let g = BoardGeometry::STANDARD;
+        let knight = CompiledPiece::new(&PieceDef::standard(PieceType::Knight), g);
+        let king = CompiledPiece::new(&PieceDef::standard(PieceType::King), g);
+        let queen = CompiledPiece::new(&PieceDef::standard(PieceType::Queen), g);
+        let occ = 0x0042_1000_8100_2400u64;
+        for sq in 0..64 {
+            let reach = |piece: &CompiledPiece| {
+                piece.reach(PieceColor::White, sq, WideBitBoard(occ as u128))
+            };
+            let bb = BitBoard::from_index(sq);
+            assert_eq!(squares(reach(&knight).moves), knight_attacks(bb).0);
+            assert_eq!(squares(reach(&king).captures), king_attacks(bb).0);
+            assert_eq!(
+                squares(reach(&queen).moves),
+                queen_attacks(bb, BitBoard(occ)).0
+            );
+        }
+    }
+
+    #[test]
+    fn estimates_put_a_pawn_near_100() {
+        let estimate = |pt| {
+            let standard = PieceDef::standard(pt);
+            PieceDef::new(&standard.name, standard.letter, standard.components).value
+        };
+        let pawn = estimate(PieceType::Pawn);
+        assert!((90..=110).contains(&pawn), "{pawn}");
+        // A knight is worth about three pawns, a queen about nine
+        let knight = estimate(PieceType::Knight);
+        assert!((270..=330).contains(&knight), "{knight}");
+        let queen = estimate(PieceType::Queen);
+        assert!((850..=950).contains(&queen), "{queen}");
+    }
+
+    #[test]
+    fn pawns_move_and_capture_differently_for_each_side() {
+        let g = BoardGeometry::STANDARD;
+        let pawn = CompiledPiece::new(&PieceDef::standard(PieceType::Pawn), g);
+        let e4 = g.square(4, 3);
+        let white = pawn.reach(PieceColor::White, e4, WideBitBoard::EMPTY);
+        assert_eq!(white.moves, WideBitBoard::from_index(g.square(4, 4)));
+        assert_eq!(white.captures.count(), 2);
+        assert!(white.captures.contains(g.square(3, 4)));
+        let black = pawn.reach(PieceColor::Black, e4, WideBitBoard::EMPTY);
+        assert!(black.moves.contains(g.square(4, 2)) && black.captures.contains(g.square(5, 2)));
+    }
+
+    #[test]
+    fn cannons_need_a_screen_to_capture() {
+        let g = BoardGeometry::STANDARD;
+        let cannon = CompiledPiece::new(&PieceDef::cannon(), g);
+        // Cannon on a1, screen on a4, targets on a6 and a8
+        let occ = [g.square(0, 3), g.square(0, 5), g.square(0, 7)]
+            .into_iter()
+            .fold(WideBitBoard::EMPTY, |bb, sq| {
+                bb | WideBitBoard::from_index(sq)
+            });
+        let reach = cannon.reach(PieceColor::White, 0, occ);
+        assert!(reach.moves.contains(g.square(0, 2)) && !reach.moves.contains(g.square(0, 4)));
+        assert!(reach.captures.contains(g.square(0, 4)) && reach.captures.contains(g.square(0, 5)));
+        assert!(
+            !reach.captures.contains(g.square(0, 3)) && !reach.captures.contains(g.square(0, 7))
+        );
+    }
+
+    #[test]
+    fn grasshoppers_land_right_behind_their_hurdle() {
+        let g = BoardGeometry::STANDARD;
+        let grasshopper = CompiledPiece::new(&PieceDef::grasshopper(), g);
+        let occ =
+            WideBitBoard::from_index(g.square(3, 3)) | WideBitBoard::from_index(g.square(0, 6));
+        let reach = grasshopper.reach(PieceColor::White, 0, occ);
+        assert_eq!(
+            reach.moves,
+            WideBitBoard::from_index(g.square(4, 4)) | WideBitBoard::from_index(g.square(0, 7))
+        );
+    }
+
+    #[test]
+    fn riders_repeat_knight_jumps_until_blocked() {
+        let g = BoardGeometry::GRAND;
+        let unicorn = CompiledPiece::new(&PieceDef::unicorn(), g);
+        let reach = unicorn.reach(PieceColor::White, 0, WideBitBoard::EMPTY);
+        // b3, c5, d7, e9 one way, c2, e3, g4, i5 the other, plus nine diagonal squares
+        assert_eq!(reach.moves.count(), 8 + 9);
+        let blocked = unicorn.reach(
+            PieceColor::White,
+            0,
+            WideBitBoard::from_index(g.square(2, 4)),
+        );
+        assert!(
+            blocked.captures.contains(g.square(2, 4)) && !blocked.moves.contains(g.square(3, 6))
+        );
+    }
+
+    #[test]
+    fn rosters_keep_letters_unique() {
+        let mut roster = Roster::capablanca();
+        assert_eq!(roster.id_of('a'), Some(6));
+        assert_eq!(roster.id_of('K'), Some(PieceType::King as PieceId));
+        assert_eq!(roster.add(PieceDef::chancellor()), None);
+        assert_eq!(roster.add(PieceDef::cannon()), Some(8));
+        assert_eq!(roster.promotions(), [1, 2, 3, 4, 6, 7, 8]);
+        assert_eq!(leaps(1, 2).len(), 8);
+        assert_eq!(leaps(2, 2).len(), 4);
+    }
+}
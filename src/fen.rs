@@ -0,0 +1,560 @@
+use std::{fmt, str::FromStr};
+
+use bevy::platform::collections::HashMap;
+
+use crate::{
+    board::BoardCoordinates,
+    game::{CastlingRights, CastlingSides, GameState},
+    geometry::BoardGeometry,
+    rendering::{PieceColor, PieceType},
+};
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Reason a FEN string was rejected, naming the offending field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TrailingFields,
+    PiecePlacement(String),
+    SideToMove(String),
+    CastlingRights(String),
+    EnPassant(String),
+    HalfmoveClock(String),
+    FullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing FEN field: {field}"),
+            FenError::TrailingFields => write!(f, "unexpected fields after the fullmove number"),
+            FenError::PiecePlacement(msg) => write!(f, "invalid piece placement: {msg}"),
+            FenError::SideToMove(s) => write!(f, "invalid side to move: '{s}'"),
+            FenError::CastlingRights(s) => write!(f, "invalid castling rights: '{s}'"),
+            FenError::EnPassant(s) => write!(f, "invalid en passant square: '{s}'"),
+            FenError::HalfmoveClock(s) => write!(f, "invalid halfmove clock: '{s}'"),
+            FenError::FullmoveNumber(s) => write!(f, "invalid fullmove number: '{s}'"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl GameState {
+    /// Parses a position from Forsyth-Edwards Notation.
+    ///
+    /// The halfmove clock and fullmove number may be omitted (as in EPD), in
+    /// which case they default to `0` and `1`.
+    pub fn from_fen(fen: &str) -> Result<GameState, FenError> {
+        let record = FenRecord::parse(fen, BoardGeometry::STANDARD)?;
+
+        let mut state = GameState::empty();
+        for (sq, piece) in record.pieces(PieceType::from_char)?.into_iter().enumerate() {
+            if let Some((pt, pc)) = piece {
+                state.put_piece(pt, pc, sq as u8);
+            }
+        }
+        for color in PieceColor::ALL {
+            check_material(&state, color)?;
+        }
+        state.side_to_move = record.side_to_move;
+        state.castling_rights = record.castling_rights;
+        state.en_passant = record.en_passant;
+        state.halfmove_clock = record.halfmove_clock;
+        state.fullmove_number = record.fullmove_number;
+        state.hash = state.compute_hash();
+        Ok(state)
+    }
+
+    /// Serializes the position to Forsyth-Edwards Notation.
+    pub fn to_fen(&self) -> String {
+        FenRecord {
+            geometry: BoardGeometry::STANDARD,
+            board: (0..64)
+                .map(|sq| self.piece_at(sq).map(|(pt, pc)| (pt.to_char(), pc)))
+                .collect(),
+            side_to_move: self.side_to_move,
+            castling_rights: self.castling_rights.clone(),
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        }
+        .write()
+    }
+}
+
+impl FromStr for GameState {
+    type Err = FenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GameState::from_fen(s)
+    }
+}
+
+/// The fields of a FEN record for a board of any geometry, through which
+/// both `GameState` and `WideGameState` read and write FEN. Boards wider or
+/// taller than 8 use multi-digit empty-square counts and square names (`j10`).
+///
+/// Pieces are kept as their uppercase letters, which each side maps to its
+/// own pieces: `K` and `R` are taken to be the king and its castling rook.
+pub(crate) struct FenRecord {
+    pub geometry: BoardGeometry,
+    /// What stands on each square, `geometry.squares()` long.
+    pub board: Vec<Option<(char, PieceColor)>>,
+    pub side_to_move: PieceColor,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<u8>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl FenRecord {
+    pub fn parse(fen: &str, geometry: BoardGeometry) -> Result<FenRecord, FenError> {
+        let mut fields = fen.split_whitespace();
+
+        let placement = fields
+            .next()
+            .ok_or(FenError::MissingField("piece placement"))?;
+        let side = fields
+            .next()
+            .ok_or(FenError::MissingField("side to move"))?;
+        let castling = fields
+            .next()
+            .ok_or(FenError::MissingField("castling rights"))?;
+        let en_passant = fields.next().ok_or(FenError::MissingField("en passant"))?;
+        let halfmove = fields.next();
+        let fullmove = fields.next();
+        if fields.next().is_some() {
+            return Err(FenError::TrailingFields);
+        }
+
+        let mut record = FenRecord {
+            geometry,
+            board: parse_placement(placement, geometry)?,
+            side_to_move: match side {
+                "w" => PieceColor::White,
+                "b" => PieceColor::Black,
+                _ => return Err(FenError::SideToMove(side.to_string())),
+            },
+            castling_rights: CastlingRights(HashMap::new()),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        };
+        record.castling_rights = record.parse_castling(castling)?;
+
+        record.en_passant = match en_passant {
+            "-" => None,
+            s => {
+                // The target square sits behind the pawn that just double-pushed
+                let expected_row = if record.side_to_move == PieceColor::White {
+                    geometry.height - 3
+                } else {
+                    2
+                };
+                match BoardCoordinates::from_algebraic_in(s, geometry) {
+                    Some(coords) if coords.row == expected_row => Some(coords.to_square(geometry)),
+                    _ => return Err(FenError::EnPassant(s.to_string())),
+                }
+            }
+        };
+
+        if let Some(s) = halfmove {
+            record.halfmove_clock = s
+                .parse()
+                .map_err(|_| FenError::HalfmoveClock(s.to_string()))?;
+        }
+
+        if let Some(s) = fullmove {
+            record.fullmove_number = match s.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(FenError::FullmoveNumber(s.to_string())),
+            };
+        }
+
+        Ok(record)
+    }
+
+    pub fn write(&self) -> String {
+        let geometry = self.geometry;
+        let mut fen = String::new();
+
+        for row in (0..geometry.height).rev() {
+            let mut empty = 0;
+            for col in 0..geometry.width {
+                match self.board[geometry.square(col, row) as usize] {
+                    Some((letter, pc)) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(match pc {
+                            PieceColor::White => letter,
+                            PieceColor::Black => letter.to_ascii_lowercase(),
+                        });
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.side_to_move {
+            PieceColor::White => " w ",
+            PieceColor::Black => " b ",
+        });
+
+        let mut castling = String::new();
+        for color in PieceColor::ALL {
+            let rights = self.castling_rights.sides(color);
+            for (file, kingside) in [(rights.kingside, true), (rights.queenside, false)] {
+                if let Some(file) = file {
+                    castling.push(self.castling_char(color, file, kingside));
+                }
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        match self.en_passant {
+            Some(sq) => fen.push_str(&format!(
+                " {} ",
+                BoardCoordinates::from_square(sq, geometry)
+            )),
+            None => fen.push_str(" - "),
+        }
+
+        fen.push_str(&format!("{} {}", self.halfmove_clock, self.fullmove_number));
+        fen
+    }
+
+    /// The board with each letter looked up by `piece`, failing on the first
+    /// letter it does not know.
+    pub fn pieces<P>(
+        &self,
+        piece: impl Fn(char) -> Option<P>,
+    ) -> Result<Vec<Option<(P, PieceColor)>>, FenError> {
+        self.board
+            .iter()
+            .map(|square| {
+                square
+                    .map(|(letter, pc)| match piece(letter) {
+                        Some(p) => Ok((p, pc)),
+                        None => Err(FenError::PiecePlacement(format!(
+                            "unknown piece '{}'",
+                            match pc {
+                                PieceColor::White => letter,
+                                PieceColor::Black => letter.to_ascii_lowercase(),
+                            }
+                        ))),
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Files of the back rank of `color` holding a piece of that color
+    /// lettered `letter`.
+    fn back_rank_files(&self, color: PieceColor, letter: char) -> impl Iterator<Item = u8> + '_ {
+        let row = match color {
+            PieceColor::White => 0,
+            PieceColor::Black => self.geometry.height - 1,
+        };
+        (0..self.geometry.width).filter(move |&col| {
+            self.board[self.geometry.square(col, row) as usize] == Some((letter, color))
+        })
+    }
+
+    /// X-FEN castling letter: `K`/`Q` when the right is held with the outermost
+    /// rook on that side of the king, as in standard chess, or the rook's file
+    /// when another rook stands further out. `parse_castling` reads a `K` as
+    /// the outermost rook, so an inner rook on the k-file cannot be written.
+    fn castling_char(&self, color: PieceColor, file: u8, kingside: bool) -> char {
+        let outermost = self
+            .back_rank_files(color, 'R')
+            .all(|f| if kingside { f <= file } else { f >= file });
+        let c = match (outermost, kingside) {
+            (true, true) => 'K',
+            (true, false) => 'Q',
+            (false, _) => char::from(b'A' + file),
+        };
+        match color {
+            PieceColor::White => c,
+            PieceColor::Black => c.to_ascii_lowercase(),
+        }
+    }
+
+    /// Reads standard, X-FEN and Shredder-FEN castling fields. `K`/`Q` name
+    /// the outermost rook on that side of the king (the corner in standard
+    /// chess), a file letter names the rook directly, which side it castles
+    /// to following from where it stands relative to the king. A right
+    /// without a king and a rook to castle with is an error.
+    ///
+    /// `K` and `Q` are never read as files, so on boards of 11 or more files
+    /// a rook on the k-file can only hold a right as the outermost rook.
+    fn parse_castling(&self, castling: &str) -> Result<CastlingRights, FenError> {
+        let mut rights = CastlingRights(HashMap::from([
+            (PieceColor::White, CastlingSides::default()),
+            (PieceColor::Black, CastlingSides::default()),
+        ]));
+        if castling == "-" {
+            return Ok(rights);
+        }
+
+        let error = || FenError::CastlingRights(castling.to_string());
+        let last_file = self.geometry.width - 1;
+        for c in castling.chars() {
+            let color = if c.is_ascii_uppercase() {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            };
+            let mut rook_files = self.back_rank_files(color, 'R');
+            let king_file = self.back_rank_files(color, 'K').next();
+
+            let (file, kingside) = match (c.to_ascii_uppercase(), king_file) {
+                ('K', Some(king)) => (
+                    rook_files.filter(|&f| f > king).last().ok_or_else(error)?,
+                    true,
+                ),
+                ('Q', Some(king)) => (rook_files.find(|&f| f < king).ok_or_else(error)?, false),
+                (file @ 'A'..='Z', Some(king)) if (file as u8 - b'A') <= last_file => {
+                    let file = file as u8 - b'A';
+                    if file == king {
+                        return Err(error());
+                    }
+                    (file, file > king)
+                }
+                _ => return Err(error()),
+            };
+
+            let sides = rights.0.get_mut(&color).unwrap();
+            let side = if kingside {
+                &mut sides.kingside
+            } else {
+                &mut sides.queenside
+            };
+            if side.is_some() {
+                return Err(error());
+            }
+            *side = Some(file);
+        }
+
+        Ok(rights)
+    }
+}
+
+/// Rejects armies no game can reach, which is also what keeps every
+/// position within the `MAX_MOVES` a `MoveList` holds: at most 16 pieces, 8
+/// pawns, and no more extra pieces than pawns have gone missing to promote.
+fn check_material(state: &GameState, color: PieceColor) -> Result<(), FenError> {
+    let count = |pt: PieceType| state.piece_bb(pt, color).0.count_ones() as usize;
+    let pawns = count(PieceType::Pawn);
+    let promoted = count(PieceType::Queen).saturating_sub(1)
+        + count(PieceType::Rook).saturating_sub(2)
+        + count(PieceType::Bishop).saturating_sub(2)
+        + count(PieceType::Knight).saturating_sub(2);
+    if state.pieces(color).0.count_ones() > 16 || pawns > 8 || pawns + promoted > 8 {
+        return Err(FenError::PiecePlacement(format!(
+            "too many pieces for {color:?}"
+        )));
+    }
+    Ok(())
+}
+
+fn parse_placement(
+    placement: &str,
+    geometry: BoardGeometry,
+) -> Result<Vec<Option<(char, PieceColor)>>, FenError> {
+    let (width, height) = (geometry.width as u32, geometry.height);
+    let mut board = vec![None; geometry.squares()];
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != height as usize {
+        return Err(FenError::PiecePlacement(format!(
+            "expected {height} ranks, found {}",
+            ranks.len()
+        )));
+    }
+
+    // FEN lists the top rank first
+    for (i, rank) in ranks.iter().enumerate() {
+        let row = height - 1 - i as u8;
+        let mut col = 0u32;
+        let mut chars = rank.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_ascii_digit() {
+                // Counts past 9 on wide boards take several digits
+                let mut count = c.to_string();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    count.push(digit);
+                }
+                let skip: u32 = count.parse().unwrap_or(0);
+                if skip == 0 || skip > width || count.starts_with('0') {
+                    return Err(FenError::PiecePlacement(format!(
+                        "bad empty-square count '{count}'"
+                    )));
+                }
+                col += skip;
+            } else {
+                if !c.is_ascii_alphabetic() {
+                    return Err(FenError::PiecePlacement(format!("unknown piece '{c}'")));
+                }
+                let pc = if c.is_ascii_uppercase() {
+                    PieceColor::White
+                } else {
+                    PieceColor::Black
+                };
+                if col >= width {
+                    return Err(FenError::PiecePlacement(format!(
+                        "rank {} has more than {width} squares",
+                        row + 1
+                    )));
+                }
+                board[geometry.square(col as u8, row) as usize] =
+                    Some((c.to_ascii_uppercase(), pc));
+                col += 1;
+            }
+        }
+        if col != width {
+            return Err(FenError::PiecePlacement(format!(
+                "rank {} describes {} squares",
+                row + 1,
+                col
+            )));
+        }
+    }
+
+    Ok(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn default_state_is_start_fen() {
+        assert_eq!(GameState::default().to_fen(), START_FEN);
+    }
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            START_FEN,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 17 42",
+        ] {
+            assert_eq!(GameState::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_fields() {
+        // Shredder-FEN names every rook by file; X-FEN only the ones K/Q can't
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let state = GameState::from_fen(shredder).unwrap();
+        assert_eq!(
+            state.castling_rights.kingside_file(PieceColor::White),
+            Some(7)
+        );
+        assert_eq!(
+            state.castling_rights.queenside_file(PieceColor::Black),
+            Some(5)
+        );
+        assert_eq!(
+            state.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+
+        let inner_rook = "4k3/8/8/8/8/8/8/R1R1K2R w KC - 0 1";
+        let state = GameState::from_fen(inner_rook).unwrap();
+        assert_eq!(
+            state.castling_rights.queenside_file(PieceColor::White),
+            Some(2)
+        );
+        assert_eq!(state.to_fen(), inner_rook);
+
+        // A file letter on the king's own file names no rook
+        assert!(matches!(
+            GameState::from_fen("4k3/8/8/8/8/8/8/R3K2R w E - 0 1"),
+            Err(FenError::CastlingRights(_))
+        ));
+        // No rook on the side the right names
+        assert!(matches!(
+            GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 w K - 0 1"),
+            Err(FenError::CastlingRights(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("4k3/8/8/8/8/8/8/4K2R w Q - 0 1"),
+            Err(FenError::CastlingRights(_))
+        ));
+    }
+
+    #[test]
+    fn clocks_are_optional() {
+        let state = GameState::from_fen("8/8/8/8/8/8/8/K6k b - -").unwrap();
+        assert_eq!(state.halfmove_clock, 0);
+        assert_eq!(state.fullmove_number, 1);
+        assert_eq!(state.side_to_move, PieceColor::Black);
+    }
+
+    #[test]
+    fn errors_name_the_field() {
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k"),
+            Err(FenError::MissingField("side to move"))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/K6k w - - 0 1"),
+            Err(FenError::PiecePlacement(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K5xk w - - 0 1"),
+            Err(FenError::PiecePlacement(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K7k w - - 0 1"),
+            Err(FenError::PiecePlacement(_))
+        ));
+        // Ten white queens: more than promotions can make
+        assert!(matches!(
+            GameState::from_fen("QQQQQQQQ/QQ6/8/8/8/8/8/K6k w - - 0 1"),
+            Err(FenError::PiecePlacement(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k x - - 0 1"),
+            Err(FenError::SideToMove(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k w KK - 0 1"),
+            Err(FenError::CastlingRights(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k w - e3 0 1"),
+            Err(FenError::EnPassant(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k w - - x 1"),
+            Err(FenError::HalfmoveClock(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k w - - 0 0"),
+            Err(FenError::FullmoveNumber(_))
+        ));
+        assert!(matches!(
+            GameState::from_fen("8/8/8/8/8/8/8/K6k w - - 0 1 extra"),
+            Err(FenError::TrailingFields)
+        ));
+    }
+}
@@ -0,0 +1,799 @@
+use bevy::prelude::{Component, Resource, Vec2};
+
+use crate::{
+    board::BoardCoordinates,
+    game::{CastlingSides, Move, MoveFlag, Promotions},
+    geometry::BoardGeometry,
+    movegen::{BISHOP_DIRECTIONS, KING_MOVES, KNIGHT_MOVES, ROOK_DIRECTIONS},
+    rendering::PieceType,
+};
+
+/// Side of the four-player board, a 14x14 square with its 3x3 corners cut
+/// away, leaving 160 squares.
+pub const BOARD_SIZE: u8 = 14;
+const CORNER: u8 = 3;
+/// Coordinates of the bounding 14x14 square. Too big for a `WideBitBoard`,
+/// so positions are kept in a mailbox and only its square arithmetic is used.
+/// With more than `geometry::MAX_SQUARES` squares it breaks what `BoardGeometry::new`
+/// guarantees, so it stays private to this module.
+const GEOMETRY: BoardGeometry = BoardGeometry {
+    width: BOARD_SIZE,
+    height: BOARD_SIZE,
+};
+const SQUARES: usize = BOARD_SIZE as usize * BOARD_SIZE as usize;
+
+/// Rank, counted from a player's own side, on which their pawns promote:
+/// the middle of the board.
+pub const PROMOTION_RANK: u8 = 7;
+/// Awarded for checkmating a player, and to a player who is stalemated.
+pub const MATE_POINTS: u32 = 20;
+
+/// The four sides, clockwise from the bottom of the board.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Player {
+    Red,
+    Blue,
+    Yellow,
+    Green,
+}
+
+impl Player {
+    /// In turn order.
+    pub const ALL: [Player; 4] = [Player::Red, Player::Blue, Player::Yellow, Player::Green];
+
+    /// Who moves after this player, eliminated or not.
+    pub fn next(self) -> Player {
+        Player::ALL[(self as usize + 1) % 4]
+    }
+
+    pub fn previous(self) -> Player {
+        Player::ALL[(self as usize + 3) % 4]
+    }
+
+    /// Turns a (file, rank) step given from Red's side of the board into the
+    /// same step from this player's side, where "forward" is +rank.
+    fn orient(self, (df, dr): (i32, i32)) -> (i32, i32) {
+        match self {
+            Player::Red => (df, dr),
+            Player::Blue => (dr, -df),
+            Player::Yellow => (-df, -dr),
+            Player::Green => (-dr, df),
+        }
+    }
+
+    /// The square `file` files across and `rank` ranks up from this player's
+    /// side of the board, as Red would count them from the bottom.
+    pub fn square(self, file: u8, rank: u8) -> u8 {
+        let last = BOARD_SIZE - 1;
+        let (col, row) = match self {
+            Player::Red => (file, rank),
+            Player::Blue => (rank, last - file),
+            Player::Yellow => (last - file, last - rank),
+            Player::Green => (last - rank, file),
+        };
+        GEOMETRY.square(col, row)
+    }
+
+    /// How many files `sq` is across from this player's left.
+    pub fn file_of(self, sq: u8) -> u8 {
+        let last = BOARD_SIZE - 1;
+        let (col, row) = (GEOMETRY.col(sq), GEOMETRY.row(sq));
+        match self {
+            Player::Red => col,
+            Player::Blue => last - row,
+            Player::Yellow => last - col,
+            Player::Green => row,
+        }
+    }
+
+    /// How many ranks `sq` is up the board from this player's side.
+    pub fn rank_of(self, sq: u8) -> u8 {
+        let last = BOARD_SIZE - 1;
+        let (col, row) = (GEOMETRY.col(sq), GEOMETRY.row(sq));
+        match self {
+            Player::Red => row,
+            Player::Blue => col,
+            Player::Yellow => last - row,
+            Player::Green => last - col,
+        }
+    }
+}
+
+/// Whether `sq` of the bounding 14x14 square is part of the cross.
+pub fn is_on_board(sq: u8) -> bool {
+    let in_band = |x: u8| (CORNER..BOARD_SIZE - CORNER).contains(&x);
+    (sq as usize) < SQUARES && (in_band(GEOMETRY.col(sq)) || in_band(GEOMETRY.row(sq)))
+}
+
+/// Every square of the cross.
+pub fn squares() -> impl Iterator<Item = u8> {
+    (0..SQUARES as u8).filter(|&sq| is_on_board(sq))
+}
+
+/// Coordinates of a square of the cross.
+pub fn coordinates(sq: u8) -> BoardCoordinates {
+    BoardCoordinates::from_square(sq, GEOMETRY)
+}
+
+/// Where the centre of a square of the cross is drawn, with the board
+/// centred on the origin.
+pub fn translation(coordinates: &BoardCoordinates) -> Vec2 {
+    coordinates.translation(GEOMETRY)
+}
+
+/// The square a (file, rank) step away from `sq`, `None` off the cross.
+fn offset(sq: u8, (dc, dr): (i32, i32)) -> Option<u8> {
+    GEOMETRY.offset(sq, dc, dr).filter(|&to| is_on_board(to))
+}
+
+/// Points for capturing a piece of a player still in the game.
+pub fn capture_points(pt: PieceType) -> u32 {
+    match pt {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 3,
+        PieceType::Bishop | PieceType::Rook => 5,
+        PieceType::Queen => 9,
+        PieceType::King => MATE_POINTS,
+    }
+}
+
+/// A free-for-all game of four-player chess.
+///
+/// Players move in turn, Red, Blue, Yellow, Green, each with pawns running
+/// away from their own side. A player left without a legal move on their
+/// turn is out: checkmated, with the points going to whoever gives the
+/// check (the most recent mover first), or stalemated, which earns the
+/// stalemated player the points instead. A king left in check can also be
+/// captured by a player moving in between, which knocks its owner out as
+/// well. The pieces of eliminated players stay on the board as dead
+/// obstacles: they neither move nor give check, and are worth nothing.
+///
+/// Pawns promote on the middle rank and there is no en passant.
+#[derive(Resource, Clone)]
+pub struct FourPlayerState {
+    board: [Option<(PieceType, Player)>; SQUARES],
+    pub side_to_move: Player,
+    /// Files of the castling rooks, counted from each player's side and
+    /// indexed by `Player as usize`.
+    castling: [CastlingSides; 4],
+    eliminated: [bool; 4],
+    points: [u32; 4],
+    /// What pawns promote to, all four pieces unless set by hand.
+    pub promotions: Promotions,
+}
+
+impl FourPlayerState {
+    /// Files of a player's back rank, from their left.
+    const BACK_RANK: [PieceType; 8] = [
+        PieceType::Rook,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Queen,
+        PieceType::King,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Rook,
+    ];
+
+    /// The starting position: each player's pieces on the files `d` to `k`
+    /// of their side, Red to move.
+    pub fn new() -> FourPlayerState {
+        let mut state = FourPlayerState::empty();
+        for player in Player::ALL {
+            for (i, pt) in FourPlayerState::BACK_RANK.into_iter().enumerate() {
+                let file = CORNER + i as u8;
+                state.put_piece(pt, player, player.square(file, 0));
+                state.put_piece(PieceType::Pawn, player, player.square(file, 1));
+            }
+            state.castling[player as usize] = CastlingSides {
+                kingside: Some(CORNER + 7),
+                queenside: Some(CORNER),
+            };
+        }
+        state
+    }
+
+    /// A board without pieces, Red to move and nobody allowed to castle.
+    pub fn empty() -> FourPlayerState {
+        FourPlayerState {
+            board: [None; SQUARES],
+            side_to_move: Player::Red,
+            castling: [CastlingSides::default(); 4],
+            eliminated: [false; 4],
+            points: [0; 4],
+            promotions: Promotions::default(),
+        }
+    }
+
+    #[inline]
+    pub fn piece_at(&self, sq: u8) -> Option<(PieceType, Player)> {
+        self.board[sq as usize]
+    }
+
+    /// Every piece on the board, dead ones included.
+    pub fn pieces(&self) -> impl Iterator<Item = (u8, PieceType, Player)> + '_ {
+        squares().filter_map(|sq| self.piece_at(sq).map(|(pt, player)| (sq, pt, player)))
+    }
+
+    /// Puts a piece on an empty square of the cross, for setting up positions.
+    pub fn put_piece(&mut self, pt: PieceType, player: Player, sq: u8) {
+        assert!(is_on_board(sq), "put_piece: square {sq} is off the board");
+        assert!(
+            self.piece_at(sq).is_none(),
+            "put_piece: square {sq} is occupied"
+        );
+        self.board[sq as usize] = Some((pt, player));
+    }
+
+    pub fn is_active(&self, player: Player) -> bool {
+        !self.eliminated[player as usize]
+    }
+
+    pub fn active_players(&self) -> impl Iterator<Item = Player> + '_ {
+        Player::ALL.into_iter().filter(|&p| self.is_active(p))
+    }
+
+    pub fn points(&self, player: Player) -> u32 {
+        self.points[player as usize]
+    }
+
+    /// The game ends once a single player is left standing.
+    pub fn is_over(&self) -> bool {
+        self.active_players().count() <= 1
+    }
+
+    /// Players by points, best first. Ties keep turn order.
+    pub fn standings(&self) -> Vec<(Player, u32)> {
+        let mut standings: Vec<(Player, u32)> = Player::ALL
+            .into_iter()
+            .map(|p| (p, self.points(p)))
+            .collect();
+        standings.sort_by_key(|&(_, points)| std::cmp::Reverse(points));
+        standings
+    }
+
+    pub fn king_square(&self, player: Player) -> Option<u8> {
+        self.pieces()
+            .find(|&(_, pt, p)| pt == PieceType::King && p == player)
+            .map(|(sq, ..)| sq)
+    }
+
+    /// Returns true if `sq` is attacked by a piece of `by`.
+    pub fn is_attacked(&self, sq: u8, by: Player) -> bool {
+        let holds = |target: Option<u8>, kinds: &[PieceType]| {
+            target
+                .and_then(|t| self.piece_at(t))
+                .is_some_and(|(pt, p)| p == by && kinds.contains(&pt))
+        };
+        let slider_hits = |directions: &[(i32, i32)], kinds: &[PieceType]| {
+            directions
+                .iter()
+                .any(|&d| holds(self.first_piece_along(sq, d), kinds))
+        };
+
+        KNIGHT_MOVES.iter().any(|&d| holds(offset(sq, d), &[PieceType::Knight]))
+            || KING_MOVES.iter().any(|&d| holds(offset(sq, d), &[PieceType::King]))
+            // A pawn of `by` attacking `sq` stands one diagonal step behind it
+            || [(-1, 1), (1, 1)].into_iter().any(|d| {
+                let (df, dr) = by.orient(d);
+                holds(offset(sq, (-df, -dr)), &[PieceType::Pawn])
+            })
+            || slider_hits(&ROOK_DIRECTIONS, &[PieceType::Rook, PieceType::Queen])
+            || slider_hits(&BISHOP_DIRECTIONS, &[PieceType::Bishop, PieceType::Queen])
+    }
+
+    /// Whether any other player still in the game attacks `sq`.
+    fn is_threatened(&self, sq: u8, player: Player) -> bool {
+        self.active_players()
+            .any(|other| other != player && self.is_attacked(sq, other))
+    }
+
+    /// Returns true if the king of `player` is attacked by a player still in the game.
+    pub fn is_in_check(&self, player: Player) -> bool {
+        self.king_square(player)
+            .is_some_and(|king| self.is_threatened(king, player))
+    }
+
+    fn first_piece_along(&self, sq: u8, direction: (i32, i32)) -> Option<u8> {
+        let mut current = sq;
+        while let Some(next) = offset(current, direction) {
+            if self.piece_at(next).is_some() {
+                return Some(next);
+            }
+            current = next;
+        }
+        None
+    }
+
+    /// Generates all legal moves for the side to move.
+    pub fn generate_legal_moves(&self) -> Vec<Move> {
+        let player = self.side_to_move;
+        let mut moves = self.generate_pseudo_legal_moves();
+        moves.retain(|&mv| {
+            let mut after = self.clone();
+            after.play(mv);
+            !after.is_in_check(player)
+        });
+        moves
+    }
+
+    /// Generates all pseudo-legal moves for the side to move.
+    pub fn generate_pseudo_legal_moves(&self) -> Vec<Move> {
+        let player = self.side_to_move;
+        let mut moves = Vec::new();
+        if !self.is_active(player) {
+            return moves;
+        }
+
+        let add = |moves: &mut Vec<Move>, from: u8, to: u8| {
+            let flag = if self.piece_at(to).is_some() {
+                MoveFlag::Capture
+            } else {
+                MoveFlag::Quiet
+            };
+            moves.push(Move { from, to, flag });
+        };
+        let is_target = |to: u8| self.piece_at(to).is_none_or(|(_, p)| p != player);
+
+        for (from, pt, _) in self.pieces().filter(|&(_, _, p)| p == player) {
+            match pt {
+                PieceType::Pawn => self.gen_pawn_moves(from, &mut moves),
+                PieceType::Knight | PieceType::King => {
+                    let steps = if pt == PieceType::Knight {
+                        &KNIGHT_MOVES
+                    } else {
+                        &KING_MOVES
+                    };
+                    for to in steps.iter().filter_map(|&d| offset(from, d)) {
+                        if is_target(to) {
+                            add(&mut moves, from, to);
+                        }
+                    }
+                    if pt == PieceType::King {
+                        self.gen_castling_moves(from, &mut moves);
+                    }
+                }
+                _ => {
+                    let directions: &[(i32, i32)] = match pt {
+                        PieceType::Bishop => &BISHOP_DIRECTIONS,
+                        PieceType::Rook => &ROOK_DIRECTIONS,
+                        _ => &[ROOK_DIRECTIONS, BISHOP_DIRECTIONS].concat(),
+                    };
+                    for &d in directions {
+                        let mut current = from;
+                        while let Some(to) = offset(current, d) {
+                            if is_target(to) {
+                                add(&mut moves, from, to);
+                            }
+                            if self.piece_at(to).is_some() {
+                                break;
+                            }
+                            current = to;
+                        }
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    fn gen_pawn_moves(&self, from: u8, moves: &mut Vec<Move>) {
+        let player = self.side_to_move;
+        let push = |moves: &mut Vec<Move>, to: u8, capture: bool| {
+            if player.rank_of(to) == PROMOTION_RANK {
+                for pt in self.promotions.iter() {
+                    let flag = if capture {
+                        MoveFlag::PromotionCapture(pt)
+                    } else {
+                        MoveFlag::Promotion(pt)
+                    };
+                    moves.push(Move { from, to, flag });
+                }
+            } else {
+                let flag = if capture {
+                    MoveFlag::Capture
+                } else {
+                    MoveFlag::Quiet
+                };
+                moves.push(Move { from, to, flag });
+            }
+        };
+
+        let forward = player.orient((0, 1));
+        if let Some(one) = offset(from, forward).filter(|&sq| self.piece_at(sq).is_none()) {
+            push(moves, one, false);
+            if player.rank_of(from) == 1
+                && let Some(two) = offset(one, forward).filter(|&sq| self.piece_at(sq).is_none())
+            {
+                moves.push(Move {
+                    from,
+                    to: two,
+                    flag: MoveFlag::DoublePawnPush,
+                });
+            }
+        }
+        for side in [-1, 1] {
+            if let Some(to) = offset(from, player.orient((side, 1)))
+                && self.piece_at(to).is_some_and(|(_, p)| p != player)
+            {
+                push(moves, to, true);
+            }
+        }
+    }
+
+    /// Castling as in standard chess, seen from the player's side: the king
+    /// moves two squares towards the rook, which lands on the square the
+    /// king crossed.
+    fn gen_castling_moves(&self, king_sq: u8, moves: &mut Vec<Move>) {
+        let player = self.side_to_move;
+        if player.rank_of(king_sq) != 0 || self.is_threatened(king_sq, player) {
+            return;
+        }
+        let sides = self.castling[player as usize];
+        for (rook_file, flag) in [
+            (sides.kingside, MoveFlag::KingsideCastle),
+            (sides.queenside, MoveFlag::QueensideCastle),
+        ] {
+            let Some(rook_file) = rook_file else {
+                continue;
+            };
+            let rook_sq = player.square(rook_file, 0);
+            if self.piece_at(rook_sq) != Some((PieceType::Rook, player)) {
+                continue;
+            }
+            let step = if flag == MoveFlag::KingsideCastle {
+                (1, 0)
+            } else {
+                (-1, 0)
+            };
+            let step = player.orient(step);
+
+            // Everything between king and rook empty
+            let mut sq = king_sq;
+            let mut clear = true;
+            while let Some(next) = offset(sq, step).filter(|&next| next != rook_sq) {
+                clear &= self.piece_at(next).is_none();
+                sq = next;
+            }
+            let Some(passed) = offset(king_sq, step) else {
+                continue;
+            };
+            let Some(king_to) = offset(passed, step) else {
+                continue;
+            };
+            if clear && !self.is_threatened(passed, player) && !self.is_threatened(king_to, player)
+            {
+                moves.push(Move {
+                    from: king_sq,
+                    to: king_to,
+                    flag,
+                });
+            }
+        }
+    }
+
+    /// Plays `mv` for the side to move: scores any capture, then hands the
+    /// turn to the next player still in, knocking out whoever is left
+    /// without a move along the way.
+    pub fn make_move(&mut self, mv: Move) {
+        let mover = self.side_to_move;
+        if let Some((pt, owner)) = self.piece_at(mv.to)
+            && owner != mover
+            && self.is_active(owner)
+        {
+            self.points[mover as usize] += capture_points(pt);
+            if pt == PieceType::King {
+                self.eliminated[owner as usize] = true;
+            }
+        }
+        self.play(mv);
+        self.pass_turn();
+    }
+
+    /// Moves the pieces of `mv` and updates castling rights, nothing more.
+    fn play(&mut self, mv: Move) {
+        let player = self.side_to_move;
+        let (pt, _) = self.board[mv.from as usize]
+            .take()
+            .expect("play: no piece at from square");
+        let placed = match mv.flag {
+            MoveFlag::Promotion(promoted) | MoveFlag::PromotionCapture(promoted) => promoted,
+            _ => pt,
+        };
+        self.board[mv.to as usize] = Some((placed, player));
+
+        if let MoveFlag::KingsideCastle | MoveFlag::QueensideCastle = mv.flag {
+            let sides = self.castling[player as usize];
+            let rook_file = if mv.flag == MoveFlag::KingsideCastle {
+                sides.kingside
+            } else {
+                sides.queenside
+            };
+            let rook_sq = player.square(rook_file.expect("castling right"), 0);
+            let rook_to = ((mv.from as u16 + mv.to as u16) / 2) as u8;
+            self.board[rook_sq as usize] = None;
+            self.board[rook_to as usize] = Some((PieceType::Rook, player));
+        }
+
+        // Kings moving and rooks leaving (or being taken on) their corners
+        // forfeit castling
+        if pt == PieceType::King {
+            self.castling[player as usize] = CastlingSides::default();
+        }
+        for sq in [mv.from, mv.to] {
+            for p in Player::ALL {
+                if p.rank_of(sq) != 0 {
+                    continue;
+                }
+                let file = Some(p.file_of(sq));
+                let sides = &mut self.castling[p as usize];
+                if sides.kingside == file {
+                    sides.kingside = None;
+                }
+                if sides.queenside == file {
+                    sides.queenside = None;
+                }
+            }
+        }
+    }
+
+    /// Hands the turn on, eliminating players who have no move when it
+    /// reaches them.
+    fn pass_turn(&mut self) {
+        loop {
+            if self.is_over() {
+                return;
+            }
+            let mut next = self.side_to_move.next();
+            while !self.is_active(next) {
+                next = next.next();
+            }
+            self.side_to_move = next;
+            if !self.generate_legal_moves().is_empty() {
+                return;
+            }
+
+            if self.is_in_check(next) {
+                let king = self.king_square(next).expect("a checked king exists");
+                let mut checker = next.previous();
+                while checker != next {
+                    if self.is_active(checker) && self.is_attacked(king, checker) {
+                        self.points[checker as usize] += MATE_POINTS;
+                        break;
+                    }
+                    checker = checker.previous();
+                }
+            } else {
+                self.points[next as usize] += MATE_POINTS;
+            }
+            self.eliminated[next as usize] = true;
+        }
+    }
+}
+
+impl Default for FourPlayerState {
+    fn default() -> Self {
+        FourPlayerState::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sq(col: u8, row: u8) -> u8 {
+        GEOMETRY.square(col, row)
+    }
+
+    /// Kings only, on their starting squares.
+    fn kings() -> FourPlayerState {
+        let mut state = FourPlayerState::empty();
+        for player in Player::ALL {
+            state.put_piece(PieceType::King, player, player.square(7, 0));
+        }
+        state
+    }
+
+    #[test]
+    fn start_position_has_four_armies_on_the_cross() {
+        assert_eq!(squares().count(), 160);
+        assert!(!is_on_board(sq(2, 2)) && is_on_board(sq(3, 0)) && is_on_board(sq(0, 3)));
+
+        let state = FourPlayerState::new();
+        for player in Player::ALL {
+            assert_eq!(state.pieces().filter(|&(_, _, p)| p == player).count(), 16);
+        }
+        assert_eq!(
+            state.piece_at(sq(7, 0)),
+            Some((PieceType::King, Player::Red))
+        );
+        assert_eq!(
+            state.piece_at(sq(0, 6)),
+            Some((PieceType::King, Player::Blue))
+        );
+        assert_eq!(
+            state.piece_at(sq(6, 13)),
+            Some((PieceType::King, Player::Yellow))
+        );
+        assert_eq!(
+            state.piece_at(sq(13, 7)),
+            Some((PieceType::King, Player::Green))
+        );
+    }
+
+    #[test]
+    fn turns_rotate_with_pawns_pushing_away_from_their_side() {
+        let mut state = FourPlayerState::new();
+        let forward = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+        for (player, (dc, dr)) in Player::ALL.into_iter().zip(forward) {
+            assert_eq!(state.side_to_move, player);
+            let moves = state.generate_legal_moves();
+            assert_eq!(moves.len(), 20, "{player:?}");
+
+            let from = player.square(4, 1);
+            let to = GEOMETRY.offset(from, dc * 2, dr * 2).unwrap();
+            let push = Move {
+                from,
+                to,
+                flag: MoveFlag::DoublePawnPush,
+            };
+            assert!(moves.contains(&push), "{player:?}");
+            state.make_move(push);
+        }
+        assert_eq!(state.side_to_move, Player::Red);
+    }
+
+    #[test]
+    fn captures_score_the_value_of_the_piece() {
+        let mut state = kings();
+        state.put_piece(PieceType::Rook, Player::Red, sq(5, 5));
+        state.put_piece(PieceType::Queen, Player::Green, sq(9, 5));
+        state.make_move(Move {
+            from: sq(5, 5),
+            to: sq(9, 5),
+            flag: MoveFlag::Capture,
+        });
+        assert_eq!(state.points(Player::Red), 9);
+        assert_eq!(state.side_to_move, Player::Blue);
+    }
+
+    #[test]
+    fn checkmated_players_are_eliminated_and_skipped() {
+        let mut state = FourPlayerState::empty();
+        state.put_piece(PieceType::King, Player::Red, sq(7, 0));
+        state.put_piece(PieceType::King, Player::Blue, sq(0, 3));
+        state.put_piece(PieceType::King, Player::Yellow, sq(6, 13));
+        state.put_piece(PieceType::King, Player::Green, sq(13, 7));
+        state.put_piece(PieceType::Rook, Player::Red, sq(1, 10));
+        state.put_piece(PieceType::Rook, Player::Red, sq(5, 9));
+
+        state.make_move(Move {
+            from: sq(5, 9),
+            to: sq(0, 9),
+            flag: MoveFlag::Quiet,
+        });
+        assert!(!state.is_active(Player::Blue));
+        assert_eq!(state.points(Player::Red), MATE_POINTS);
+        assert_eq!(state.side_to_move, Player::Yellow);
+
+        // Blue's king is dead: it no longer attacks anything and is worth nothing
+        state.make_move(state.generate_legal_moves()[0]);
+        state.make_move(state.generate_legal_moves()[0]);
+        assert_eq!(state.side_to_move, Player::Red);
+        state.make_move(Move {
+            from: sq(0, 9),
+            to: sq(0, 3),
+            flag: MoveFlag::Capture,
+        });
+        assert_eq!(state.points(Player::Red), MATE_POINTS);
+        assert_eq!(state.side_to_move, Player::Yellow);
+    }
+
+    #[test]
+    fn stalemate_eliminates_the_player_but_pays_them() {
+        let mut state = FourPlayerState::empty();
+        state.put_piece(PieceType::King, Player::Red, sq(7, 0));
+        state.put_piece(PieceType::King, Player::Blue, sq(0, 3));
+        state.put_piece(PieceType::King, Player::Yellow, sq(6, 13));
+        state.put_piece(PieceType::King, Player::Green, sq(13, 7));
+        state.put_piece(PieceType::Rook, Player::Red, sq(1, 10));
+        state.put_piece(PieceType::Rook, Player::Red, sq(5, 4));
+
+        state.make_move(Move {
+            from: sq(7, 0),
+            to: sq(7, 1),
+            flag: MoveFlag::Quiet,
+        });
+        assert!(!state.is_active(Player::Blue));
+        assert_eq!(state.points(Player::Blue), MATE_POINTS);
+        assert_eq!(state.points(Player::Red), 0);
+        assert_eq!(state.standings()[0], (Player::Blue, MATE_POINTS));
+    }
+
+    #[test]
+    fn capturing_a_king_left_in_check_knocks_its_owner_out() {
+        let mut state = kings();
+        state.put_piece(PieceType::Rook, Player::Red, sq(6, 5));
+        state.put_piece(PieceType::Queen, Player::Blue, sq(3, 12));
+        // Red checks Yellow's king, Blue takes it before Yellow can react
+        state.make_move(Move {
+            from: sq(6, 5),
+            to: sq(6, 10),
+            flag: MoveFlag::Quiet,
+        });
+        assert!(state.is_in_check(Player::Yellow));
+        state.make_move(Move {
+            from: sq(3, 12),
+            to: sq(6, 13),
+            flag: MoveFlag::Capture,
+        });
+        assert!(!state.is_active(Player::Yellow));
+        assert_eq!(state.points(Player::Blue), MATE_POINTS);
+        assert_eq!(state.side_to_move, Player::Green);
+    }
+
+    #[test]
+    fn game_ends_with_one_player_left() {
+        let mut state = FourPlayerState::empty();
+        state.put_piece(PieceType::King, Player::Red, sq(7, 0));
+        state.put_piece(PieceType::King, Player::Blue, sq(0, 3));
+        state.put_piece(PieceType::Rook, Player::Red, sq(1, 10));
+        state.put_piece(PieceType::Rook, Player::Red, sq(5, 9));
+        // Yellow and Green have no pieces, so they are out when their turn comes
+        state.make_move(Move {
+            from: sq(5, 9),
+            to: sq(0, 9),
+            flag: MoveFlag::Quiet,
+        });
+        assert!(state.is_over());
+        assert_eq!(state.active_players().collect::<Vec<_>>(), [Player::Red]);
+    }
+
+    #[test]
+    fn pawns_promote_on_the_middle_rank() {
+        let mut state = kings();
+        state.side_to_move = Player::Blue;
+        state.put_piece(PieceType::Pawn, Player::Blue, sq(6, 5));
+        let moves = state.generate_legal_moves();
+        let promotion = Move {
+            from: sq(6, 5),
+            to: sq(7, 5),
+            flag: MoveFlag::Promotion(PieceType::Queen),
+        };
+        assert!(moves.contains(&promotion));
+        state.make_move(promotion);
+        assert_eq!(
+            state.piece_at(sq(7, 5)),
+            Some((PieceType::Queen, Player::Blue))
+        );
+    }
+
+    #[test]
+    fn castling_moves_king_and_rook_from_each_side() {
+        let mut state = FourPlayerState::new();
+        for (file, rank) in [(8, 0), (9, 0)] {
+            state.board[Player::Green.square(file, rank) as usize] = None;
+        }
+        state.side_to_move = Player::Green;
+        let castle = Move {
+            from: Player::Green.square(7, 0),
+            to: Player::Green.square(9, 0),
+            flag: MoveFlag::KingsideCastle,
+        };
+        assert!(state.generate_legal_moves().contains(&castle));
+        state.make_move(castle);
+        assert_eq!(
+            state.piece_at(sq(13, 9)),
+            Some((PieceType::King, Player::Green))
+        );
+        assert_eq!(
+            state.piece_at(sq(13, 8)),
+            Some((PieceType::Rook, Player::Green))
+        );
+        assert_eq!(state.piece_at(sq(13, 10)), None);
+        assert_eq!(
+            state.castling[Player::Green as usize],
+            CastlingSides::default()
+        );
+    }
+}
@@ -4,36 +4,168 @@ use bevy::{ecs::resource::Resource, platform::collections::HashMap};
 
 use crate::{
     bitboard::BitBoard,
+    fen::START_FEN,
+    movegen::king_attacks,
     rendering::{PieceColor, PieceType},
+    zobrist::ZOBRIST,
 };
 
 // --- Move types ---
 
+/// A move from one square to another. `P` names the piece a pawn promotes
+/// to: a `PieceType` everywhere but in `WideGameState`, whose pieces are
+/// ids into a roster of piece definitions.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct Move {
+pub struct Move<P = PieceType> {
     pub from: u8,
     pub to: u8,
-    pub flag: MoveFlag,
+    pub flag: MoveFlag<P>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum MoveFlag {
+pub enum MoveFlag<P = PieceType> {
     Quiet,
     DoublePawnPush,
     KingsideCastle,
     QueensideCastle,
     Capture,
     EnPassant,
-    Promotion(PieceType),
-    PromotionCapture(PieceType),
+    Promotion(P),
+    PromotionCapture(P),
+}
+
+impl<P> Move<P> {
+    /// The same move with its promotion piece, if any, named by `f`: how
+    /// moves cross between `GameState` and `WideGameState`.
+    pub fn map_promotion<Q>(self, f: impl FnOnce(P) -> Q) -> Move<Q> {
+        let flag = match self.flag {
+            MoveFlag::Quiet => MoveFlag::Quiet,
+            MoveFlag::DoublePawnPush => MoveFlag::DoublePawnPush,
+            MoveFlag::KingsideCastle => MoveFlag::KingsideCastle,
+            MoveFlag::QueensideCastle => MoveFlag::QueensideCastle,
+            MoveFlag::Capture => MoveFlag::Capture,
+            MoveFlag::EnPassant => MoveFlag::EnPassant,
+            MoveFlag::Promotion(p) => MoveFlag::Promotion(f(p)),
+            MoveFlag::PromotionCapture(p) => MoveFlag::PromotionCapture(f(p)),
+        };
+        Move {
+            from: self.from,
+            to: self.to,
+            flag,
+        }
+    }
+}
+
+/// Upper bound on the moves of any position; the known maximum is 218.
+pub const MAX_MOVES: usize = 256;
+
+/// Fixed-capacity list of moves kept on the stack, so move generation never
+/// allocates. Dereferences to a slice for everything read-only.
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            moves: [Move { from: 0, to: 0, flag: MoveFlag::Quiet }; MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    pub fn push(&mut self, mv: Move) {
+        debug_assert!(self.len < MAX_MOVES, "more than {MAX_MOVES} moves");
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    /// Removes the move at `index`, replacing it with the last one.
+    pub fn swap_remove(&mut self, index: usize) -> Move {
+        let mv = self[index];
+        self.len -= 1;
+        self.moves[index] = self.moves[self.len];
+        mv
+    }
+
+    /// Keeps only the moves matching `keep`, preserving their order.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl std::ops::Deref for MoveList {
+    type Target = [Move];
+
+    #[inline]
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl std::ops::DerefMut for MoveList {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 // --- Castling rights ---
 
-#[derive(Clone, Copy, Debug, Default)]
+/// Files of the rooks a color may still castle with, `None` once that side is
+/// lost. Recording the files rather than flags lets the same rules cover
+/// Chess960 setups, where the rooks can start anywhere on the back rank.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct CastlingSides {
-    pub kingside: bool,
-    pub queenside: bool,
+    pub kingside: Option<u8>,
+    pub queenside: Option<u8>,
 }
 
 /// Castling availability per color. Adding a new color (e.g. for 4-player chess)
@@ -43,30 +175,122 @@ pub struct CastlingRights(pub HashMap<PieceColor, CastlingSides>);
 
 impl CastlingRights {
     pub fn kingside(&self, color: PieceColor) -> bool {
-        self.0.get(&color).map_or(false, |s| s.kingside)
+        self.kingside_file(color).is_some()
     }
 
     pub fn queenside(&self, color: PieceColor) -> bool {
-        self.0.get(&color).map_or(false, |s| s.queenside)
+        self.queenside_file(color).is_some()
+    }
+
+    /// File of the rook `color` may castle kingside with.
+    pub fn kingside_file(&self, color: PieceColor) -> Option<u8> {
+        self.0.get(&color).and_then(|s| s.kingside)
+    }
+
+    /// File of the rook `color` may castle queenside with.
+    pub fn queenside_file(&self, color: PieceColor) -> Option<u8> {
+        self.0.get(&color).and_then(|s| s.queenside)
+    }
+
+    pub fn sides(&self, color: PieceColor) -> CastlingSides {
+        self.0.get(&color).copied().unwrap_or_default()
     }
 
     pub fn revoke_kingside(&mut self, color: PieceColor) {
         if let Some(s) = self.0.get_mut(&color) {
-            s.kingside = false;
+            s.kingside = None;
         }
     }
 
     pub fn revoke_queenside(&mut self, color: PieceColor) {
         if let Some(s) = self.0.get_mut(&color) {
-            s.queenside = false;
+            s.queenside = None;
         }
     }
 
     pub fn revoke_all(&mut self, color: PieceColor) {
         if let Some(s) = self.0.get_mut(&color) {
-            s.kingside = false;
-            s.queenside = false;
+            s.kingside = None;
+            s.queenside = None;
+        }
+    }
+
+    /// Revokes whichever side `color` would castle with the rook on `file`.
+    pub fn revoke_file(&mut self, color: PieceColor, file: u8) {
+        if let Some(s) = self.0.get_mut(&color) {
+            if s.kingside == Some(file) {
+                s.kingside = None;
+            }
+            if s.queenside == Some(file) {
+                s.queenside = None;
+            }
+        }
+    }
+}
+
+/// Row a color's pieces start on, and castle along.
+#[inline]
+pub fn back_rank(color: PieceColor) -> u8 {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 7,
+    }
+}
+
+/// Files the king and rook end up on after castling kingside, in standard chess
+/// and Chess960 alike.
+pub const KINGSIDE_CASTLE_FILES: (u8, u8) = (6, 5);
+/// Files the king and rook end up on after castling queenside.
+pub const QUEENSIDE_CASTLE_FILES: (u8, u8) = (2, 3);
+
+/// Which rules a `GameState` is played under.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rules {
+    #[default]
+    Standard,
+    /// Captures explode, taking the capturer, the captured piece and every
+    /// piece but pawns around the target square off the board. Kings can't
+    /// capture, and blowing up the enemy king wins.
+    Atomic,
+}
+
+/// The pieces pawns may promote to, which a variant's rules may narrow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Promotions(u8);
+
+impl Promotions {
+    /// Queen, rook, bishop and knight, in the order moves are generated.
+    const ORDER: [PieceType; 4] = [
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+    ];
+
+    /// `None` if any of `pieces` is a pawn or a king.
+    pub fn new(pieces: impl IntoIterator<Item = PieceType>) -> Option<Promotions> {
+        let mut mask = 0;
+        for pt in pieces {
+            if matches!(pt, PieceType::Pawn | PieceType::King) {
+                return None;
+            }
+            mask |= 1 << pt as u8;
         }
+        Some(Promotions(mask))
+    }
+
+    pub fn contains(self, pt: PieceType) -> bool {
+        self.0 & 1 << pt as u8 != 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = PieceType> {
+        Promotions::ORDER.into_iter().filter(move |&pt| self.contains(pt))
+    }
+}
+
+impl Default for Promotions {
+    fn default() -> Self {
+        Promotions::new(Promotions::ORDER).expect("no pawn or king")
     }
 }
 
@@ -74,186 +298,390 @@ impl CastlingRights {
 
 #[derive(Resource, Clone)]
 pub struct GameState {
-    pub pieces: HashMap<(PieceType, PieceColor), BitBoard>,
+    /// Bitboards indexed by `[PieceType as usize][PieceColor as usize]`.
+    pieces: [[BitBoard; 2]; 6],
+    /// Union of each color's bitboards, indexed by `PieceColor as usize`.
+    color_occupancy: [BitBoard; 2],
+    /// What stands on each square, mirroring the bitboards.
+    mailbox: [Option<(PieceType, PieceColor)>; 64],
     pub side_to_move: PieceColor,
     pub castling_rights: CastlingRights,
     pub en_passant: Option<u8>,
     pub halfmove_clock: u32,
     pub fullmove_number: u32,
+    /// Zobrist hash of the position, kept up to date by `apply_move`.
+    pub hash: u64,
+    /// Standard unless changed with `set_rules`: FEN does not record it.
+    pub(crate) rules: Rules,
+    /// All four pieces unless set by hand. FEN does not record it either.
+    pub promotions: Promotions,
 }
 
 impl GameState {
+    /// A board without pieces, White to move and nobody allowed to castle.
+    pub fn empty() -> GameState {
+        let mut state = GameState {
+            pieces: [[BitBoard(0); 2]; 6],
+            color_occupancy: [BitBoard(0); 2],
+            mailbox: [None; 64],
+            side_to_move: PieceColor::White,
+            castling_rights: CastlingRights(HashMap::from([
+                (PieceColor::White, CastlingSides::default()),
+                (PieceColor::Black, CastlingSides::default()),
+            ])),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            rules: Rules::Standard,
+            promotions: Promotions::default(),
+        };
+        state.hash = state.compute_hash();
+        state
+    }
+
+    #[inline]
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Switches the rules the position is played under, and its `hash` with
+    /// them.
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.hash ^= ZOBRIST.rules(self.rules) ^ ZOBRIST.rules(rules);
+        self.rules = rules;
+    }
+
+    #[inline]
+    pub fn piece_bb(&self, pt: PieceType, color: PieceColor) -> BitBoard {
+        self.pieces[pt as usize][color as usize]
+    }
+
+    #[inline]
     pub fn pieces(&self, color: PieceColor) -> BitBoard {
-        self.pieces
-            .iter()
-            .filter(|(k, _v)| k.1 == color)
-            .fold(BitBoard(0), |acc, x| acc | x.1)
+        self.color_occupancy[color as usize]
     }
 
+    #[inline]
     pub fn occupancy(&self) -> BitBoard {
-        self.pieces.iter().fold(BitBoard(0), |acc, x| acc | x.1)
+        self.color_occupancy[0] | self.color_occupancy[1]
     }
 
+    #[inline]
     pub fn piece_at(&self, sq: u8) -> Option<(PieceType, PieceColor)> {
-        let bit = BitBoard::from_index(sq);
-        for ((pt, pc), bb) in &self.pieces {
-            if (*bb & bit) != BitBoard(0) {
-                return Some((*pt, *pc));
+        self.mailbox[sq as usize]
+    }
+
+    /// Every piece kind with its bitboard, empty ones included.
+    pub fn bitboards(&self) -> impl Iterator<Item = (PieceType, PieceColor, BitBoard)> + '_ {
+        PieceType::ALL.into_iter().flat_map(move |pt| {
+            PieceColor::ALL
+                .into_iter()
+                .map(move |pc| (pt, pc, self.piece_bb(pt, pc)))
+        })
+    }
+
+    /// Puts a piece on an empty square, for setting up positions.
+    pub fn put_piece(&mut self, pt: PieceType, pc: PieceColor, sq: u8) {
+        assert!(self.piece_at(sq).is_none(), "put_piece: square {sq} is occupied");
+        self.toggle_piece(pt, pc, sq);
+    }
+
+    /// Takes whatever stands on `sq` off the board.
+    pub fn remove_piece(&mut self, sq: u8) -> Option<(PieceType, PieceColor)> {
+        let piece = self.piece_at(sq)?;
+        self.toggle_piece(piece.0, piece.1, sq);
+        Some(piece)
+    }
+
+    /// Rook origin and destination for a castling move by `color`, `None` for
+    /// any other move or a side that can no longer castle.
+    pub fn castling_rook_squares(&self, flag: MoveFlag, color: PieceColor) -> Option<(u8, u8)> {
+        let rank = back_rank(color) * 8;
+        let (file, (_, rook_to)) = match flag {
+            MoveFlag::KingsideCastle => {
+                (self.castling_rights.kingside_file(color)?, KINGSIDE_CASTLE_FILES)
             }
-        }
-        None
+            MoveFlag::QueensideCastle => {
+                (self.castling_rights.queenside_file(color)?, QUEENSIDE_CASTLE_FILES)
+            }
+            _ => return None,
+        };
+        Some((rank + file, rank + rook_to))
     }
 
+    /// Returns the position after `mv`, leaving `self` untouched.
     pub fn apply_move(&self, mv: Move) -> GameState {
         let mut state = self.clone();
-        let from_bit = BitBoard::from_index(mv.from);
-        let to_bit = BitBoard::from_index(mv.to);
+        state.make_move(mv);
+        state
+    }
+
+    /// Plays `mv` in place. The returned `Undo` must be handed back to
+    /// `unmake_move` (together with the same move) to restore the position.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let keys = &*ZOBRIST;
         let (moving_pt, moving_pc) = self
             .piece_at(mv.from)
-            .expect("apply_move: no piece at from square");
+            .expect("make_move: no piece at from square");
+        let enemy = moving_pc.opponent();
+
+        let mut undo = Undo {
+            captured: None,
+            explosion: None,
+            castling: [
+                (moving_pc, self.castling_rights.sides(moving_pc)),
+                (enemy, self.castling_rights.sides(enemy)),
+            ],
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        // Whether the old en passant square counts depends on the pawns
+        // around it, so it leaves the hash before any of them move
+        self.hash ^= self.en_passant_key();
 
-        // Remove moving piece from source
-        *state.pieces.get_mut(&(moving_pt, moving_pc)).unwrap() &= !from_bit;
+        // Remove moving piece from source. In Chess960 the king may land on
+        // its rook's square, so a castling rook is lifted before the king lands.
+        let castling_rook = self.castling_rook_squares(mv.flag, moving_pc);
+        self.toggle_piece(moving_pt, moving_pc, mv.from);
+        if let Some((rf, _)) = castling_rook {
+            self.toggle_piece(PieceType::Rook, moving_pc, rf);
+        }
 
         // Handle captures
         match mv.flag {
             MoveFlag::Capture | MoveFlag::PromotionCapture(_) => {
                 if let Some((cap_pt, cap_pc)) = self.piece_at(mv.to) {
-                    *state.pieces.get_mut(&(cap_pt, cap_pc)).unwrap() &= !to_bit;
+                    self.toggle_piece(cap_pt, cap_pc, mv.to);
+                    undo.captured = Some(cap_pt);
                 }
             }
             MoveFlag::EnPassant => {
-                let cap_sq = if moving_pc == PieceColor::White {
-                    mv.to - 8
-                } else {
-                    mv.to + 8
-                };
-                let enemy = moving_pc.opponent();
-                *state.pieces.get_mut(&(PieceType::Pawn, enemy)).unwrap() &=
-                    !BitBoard::from_index(cap_sq);
+                self.toggle_piece(PieceType::Pawn, enemy, en_passant_victim(mv.to, moving_pc));
+                undo.captured = Some(PieceType::Pawn);
             }
             _ => {}
         }
 
-        // Place piece at destination (swapped for promotions)
-        let placed_pt = match mv.flag {
-            MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) => pt,
-            _ => moving_pt,
-        };
-        *state.pieces.get_mut(&(placed_pt, moving_pc)).unwrap() |= to_bit;
-
-        // Castling: also move the rook
-        match mv.flag {
-            MoveFlag::KingsideCastle => {
-                let (rf, rt) = if moving_pc == PieceColor::White {
-                    (7u8, 5u8)
-                } else {
-                    (63u8, 61u8)
-                };
-                let bb = state.pieces.get_mut(&(PieceType::Rook, moving_pc)).unwrap();
-                *bb = (*bb & !BitBoard::from_index(rf)) | BitBoard::from_index(rt);
-            }
-            MoveFlag::QueensideCastle => {
-                let (rf, rt) = if moving_pc == PieceColor::White {
-                    (0u8, 3u8)
-                } else {
-                    (56u8, 59u8)
-                };
-                let bb = state.pieces.get_mut(&(PieceType::Rook, moving_pc)).unwrap();
-                *bb = (*bb & !BitBoard::from_index(rf)) | BitBoard::from_index(rt);
+        if self.rules == Rules::Atomic && undo.captured.is_some() {
+            // The capturer goes up with everything around it but pawns
+            let mut explosion = Explosion {
+                capturer: moving_pt,
+                victims: [None; 8],
+            };
+            let blast = king_attacks(BitBoard::from_index(mv.to));
+            for (victim, sq) in explosion.victims.iter_mut().zip(blast) {
+                if let Some((pt, pc)) = self.piece_at(sq)
+                    && pt != PieceType::Pawn
+                {
+                    self.toggle_piece(pt, pc, sq);
+                    *victim = Some((sq, pt, pc));
+                }
             }
-            _ => {}
+            undo.explosion = Some(explosion);
+        } else {
+            // Place piece at destination (swapped for promotions)
+            let placed_pt = match mv.flag {
+                MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) => pt,
+                _ => moving_pt,
+            };
+            self.toggle_piece(placed_pt, moving_pc, mv.to);
+        }
+
+        // Castling: put the rook down next to the king
+        if let Some((_, rt)) = castling_rook {
+            self.toggle_piece(PieceType::Rook, moving_pc, rt);
         }
 
         // En passant target square (the square the pawn skipped over)
-        state.en_passant = if mv.flag == MoveFlag::DoublePawnPush {
+        self.en_passant = if mv.flag == MoveFlag::DoublePawnPush {
             Some((mv.from + mv.to) / 2)
         } else {
             None
         };
 
         // Castling rights: king moves forfeit both sides
+        self.hash ^= keys.castling(&self.castling_rights);
         if moving_pt == PieceType::King {
-            state.castling_rights.revoke_all(moving_pc);
+            self.castling_rights.revoke_all(moving_pc);
         }
-        // Castling rights: rook leaving its corner forfeits that side
-        for sq in [mv.from, mv.to] {
-            match sq {
-                0 => state.castling_rights.revoke_queenside(PieceColor::White),
-                7 => state.castling_rights.revoke_kingside(PieceColor::White),
-                56 => state.castling_rights.revoke_queenside(PieceColor::Black),
-                63 => state.castling_rights.revoke_kingside(PieceColor::Black),
-                _ => {}
+        // Castling rights: a castling rook moving, being captured or blown up
+        // forfeits its side, and so does a king blown up
+        let exploded = undo.explosion.iter().flat_map(|e| e.victims.into_iter().flatten());
+        for (_, pt, pc) in exploded.clone() {
+            if pt == PieceType::King {
+                self.castling_rights.revoke_all(pc);
             }
         }
+        for sq in [mv.from, mv.to].into_iter().chain(exploded.map(|(sq, _, _)| sq)) {
+            for color in PieceColor::ALL {
+                if sq / 8 == back_rank(color) {
+                    self.castling_rights.revoke_file(color, sq % 8);
+                }
+            }
+        }
+        self.hash ^= keys.castling(&self.castling_rights);
 
         // Side to move
-        state.side_to_move = moving_pc.opponent();
+        self.side_to_move = enemy;
+        self.hash ^= keys.side();
+        self.hash ^= self.en_passant_key();
 
         // Halfmove clock
-        if moving_pt == PieceType::Pawn
-            || matches!(
-                mv.flag,
-                MoveFlag::Capture | MoveFlag::EnPassant | MoveFlag::PromotionCapture(_)
-            )
-        {
-            state.halfmove_clock = 0;
+        if moving_pt == PieceType::Pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
         } else {
-            state.halfmove_clock += 1;
+            self.halfmove_clock += 1;
         }
 
         // Fullmove number increments after Black's move
         if moving_pc == PieceColor::Black {
-            state.fullmove_number += 1;
+            self.fullmove_number += 1;
         }
 
-        state
+        undo
     }
+
+    /// Takes back `mv`, which must be the last move made with `make_move`.
+    pub fn unmake_move(&mut self, mv: Move, undo: Undo) {
+        let moving_pc = self.side_to_move.opponent();
+        let enemy = self.side_to_move;
+        // An atomic capture leaves the destination square empty
+        let placed_pt = match undo.explosion {
+            Some(_) => None,
+            None => Some(
+                self.piece_at(mv.to)
+                    .expect("unmake_move: no piece at destination square")
+                    .0,
+            ),
+        };
+        let moving_pt = match (undo.explosion, mv.flag) {
+            (Some(explosion), _) => explosion.capturer,
+            (None, MoveFlag::Promotion(_) | MoveFlag::PromotionCapture(_)) => PieceType::Pawn,
+            (None, _) => placed_pt.expect("set when nothing exploded"),
+        };
+
+        // Rights first: they say which rook a castling move took along
+        for (color, sides) in undo.castling {
+            self.castling_rights.0.insert(color, sides);
+        }
+        let castling_rook = self.castling_rook_squares(mv.flag, moving_pc);
+        if let Some((_, rt)) = castling_rook {
+            self.toggle_piece(PieceType::Rook, moving_pc, rt);
+        }
+
+        if let Some(placed_pt) = placed_pt {
+            self.toggle_piece(placed_pt, moving_pc, mv.to);
+        }
+        for (sq, pt, pc) in undo.explosion.iter().flat_map(|e| e.victims.into_iter().flatten()) {
+            self.toggle_piece(pt, pc, sq);
+        }
+        if let Some(cap_pt) = undo.captured {
+            let cap_sq = if mv.flag == MoveFlag::EnPassant {
+                en_passant_victim(mv.to, moving_pc)
+            } else {
+                mv.to
+            };
+            self.toggle_piece(cap_pt, enemy, cap_sq);
+        }
+        self.toggle_piece(moving_pt, moving_pc, mv.from);
+        if let Some((rf, _)) = castling_rook {
+            self.toggle_piece(PieceType::Rook, moving_pc, rf);
+        }
+
+        if moving_pc == PieceColor::Black {
+            self.fullmove_number -= 1;
+        }
+        self.side_to_move = moving_pc;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+    }
+
+    /// Adds or removes a piece, keeping the occupancy, mailbox and hash in sync.
+    /// Callers never place a piece on an occupied square: captured pieces are
+    /// removed first.
+    #[inline]
+    fn toggle_piece(&mut self, pt: PieceType, pc: PieceColor, sq: u8) {
+        let bit = BitBoard::from_index(sq);
+        let bb = &mut self.pieces[pt as usize][pc as usize];
+        *bb ^= bit;
+        self.mailbox[sq as usize] = (*bb & bit != BitBoard(0)).then_some((pt, pc));
+        self.color_occupancy[pc as usize] ^= bit;
+        self.hash ^= ZOBRIST.piece(pt, pc, sq);
+    }
+}
+
+/// What `make_move` destroys and `unmake_move` needs to put back.
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    captured: Option<PieceType>,
+    explosion: Option<Explosion>,
+    castling: [(PieceColor, CastlingSides); 2],
+    en_passant: Option<u8>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+
+/// The pieces an atomic capture took off the board besides the captured one.
+#[derive(Clone, Copy, Debug)]
+struct Explosion {
+    /// What the capturing piece was before it moved.
+    capturer: PieceType,
+    /// Pieces around the target square, as (square, piece, color).
+    victims: [Option<(u8, PieceType, PieceColor)>; 8],
+}
+
+/// Square of the pawn taken by an en passant capture landing on `to`.
+#[inline]
+fn en_passant_victim(to: u8, color: PieceColor) -> u8 {
+    if color == PieceColor::White { to - 8 } else { to + 8 }
 }
 
 impl fmt::Display for GameState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut array = ['o'; 64];
-        for ((piece, color), bb) in self.pieces.iter() {
-            for i in bb.get_piece_positions() {
+        for (piece, color, bb) in self.bitboards() {
+            for i in bb {
                 array[i as usize] = match piece {
                     PieceType::Pawn => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'P'
                         } else {
                             'p'
                         }
                     }
                     PieceType::Knight => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'N'
                         } else {
                             'n'
                         }
                     }
                     PieceType::Bishop => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'B'
                         } else {
                             'b'
                         }
                     }
                     PieceType::Rook => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'R'
                         } else {
                             'r'
                         }
                     }
                     PieceType::Queen => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'Q'
                         } else {
                             'q'
                         }
                     }
                     PieceType::King => {
-                        if *color == PieceColor::White {
+                        if color == PieceColor::White {
                             'K'
                         } else {
                             'k'
@@ -274,89 +702,77 @@ impl fmt::Display for GameState {
 
 impl Default for GameState {
     fn default() -> Self {
-        GameState {
-            pieces: HashMap::from([
-                (
-                    (PieceType::Pawn, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_11111111_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::Pawn, PieceColor::Black),
-                    BitBoard(
-                        0b00000000_11111111_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::Knight, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_01000010,
-                    ),
-                ),
-                (
-                    (PieceType::Knight, PieceColor::Black),
-                    BitBoard(
-                        0b01000010_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::Bishop, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00100100,
-                    ),
-                ),
-                (
-                    (PieceType::Bishop, PieceColor::Black),
-                    BitBoard(
-                        0b00100100_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::Rook, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10000001,
-                    ),
-                ),
-                (
-                    (PieceType::Rook, PieceColor::Black),
-                    BitBoard(
-                        0b10000001_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::Queen, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00001000,
-                    ),
-                ),
-                (
-                    (PieceType::Queen, PieceColor::Black),
-                    BitBoard(
-                        0b00001000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-                (
-                    (PieceType::King, PieceColor::White),
-                    BitBoard(
-                        0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00010000,
-                    ),
-                ),
-                (
-                    (PieceType::King, PieceColor::Black),
-                    BitBoard(
-                        0b00010000_00000000_00000000_00000000_00000000_00000000_00000000_00000000,
-                    ),
-                ),
-            ]),
-            side_to_move: PieceColor::White,
-            castling_rights: CastlingRights(HashMap::from([
-                (PieceColor::White, CastlingSides { kingside: true, queenside: true }),
-                (PieceColor::Black, CastlingSides { kingside: true, queenside: true }),
-            ])),
-            en_passant: None,
-            halfmove_clock: 0,
-            fullmove_number: 1,
+        GameState::from_fen(START_FEN).expect("START_FEN is valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate_legal_moves;
+
+    fn check_unmake(state: &mut GameState, depth: u32) {
+        if depth == 0 {
+            return;
+        }
+        let fen = state.to_fen();
+        let hash = state.hash;
+        for mv in generate_legal_moves(state) {
+            let undo = state.make_move(mv);
+            assert_eq!(state.hash, state.compute_hash());
+            check_unmake(state, depth - 1);
+            state.unmake_move(mv, undo);
+            assert_eq!(state.to_fen(), fen, "unmaking {mv:?}");
+            assert_eq!(state.hash, hash);
+        }
+    }
+
+    #[test]
+    fn unmake_restores_position() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            check_unmake(&mut GameState::from_fen(fen).unwrap(), 3);
         }
     }
+
+    #[test]
+    fn atomic_captures_explode_and_unmake() {
+        // Nxe5 takes the bishop on d6 along, but not the pawns around e5
+        let mut state = GameState::from_fen("4k3/8/3b1p2/4p3/4P3/5N2/8/4K3 w - - 0 1").unwrap();
+        state.set_rules(Rules::Atomic);
+        let before = state.clone();
+        let mv = Move { from: 21, to: 36, flag: MoveFlag::Capture };
+        let undo = state.make_move(mv);
+        assert_eq!(state.to_fen(), "4k3/8/5p2/8/4P3/8/8/4K3 b - - 0 1");
+        assert_eq!(state.hash, state.compute_hash());
+        state.unmake_move(mv, undo);
+        assert_eq!(state.to_fen(), before.to_fen());
+        assert_eq!(state.hash, before.hash);
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let mut state = GameState::from_fen(fen).unwrap();
+            state.set_rules(Rules::Atomic);
+            check_unmake(&mut state, 3);
+        }
+    }
+
+    #[test]
+    fn apply_move_matches_make_move() {
+        let state = GameState::default();
+        let mv = Move { from: 12, to: 28, flag: MoveFlag::DoublePawnPush };
+        let mut in_place = state.clone();
+        in_place.make_move(mv);
+        assert_eq!(state.apply_move(mv).to_fen(), in_place.to_fen());
+        assert_eq!(
+            in_place.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
 }
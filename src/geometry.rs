@@ -0,0 +1,178 @@
+use bevy::ecs::resource::Resource;
+
+use crate::bitboard::WideBitBoard;
+
+/// Most squares a board can have and still fit a `WideBitBoard`.
+pub const MAX_SQUARES: usize = 128;
+
+/// Dimensions of a rectangular board. Squares are numbered row by row from
+/// the bottom left, `row * width + col`, which on the standard board matches
+/// the `a1 = 0` numbering of `BitBoard`.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoardGeometry {
+    pub width: u8,
+    pub height: u8,
+}
+
+impl BoardGeometry {
+    pub const STANDARD: BoardGeometry = BoardGeometry {
+        width: 8,
+        height: 8,
+    };
+    /// Capablanca chess and its relatives.
+    pub const CAPABLANCA: BoardGeometry = BoardGeometry {
+        width: 10,
+        height: 8,
+    };
+    /// Grand chess.
+    pub const GRAND: BoardGeometry = BoardGeometry {
+        width: 10,
+        height: 10,
+    };
+    /// Courier chess.
+    pub const COURIER: BoardGeometry = BoardGeometry {
+        width: 12,
+        height: 8,
+    };
+
+    /// A `width` x `height` board, `None` if it has no squares, more than
+    /// `MAX_SQUARES`, or more files than there are letters to name them.
+    pub fn new(width: u8, height: u8) -> Option<BoardGeometry> {
+        let squares = width as usize * height as usize;
+        (squares > 0 && squares <= MAX_SQUARES && width <= 26)
+            .then_some(BoardGeometry { width, height })
+    }
+
+    #[inline]
+    pub fn squares(self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    #[inline]
+    pub fn square(self, col: u8, row: u8) -> u8 {
+        row * self.width + col
+    }
+
+    #[inline]
+    pub fn col(self, sq: u8) -> u8 {
+        sq % self.width
+    }
+
+    #[inline]
+    pub fn row(self, sq: u8) -> u8 {
+        sq / self.width
+    }
+
+    /// The square `dc` files and `dr` ranks away from `sq`, `None` when that
+    /// falls off the board.
+    #[inline]
+    pub fn offset(self, sq: u8, dc: i32, dr: i32) -> Option<u8> {
+        let col = self.col(sq) as i32 + dc;
+        let row = self.row(sq) as i32 + dr;
+        ((0..self.width as i32).contains(&col) && (0..self.height as i32).contains(&row))
+            .then(|| self.square(col as u8, row as u8))
+    }
+
+    /// Every square of the board.
+    pub fn all(self) -> WideBitBoard {
+        WideBitBoard(u128::MAX >> (128 - self.squares()))
+    }
+
+    /// The squares of one rank, `row` counted from 0.
+    pub fn rank(self, row: u8) -> WideBitBoard {
+        let rank = WideBitBoard(u128::MAX >> (128 - self.width as usize));
+        rank << (row as usize * self.width as usize)
+    }
+
+    /// For every square, the squares reached by one jump of each of `moves`
+    /// (file, rank offsets) — `generate_table` for boards of any size.
+    pub fn leaper_table(self, moves: &[(i32, i32)]) -> Vec<WideBitBoard> {
+        (0..self.squares() as u8)
+            .map(|sq| {
+                moves
+                    .iter()
+                    .filter_map(|&(dc, dr)| self.offset(sq, dc, dr))
+                    .fold(WideBitBoard::EMPTY, |bb, to| {
+                        bb | WideBitBoard::from_index(to)
+                    })
+            })
+            .collect()
+    }
+
+    /// Squares a slider on `sq` reaches along `directions`, each ray stopping
+    /// at (and including) the first occupied square.
+    pub fn slider_attacks(
+        self,
+        sq: u8,
+        occ: WideBitBoard,
+        directions: &[(i32, i32)],
+    ) -> WideBitBoard {
+        let mut attacks = WideBitBoard::EMPTY;
+        for &(dc, dr) in directions {
+            let mut current = sq;
+            while let Some(next) = self.offset(current, dc, dr) {
+                attacks |= WideBitBoard::from_index(next);
+                if occ.contains(next) {
+                    break;
+                }
+                current = next;
+            }
+        }
+        attacks
+    }
+}
+
+impl Default for BoardGeometry {
+    fn default() -> Self {
+        BoardGeometry::STANDARD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::{KNIGHT_MOVES, ROOK_DIRECTIONS};
+
+    #[test]
+    fn rejects_boards_a_wide_bitboard_cannot_hold() {
+        assert_eq!(BoardGeometry::new(10, 10), Some(BoardGeometry::GRAND));
+        assert_eq!(BoardGeometry::new(12, 11), None);
+        assert_eq!(BoardGeometry::new(0, 8), None);
+        assert_eq!(BoardGeometry::COURIER.all().count(), 96);
+        assert_eq!(BoardGeometry::GRAND.rank(9).count(), 10);
+        assert!(BoardGeometry::GRAND.rank(9).contains(99));
+    }
+
+    #[test]
+    fn offsets_do_not_wrap_around_the_edge() {
+        let g = BoardGeometry::CAPABLANCA;
+        let j1 = g.square(9, 0);
+        assert_eq!(g.offset(j1, 1, 0), None);
+        assert_eq!(g.offset(j1, -1, 1), Some(g.square(8, 1)));
+        assert_eq!(g.offset(g.square(0, 7), 0, 1), None);
+    }
+
+    #[test]
+    fn leaper_table_matches_the_standard_board() {
+        let knights = BoardGeometry::STANDARD.leaper_table(&KNIGHT_MOVES);
+        assert_eq!(knights[0].0, 0x0002_0400);
+        assert_eq!(knights[27].count(), 8);
+        // A knight in the corner of a Courier board still only has two moves
+        let courier = BoardGeometry::COURIER.leaper_table(&KNIGHT_MOVES);
+        assert_eq!(courier[11].count(), 2);
+        assert_eq!(courier[95].count(), 2);
+    }
+
+    #[test]
+    fn sliders_stop_at_blockers() {
+        let g = BoardGeometry::GRAND;
+        assert_eq!(
+            g.slider_attacks(0, WideBitBoard::EMPTY, &ROOK_DIRECTIONS)
+                .count(),
+            18
+        );
+        let blocker = WideBitBoard::from_index(g.square(0, 5));
+        let attacks = g.slider_attacks(0, blocker, &ROOK_DIRECTIONS);
+        assert!(attacks.contains(g.square(0, 5)) && !attacks.contains(g.square(0, 6)));
+    }
+}
@@ -0,0 +1,231 @@
+use bevy::ecs::resource::Resource;
+
+use crate::{
+    game::{GameState, Move},
+    outcome::{DrawReason, GameOutcome, claimable_draw, game_outcome},
+    wide::{WideGameState, WideMove},
+};
+
+/// A position a `GameHistory` can record.
+pub trait Position: Clone {
+    type Move: Copy + PartialEq;
+
+    /// The position after `mv`, leaving `self` untouched.
+    fn apply_move(&self, mv: Self::Move) -> Self;
+}
+
+impl Position for GameState {
+    type Move = Move;
+
+    fn apply_move(&self, mv: Move) -> GameState {
+        GameState::apply_move(self, mv)
+    }
+}
+
+impl Position for WideGameState {
+    type Move = WideMove;
+
+    fn apply_move(&self, mv: WideMove) -> WideGameState {
+        WideGameState::apply_move(self, mv)
+    }
+}
+
+/// A game as a sequence of moves from an initial position, with a cursor that
+/// can walk back and forth through it (takebacks, replay). `S` is
+/// `WideGameState` for the games of a variant loaded from a rule file.
+///
+/// Moves after the cursor form the redo line: they are kept until a different
+/// move is played from an earlier position.
+#[derive(Resource, Clone)]
+pub struct GameHistory<S: Position = GameState> {
+    /// `states[i]` is the position after `i` plies, `states[0]` the initial one.
+    states: Vec<S>,
+    moves: Vec<S::Move>,
+    ply: usize,
+}
+
+impl<S: Position> GameHistory<S> {
+    pub fn new(initial: S) -> GameHistory<S> {
+        GameHistory {
+            states: vec![initial],
+            moves: Vec::new(),
+            ply: 0,
+        }
+    }
+
+    /// Position at the cursor.
+    pub fn current(&self) -> &S {
+        &self.states[self.ply]
+    }
+
+    pub fn initial(&self) -> &S {
+        &self.states[0]
+    }
+
+    /// Number of plies played to reach the current position.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// Number of recorded plies, including the redo line.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Moves leading to the current position.
+    pub fn played_moves(&self) -> &[S::Move] {
+        &self.moves[..self.ply]
+    }
+
+    /// Every recorded move, including the ones that were undone.
+    pub fn all_moves(&self) -> &[S::Move] {
+        &self.moves
+    }
+
+    /// Plays `mv` from the current position. The redo line is kept if `mv` is
+    /// its next move and discarded otherwise.
+    pub fn push(&mut self, mv: S::Move) {
+        if self.moves.get(self.ply) == Some(&mv) {
+            self.ply += 1;
+            return;
+        }
+        let next = self.current().apply_move(mv);
+        self.moves.truncate(self.ply);
+        self.states.truncate(self.ply + 1);
+        self.moves.push(mv);
+        self.states.push(next);
+        self.ply += 1;
+    }
+
+    /// Steps back one ply, returning the move taken back.
+    pub fn undo(&mut self) -> Option<S::Move> {
+        if self.ply == 0 {
+            return None;
+        }
+        self.ply -= 1;
+        Some(self.moves[self.ply])
+    }
+
+    /// Replays the next move of the redo line.
+    pub fn redo(&mut self) -> Option<S::Move> {
+        let mv = *self.moves.get(self.ply)?;
+        self.ply += 1;
+        Some(mv)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.ply > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.ply < self.moves.len()
+    }
+
+    /// Moves the cursor to `ply`. Returns `false` (and stays put) if it is past
+    /// the recorded moves.
+    pub fn jump_to(&mut self, ply: usize) -> bool {
+        if ply > self.moves.len() {
+            return false;
+        }
+        self.ply = ply;
+        true
+    }
+}
+
+impl GameHistory {
+    /// Hashes of the positions before the current one, oldest first.
+    pub fn previous_hashes(&self) -> Vec<u64> {
+        self.states[..self.ply].iter().map(|s| s.hash).collect()
+    }
+
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        game_outcome(self.current(), &self.previous_hashes())
+    }
+
+    pub fn claimable_draw(&self) -> Option<DrawReason> {
+        claimable_draw(self.current(), &self.previous_hashes())
+    }
+}
+
+impl Default for GameHistory {
+    fn default() -> Self {
+        GameHistory::new(GameState::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(history: &mut GameHistory, moves: &[&str]) {
+        for uci in moves {
+            let mv = Move::from_uci(uci, history.current()).unwrap();
+            history.push(mv);
+        }
+    }
+
+    #[test]
+    fn undo_redo_and_jump() {
+        let mut history = GameHistory::default();
+        play(&mut history, &["e2e4", "e7e5", "g1f3"]);
+        let after_nf3 = history.current().to_fen();
+
+        assert_eq!(
+            history.undo().map(|mv| mv.to_uci()),
+            Some("g1f3".to_string())
+        );
+        assert_eq!(history.ply(), 2);
+        assert!(history.can_redo());
+        assert_eq!(
+            history.redo().map(|mv| mv.to_uci()),
+            Some("g1f3".to_string())
+        );
+        assert_eq!(history.current().to_fen(), after_nf3);
+        assert_eq!(history.redo(), None);
+
+        assert!(history.jump_to(0));
+        assert_eq!(history.current().to_fen(), GameState::default().to_fen());
+        assert_eq!(history.undo(), None);
+        assert!(!history.jump_to(4));
+        assert!(history.jump_to(3));
+        assert_eq!(history.current().to_fen(), after_nf3);
+    }
+
+    #[test]
+    fn new_move_discards_redo_line() {
+        let mut history = GameHistory::default();
+        play(&mut history, &["e2e4", "e7e5", "g1f3"]);
+        history.jump_to(1);
+
+        // Replaying the recorded move keeps the rest of the line
+        play(&mut history, &["e7e5"]);
+        assert_eq!(history.len(), 3);
+
+        play(&mut history, &["d2d4"]);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.ply(), 3);
+        assert!(!history.can_redo());
+        assert_eq!(history.played_moves()[2].to_uci(), "d2d4");
+    }
+
+    #[test]
+    fn detects_repetition_from_history() {
+        let mut history = GameHistory::default();
+        for _ in 0..2 {
+            play(&mut history, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        }
+        assert_eq!(
+            history.claimable_draw(),
+            Some(DrawReason::ThreefoldRepetition)
+        );
+        assert_eq!(history.outcome(), None);
+
+        // Stepping back undoes the repetition
+        history.jump_to(4);
+        assert_eq!(history.claimable_draw(), None);
+    }
+}
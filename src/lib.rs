@@ -0,0 +1,22 @@
+pub mod bitboard;
+pub mod movegen;
+pub mod board;
+pub mod geometry;
+pub mod game;
+pub mod fairy;
+pub mod betza;
+pub mod wide;
+pub mod four_player;
+pub mod variant;
+pub mod chess960;
+pub mod fen;
+pub mod zobrist;
+pub mod outcome;
+pub mod san;
+pub mod uci;
+pub mod pgn;
+pub mod history;
+pub mod eval;
+pub mod search;
+pub mod transposition;
+pub mod rendering;
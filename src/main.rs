@@ -1,63 +1,193 @@
-mod bitboard;
-mod movegen;
-mod board;
-mod game;
-mod rendering;
+
+use std::time::Duration;
 
 use bevy::prelude::*;
 use bevy_inspector_egui::{bevy_egui::EguiPlugin, quick::WorldInspectorPlugin};
 
-use crate::bitboard::BitBoard;
-use crate::movegen::{bishop_attacks, queen_attacks, rook_attacks};
-use crate::rendering::{ChessPiece, PieceColor};
-use crate::board::BoardCoordinates;
+use enhanced_chess::rendering::VariantPiece;
+use enhanced_chess::board::{self, BoardCoordinates};
+use enhanced_chess::four_player::FourPlayerState;
+use enhanced_chess::game::GameState;
+use enhanced_chess::history::GameHistory;
+use enhanced_chess::uci::{EngineEvent, GoOptions, UciClient};
+use enhanced_chess::variant::{self, Variant};
+use enhanced_chess::wide::WideGameState;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .add_plugins(EguiPlugin::default())
-        .add_plugins(WorldInspectorPlugin::new())
-        .init_resource::<game::GameState>()
-        .add_systems(Startup, (setup, board::setup))
-        .run();
-}
+        .add_plugins(WorldInspectorPlugin::new());
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>, game_state: Res<game::GameState>) {
-    commands.spawn(Camera2d);
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--four-player") {
+        app.init_resource::<FourPlayerState>()
+            .add_systems(Startup, board::setup_four_player);
+    } else {
+        // Rules are read at startup, so editing them only needs a restart
+        let path = arg_value(&args, "--variant").unwrap_or(variant::DEFAULT_RULES);
+        let variant = match Variant::load(path) {
+            Ok(variant) => variant,
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                std::process::exit(1);
+            }
+        };
+        app.insert_resource(variant.geometry())
+            .insert_resource(GameHistory::new(variant.start_position()))
+            .insert_resource(variant)
+            .add_systems(Startup, (setup, board::setup));
 
-    for bb in &game_state.pieces {
-        let piece_type = &bb.0.0;
-        let piece_color = &bb.0.1;
-        for bit in bb.1.get_piece_positions() {
-            commands
-                .spawn(ChessPiece::new(
-                    piece_type.clone(),
-                    piece_color.clone(),
-                    BoardCoordinates::from_bit(bit),
-                    &asset_server,
-                ))
-                .observe(on_click_piece)
-                .observe(on_drag_piece);
+        if let Some(program) = arg_value(&args, "--engine") {
+            add_engine(&mut app, program);
         }
     }
+    app.run();
+}
 
-    println!("Initial Game State:\n{}", *game_state);
-    println!("Black pieces:\n{}", game_state.pieces(PieceColor::Black));
-    let test  = BitBoard::from_index(28);
-    println!("{}", PieceColor::Black as u8);
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
 }
 
-fn on_drag_piece(drag: On<Pointer<Drag>>, mut transforms: Query<&mut Transform>) {
-    if let Ok(mut transform) = transforms.get_mut(drag.entity) {
-        // Pointer<Drag> provides 'delta' in world space units for 2D sprites
-        transform.translation.x += drag.delta.x;
-        transform.translation.y -= drag.delta.y; // Y is often inverted in screen-to-world
+/// Starts the UCI engine at `program` to analyse the game. Engines only know
+/// orthodox chess, so the variant has to be chess with the standard pieces.
+fn add_engine(app: &mut App, program: &str) {
+    let variant = app.world().resource::<Variant>();
+    let Some(initial) = variant.orthodox_start() else {
+        eprintln!("{}: no engine for this variant", variant.name);
+        std::process::exit(1);
+    };
+    let client = match UciClient::spawn(program, &[]) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("{program}: {err}");
+            std::process::exit(1);
+        }
+    };
+    app.insert_resource(client)
+        .insert_resource(EngineSearch {
+            initial,
+            running: false,
+        })
+        .add_systems(
+            Update,
+            (start_engine_search, poll_engine)
+                .chain()
+                .run_if(resource_exists::<UciClient>),
+        );
+}
+
+#[derive(Resource)]
+struct EngineSearch {
+    /// The variant's start position as the engine plays it.
+    initial: GameState,
+    /// Whether the engine is thinking about the current position.
+    running: bool,
+}
+
+/// Sends the position to the engine whenever the game moves on.
+fn start_engine_search(
+    history: Res<GameHistory<WideGameState>>,
+    variant: Res<Variant>,
+    mut client: ResMut<UciClient>,
+    mut search: ResMut<EngineSearch>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    let result = (|| {
+        if search.running {
+            // Drain the answer to the old position before sending the new one
+            client.stop()?;
+            client.wait_best_move(Some(ENGINE_STOP_TIMEOUT), |_| {})?;
+        }
+        let moves: Vec<_> = history
+            .played_moves()
+            .iter()
+            .map(|mv| {
+                mv.map_promotion(|id| {
+                    variant
+                        .standard_piece(id)
+                        .expect("checked by orthodox_start")
+                })
+            })
+            .collect();
+        client.set_position(&search.initial, &moves)?;
+        client.go(&GoOptions {
+            movetime: Some(ENGINE_MOVE_TIME),
+            ..default()
+        })
+    })();
+    match result {
+        Ok(()) => search.running = true,
+        Err(err) => {
+            error!("engine: {err}");
+            search.running = false;
+        }
     }
 }
 
-fn on_click_piece(click: On<Pointer<Press>>, mut transforms: Query<&mut Transform>) {
-    if let Ok(mut transform) = transforms.get_mut(click.entity) {
-        transform.translation.x = click.hit.position.unwrap().x;
-        transform.translation.y = click.hit.position.unwrap().y;
+fn poll_engine(
+    mut commands: Commands,
+    mut client: ResMut<UciClient>,
+    mut search: ResMut<EngineSearch>,
+) {
+    loop {
+        match client.poll() {
+            Ok(Some(EngineEvent::Info(info))) => {
+                if let (Some(depth), Some(mv)) = (info.depth, info.pv.first()) {
+                    debug!("engine: depth {depth} {mv}");
+                }
+            }
+            Ok(Some(EngineEvent::BestMove { best, .. })) => {
+                search.running = false;
+                match best {
+                    Some(mv) => info!("engine: best move {mv}"),
+                    None => info!("engine: no legal move"),
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                // The engine is gone, stop talking to it
+                error!("engine: {err}");
+                search.running = false;
+                commands.remove_resource::<UciClient>();
+                break;
+            }
+        }
     }
 }
+
+const ENGINE_MOVE_TIME: Duration = Duration::from_secs(1);
+const ENGINE_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    variant: Res<Variant>,
+    history: Res<GameHistory<WideGameState>>,
+) {
+    commands.spawn(Camera2d);
+    let game_state = history.current();
+    let geometry = variant.geometry();
+
+    for sq in 0..geometry.squares() as u8 {
+        if let Some((id, piece_color)) = game_state.piece_at(sq) {
+            commands
+                .spawn(VariantPiece::new(
+                    id,
+                    piece_color,
+                    BoardCoordinates::from_square(sq, geometry),
+                    &variant,
+                    &asset_server,
+                ))
+                .observe(board::on_click_piece)
+                .observe(board::on_drag_piece);
+        }
+    }
+
+    info!("{}: {}", variant.name, game_state.to_fen());
+}
@@ -2,87 +2,161 @@ use std::sync::LazyLock;
 
 use crate::{
     bitboard::BitBoard,
-    game::{GameState, Move, MoveFlag},
+    game::{
+        GameState, KINGSIDE_CASTLE_FILES, Move, MoveFlag, MoveList, QUEENSIDE_CASTLE_FILES,
+        Rules, back_rank,
+    },
     rendering::{PieceColor, PieceType},
 };
 
-// I don't fully understand that code, but I've read about Hyperbola Quintessence
-// and the code works, so I'll study it further when I'll feel like switching to magic numbers
+// Slider attacks come from "fancy" magic bitboards: for every square, the
+// relevant blockers are multiplied by a magic number whose top bits form a
+// collision-free index into a table of precomputed attack sets.
 
 const A_FILE: u64 = 0x0101010101010101;
 const H_FILE: u64 = 0x8080808080808080;
-const RANK: u64 = 0xFF;
-const KNIGHT_MOVES: [(i32,i32);8] = [
+pub(crate) const KNIGHT_MOVES: [(i32,i32);8] = [
     (2,1),(2,-1),(-2,1),(-2,-1),
     (1,2),(1,-2),(-1,2),(-1,-2),
 ];
-const KING_MOVES: [(i32,i32);8] = [
+pub(crate) const KING_MOVES: [(i32,i32);8] = [
     (1,0),(-1,0),(0,1),(0,-1),
     (1,1),(1,-1),(-1,1),(-1,-1),
 ];
+pub(crate) const ROOK_DIRECTIONS: [(i32,i32);4] = [(1,0),(-1,0),(0,1),(0,-1)];
+pub(crate) const BISHOP_DIRECTIONS: [(i32,i32);4] = [(1,1),(1,-1),(-1,1),(-1,-1)];
 
-#[inline]
-fn file_mask(square: BitBoard) -> BitBoard {
-    let file = square.0.trailing_zeros() & 7;
-    BitBoard(A_FILE << file)
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
 }
 
-#[inline]
-fn rank_mask(square: BitBoard) -> BitBoard {
-    let sq = square.0.trailing_zeros();
-    let rank = sq >> 3;
-    BitBoard(RANK << (rank * 8))
+struct MagicTable {
+    magics: Vec<Magic>,
+    attacks: Vec<BitBoard>,
 }
 
-#[inline]
-fn diag_mask(square: BitBoard) -> BitBoard {
-    let sq = square.0.trailing_zeros() as i32;
-    let rank = sq >> 3;
-    let file = sq & 7;
+impl MagicTable {
+    /// Searches a magic number for every square. The search is seeded, so the
+    /// tables are identical from one run to the next.
+    fn new(directions: &[(i32, i32)], seed: u64) -> MagicTable {
+        let mut rng = seed;
+        let mut magics = Vec::with_capacity(64);
+        let mut attacks = Vec::new();
 
-    let mut mask = 0u64;
+        for sq in 0..64 {
+            let mask = relevant_blockers(sq, directions);
+            let bits = mask.count_ones();
+            let shift = 64 - bits;
 
-    for r in 0..8 {
-        let f = r + file - rank;
-        if f >= 0 && f < 8 {
-            mask |= 1u64 << (r * 8 + f);
+            // Enumerate every blocker subset of the mask (Carry-Rippler trick)
+            let mut occupancies = Vec::with_capacity(1 << bits);
+            let mut references = Vec::with_capacity(1 << bits);
+            let mut occ = 0u64;
+            loop {
+                occupancies.push(occ);
+                references.push(ray_attacks(sq, occ, directions));
+                occ = occ.wrapping_sub(mask) & mask;
+                if occ == 0 {
+                    break;
+                }
+            }
+
+            let mut table = vec![None; 1 << bits];
+            let magic = loop {
+                // Sparse candidates are far more likely to be magic
+                let candidate = next_random(&mut rng) & next_random(&mut rng) & next_random(&mut rng);
+                if (mask.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+                    continue;
+                }
+
+                table.fill(None);
+                let collision_free = occupancies.iter().zip(&references).all(|(&occ, &reference)| {
+                    let index = (occ.wrapping_mul(candidate) >> shift) as usize;
+                    match table[index] {
+                        None => {
+                            table[index] = Some(reference);
+                            true
+                        }
+                        Some(existing) => existing == reference,
+                    }
+                });
+                if collision_free {
+                    break candidate;
+                }
+            };
+
+            magics.push(Magic { mask, magic, shift, offset: attacks.len() });
+            attacks.extend(table.into_iter().map(|a| BitBoard(a.unwrap_or(0))));
         }
+
+        MagicTable { magics, attacks }
     }
 
-    BitBoard(mask)
+    #[inline]
+    fn attacks(&self, sq: usize, occ: BitBoard) -> BitBoard {
+        let m = &self.magics[sq];
+        let index = ((occ.0 & m.mask).wrapping_mul(m.magic) >> m.shift) as usize;
+        self.attacks[m.offset + index]
+    }
 }
 
-// look into not computing that but using masks like for the rooks
-#[inline]
-fn anti_diag_mask(square: BitBoard) -> BitBoard {
-    let sq = square.0.trailing_zeros() as i32;
-    let rank = sq >> 3;
-    let file = sq & 7;
+/// xorshift64*, only used to search for magic numbers.
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545F4914F6CDD1D)
+}
 
-    let mut mask = 0u64;
+/// Attacks from `sq` along `directions`, stopping on (and including) the first blocker.
+fn ray_attacks(sq: usize, occ: u64, directions: &[(i32, i32)]) -> u64 {
+    let rank = (sq / 8) as i32;
+    let file = (sq % 8) as i32;
+    let mut attacks = 0u64;
 
-    for r in 0..8 {
-        let f = rank + file - r;
-        if f >= 0 && f < 8 {
-            mask |= 1u64 << (r * 8 + f);
+    for (dr, df) in directions {
+        let (mut r, mut f) = (rank + dr, file + df);
+        while (0..8).contains(&r) && (0..8).contains(&f) {
+            let bit = 1u64 << (r * 8 + f);
+            attacks |= bit;
+            if occ & bit != 0 {
+                break;
+            }
+            r += dr;
+            f += df;
         }
     }
-
-    BitBoard(mask)
+    attacks
 }
 
-#[inline]
-fn sliding_attacks(occ: BitBoard, sq: BitBoard, mask: BitBoard) -> BitBoard {
-    let forward = occ & mask;
-    let reverse = forward.reverse_bits();
+/// Squares whose occupancy matters for a slider on `sq`: its rays minus the
+/// board edge, since a piece on the last square of a ray never blocks anything.
+fn relevant_blockers(sq: usize, directions: &[(i32, i32)]) -> u64 {
+    let rank = (sq / 8) as i32;
+    let file = (sq % 8) as i32;
+    let mut mask = 0u64;
 
-    let sq_rev = sq.reverse_bits();
+    for (dr, df) in directions {
+        let (mut r, mut f) = (rank + dr, file + df);
+        while (0..8).contains(&(r + dr)) && (0..8).contains(&(f + df)) {
+            mask |= 1u64 << (r * 8 + f);
+            r += dr;
+            f += df;
+        }
+    }
+    mask
+}
 
-    let left = forward.wrapping_sub(sq << 1);
-    let right = reverse.wrapping_sub(sq_rev << 1);
+static ROOK_MAGICS: LazyLock<MagicTable> = LazyLock::new(|| {
+    MagicTable::new(&ROOK_DIRECTIONS, 0x9E3779B97F4A7C15)
+});
 
-    (left ^ right.reverse_bits()) & mask
-}
+static BISHOP_MAGICS: LazyLock<MagicTable> = LazyLock::new(|| {
+    MagicTable::new(&BISHOP_DIRECTIONS, 0xD1B54A32D192ED03)
+});
 
 static KNIGHT_ATTACKS: LazyLock<[BitBoard; 64]> = LazyLock::new(|| {
     generate_table(&KNIGHT_MOVES)
@@ -136,14 +210,12 @@ static PAWN_ATTACKS: LazyLock<[[BitBoard; 64]; 2]> = LazyLock::new(|| {
 
 #[inline]
 pub fn rook_attacks(square: BitBoard, occ: BitBoard) -> BitBoard {
-    sliding_attacks(occ, square, rank_mask(square))
-        | sliding_attacks(occ, square, file_mask(square))
+    ROOK_MAGICS.attacks(square.0.trailing_zeros() as usize, occ)
 }
 
 #[inline]
 pub fn bishop_attacks(square: BitBoard, occ: BitBoard) -> BitBoard {
-    sliding_attacks(occ, square, diag_mask(square))
-        | sliding_attacks(occ, square, anti_diag_mask(square))
+    BISHOP_MAGICS.attacks(square.0.trailing_zeros() as usize, occ)
 }
 
 #[inline]
@@ -172,13 +244,6 @@ const RANK_2: u64 = 0x000000000000FF00; // bits  8-15 (white pawn starting rank)
 const RANK_7: u64 = 0x00FF000000000000; // bits 48-55 (black pawn starting rank)
 const RANK_8: u64 = 0xFF00000000000000; // bits 56-63 (white promotes here)
 
-const PROMO_PIECES: [PieceType; 4] = [
-    PieceType::Queen,
-    PieceType::Rook,
-    PieceType::Bishop,
-    PieceType::Knight,
-];
-
 /// Returns true if `sq` is attacked by any piece of `by_color`.
 pub fn is_attacked(sq: u8, by_color: PieceColor, state: &GameState) -> bool {
     let sq_bb = BitBoard::from_index(sq);
@@ -186,28 +251,28 @@ pub fn is_attacked(sq: u8, by_color: PieceColor, state: &GameState) -> bool {
     let opp = by_color.opponent();
 
     // Pawn: a pawn of `by_color` attacks `sq` iff a pawn of `opp` on `sq` would attack a `by_color` pawn
-    let enemy_pawns = *state.pieces.get(&(PieceType::Pawn, by_color)).unwrap_or(&BitBoard(0));
+    let enemy_pawns = state.piece_bb(PieceType::Pawn, by_color);
     if pawn_attacks(sq_bb, opp) & enemy_pawns != BitBoard(0) {
         return true;
     }
 
-    let enemy_knights = *state.pieces.get(&(PieceType::Knight, by_color)).unwrap_or(&BitBoard(0));
+    let enemy_knights = state.piece_bb(PieceType::Knight, by_color);
     if knight_attacks(sq_bb) & enemy_knights != BitBoard(0) {
         return true;
     }
 
-    let enemy_king = *state.pieces.get(&(PieceType::King, by_color)).unwrap_or(&BitBoard(0));
+    let enemy_king = state.piece_bb(PieceType::King, by_color);
     if king_attacks(sq_bb) & enemy_king != BitBoard(0) {
         return true;
     }
 
-    let enemy_bishops = *state.pieces.get(&(PieceType::Bishop, by_color)).unwrap_or(&BitBoard(0));
-    let enemy_queens = *state.pieces.get(&(PieceType::Queen, by_color)).unwrap_or(&BitBoard(0));
+    let enemy_bishops = state.piece_bb(PieceType::Bishop, by_color);
+    let enemy_queens = state.piece_bb(PieceType::Queen, by_color);
     if bishop_attacks(sq_bb, occ) & (enemy_bishops | enemy_queens) != BitBoard(0) {
         return true;
     }
 
-    let enemy_rooks = *state.pieces.get(&(PieceType::Rook, by_color)).unwrap_or(&BitBoard(0));
+    let enemy_rooks = state.piece_bb(PieceType::Rook, by_color);
     if rook_attacks(sq_bb, occ) & (enemy_rooks | enemy_queens) != BitBoard(0) {
         return true;
     }
@@ -216,37 +281,42 @@ pub fn is_attacked(sq: u8, by_color: PieceColor, state: &GameState) -> bool {
 }
 
 /// Returns true if the king of `color` is currently in check.
+///
+/// Under atomic rules kings side by side are never in check, as taking
+/// either would blow up both, and a king already blown up counts as
+/// checked so that no move can leave its side without one.
 pub fn is_in_check(color: PieceColor, state: &GameState) -> bool {
-    let king_bb = *state.pieces.get(&(PieceType::King, color)).unwrap_or(&BitBoard(0));
+    let king_bb = state.piece_bb(PieceType::King, color);
     if king_bb == BitBoard(0) {
-        return false;
+        return state.rules == Rules::Atomic;
+    }
+    let king_sq = king_bb.0.trailing_zeros() as u8;
+    let them = color.opponent();
+    match state.rules {
+        Rules::Standard => is_attacked(king_sq, them, state),
+        Rules::Atomic => {
+            let enemy_king = state.piece_bb(PieceType::King, them);
+            king_attacks(king_bb) & enemy_king == BitBoard(0)
+                && attackers_to(state, king_sq, them, state.occupancy()) & !enemy_king != BitBoard(0)
+        }
     }
-    is_attacked(king_bb.0.trailing_zeros() as u8, color.opponent(), state)
 }
 
 /// Generates all pseudo-legal moves for the side to move.
-pub fn generate_pseudo_legal_moves(state: &GameState) -> Vec<Move> {
+pub fn generate_pseudo_legal_moves(state: &GameState) -> MoveList {
     let color = state.side_to_move;
     let occ = state.occupancy();
     let own = state.pieces(color);
     let enemy = state.pieces(color.opponent());
 
-    // Collect first to avoid holding a borrow on `state` while passing it to subfunctions
-    let piece_bbs: Vec<(PieceType, BitBoard)> = state
-        .pieces
-        .iter()
-        .filter(|((_, pc), _)| *pc == color)
-        .map(|((pt, _), bb)| (*pt, *bb))
-        .collect();
+    let mut moves = MoveList::new();
 
-    let mut moves = Vec::new();
-
-    for (pt, bb) in piece_bbs {
-        for from in bb.get_piece_positions() {
+    for pt in PieceType::ALL {
+        for from in state.piece_bb(pt, color) {
             let from_bb = BitBoard::from_index(from);
             match pt {
                 PieceType::Pawn => {
-                    gen_pawn_moves(from, from_bb, color, occ, enemy, state.en_passant, &mut moves);
+                    gen_pawn_moves(from, from_bb, color, occ, enemy, state, &mut moves);
                 }
                 PieceType::Knight => {
                     gen_leaper_moves(from, knight_attacks(from_bb) & !own, enemy, &mut moves);
@@ -271,17 +341,253 @@ pub fn generate_pseudo_legal_moves(state: &GameState) -> Vec<Move> {
     moves
 }
 
-/// Filters pseudo-legal moves to only those that don't leave the moving side's king in check.
-pub fn generate_legal_moves(state: &GameState) -> Vec<Move> {
-    let color = state.side_to_move;
-    generate_pseudo_legal_moves(state)
+/// Generates all legal moves for the side to move.
+///
+/// Checkers, pins and the squares the enemy attacks are worked out once up
+/// front, so no move has to be played out to be validated. The only move
+/// tested individually is en passant, which removes two pieces from one rank
+/// and can uncover a check no pin ray sees.
+pub fn generate_legal_moves(state: &GameState) -> MoveList {
+    if state.rules == Rules::Atomic {
+        return generate_atomic_moves(state);
+    }
+    let us = state.side_to_move;
+    let them = us.opponent();
+    let king = state.piece_bb(PieceType::King, us);
+    if king == BitBoard(0) {
+        // Nothing to keep out of check (e.g. a test position without a king)
+        return generate_pseudo_legal_moves(state);
+    }
+    let king_sq = king.0.trailing_zeros() as u8;
+
+    let occ = state.occupancy();
+    let own = state.pieces(us);
+    let enemy = state.pieces(them);
+    let mut moves = MoveList::new();
+
+    // The king itself is left out of the blockers, or it could step back
+    // along the ray of a slider checking it
+    let danger = attacked_squares(state, them, occ & !king);
+    gen_leaper_moves(king_sq, king_attacks(king) & !own & !danger, enemy, &mut moves);
+
+    let checkers = attackers_to(state, king_sq, them, occ);
+    if checkers.0.count_ones() > 1 {
+        // Double check: only the king can move
+        return moves;
+    }
+    // Squares that resolve the check: capturing the checker or blocking it
+    let evasion = match checkers.0.trailing_zeros() {
+        64 => BitBoard(!0),
+        checker => checkers | BETWEEN[king_sq as usize][checker as usize],
+    };
+    let pin_rays = pin_rays(state, king_sq, us, occ);
+
+    for pt in PieceType::ALL {
+        if pt == PieceType::King {
+            continue;
+        }
+        for from in state.piece_bb(pt, us) {
+            let from_bb = BitBoard::from_index(from);
+            let allowed = evasion & pin_rays[from as usize];
+            match pt {
+                PieceType::Pawn => {
+                    let start = moves.len();
+                    gen_pawn_moves(from, from_bb, us, occ, enemy, state, &mut moves);
+                    let mut i = start;
+                    while i < moves.len() {
+                        let mv = moves[i];
+                        let legal = if mv.flag == MoveFlag::EnPassant {
+                            en_passant_is_legal(state, mv, king_sq)
+                        } else {
+                            BitBoard::from_index(mv.to) & allowed != BitBoard(0)
+                        };
+                        if legal {
+                            i += 1;
+                        } else {
+                            moves.swap_remove(i);
+                        }
+                    }
+                }
+                PieceType::Knight => {
+                    gen_leaper_moves(from, knight_attacks(from_bb) & !own & allowed, enemy, &mut moves);
+                }
+                PieceType::Bishop => {
+                    gen_leaper_moves(from, bishop_attacks(from_bb, occ) & !own & allowed, enemy, &mut moves);
+                }
+                PieceType::Rook => {
+                    gen_leaper_moves(from, rook_attacks(from_bb, occ) & !own & allowed, enemy, &mut moves);
+                }
+                PieceType::Queen => {
+                    gen_leaper_moves(from, queen_attacks(from_bb, occ) & !own & allowed, enemy, &mut moves);
+                }
+                PieceType::King => {}
+            }
+        }
+    }
+
+    if checkers == BitBoard(0) {
+        gen_castling_moves(king_sq, us, occ, state, &mut moves);
+    }
+    moves
+}
+
+/// Legal moves under atomic rules. An explosion can take away pinned pieces
+/// and checkers alike, so every move is made on a scratch copy and the
+/// position after it inspected before unmaking it: the mover's king must
+/// survive, and be out of check unless the enemy king went up with it.
+fn generate_atomic_moves(state: &GameState) -> MoveList {
+    let us = state.side_to_move;
+    let them = us.opponent();
+    let mut moves = generate_pseudo_legal_moves(state);
+    let in_check = is_in_check(us, state);
+    let mut scratch = state.clone();
+    moves.retain(|&mv| {
+        let king_moves = state.piece_bb(PieceType::King, us) & BitBoard::from_index(mv.from);
+        if king_moves != BitBoard(0) && mv.flag == MoveFlag::Capture {
+            return false;
+        }
+        if in_check && matches!(mv.flag, MoveFlag::KingsideCastle | MoveFlag::QueensideCastle) {
+            return false;
+        }
+        let undo = scratch.make_move(mv);
+        let legal = scratch.piece_bb(PieceType::King, us) != BitBoard(0)
+            && (scratch.piece_bb(PieceType::King, them) == BitBoard(0)
+                || !is_in_check(us, &scratch));
+        scratch.unmake_move(mv, undo);
+        legal
+    });
+    moves
+}
+
+/// Squares strictly between two squares on a shared rank, file or diagonal;
+/// empty for any other pair.
+static BETWEEN: LazyLock<Box<[[BitBoard; 64]; 64]>> = LazyLock::new(|| {
+    let mut table = Box::new([[BitBoard(0); 64]; 64]);
+    for from in 0..64 {
+        for &(dr, df) in ROOK_DIRECTIONS.iter().chain(&BISHOP_DIRECTIONS) {
+            let (mut r, mut f) = ((from / 8) as i32 + dr, (from % 8) as i32 + df);
+            let mut between = 0u64;
+            while (0..8).contains(&r) && (0..8).contains(&f) {
+                let to = (r * 8 + f) as usize;
+                table[from][to] = BitBoard(between);
+                between |= 1u64 << to;
+                r += dr;
+                f += df;
+            }
+        }
+    }
+    table
+});
+
+/// Pieces of `by_color` attacking `sq`, with sliders blocked by `occ`.
+fn attackers_to(state: &GameState, sq: u8, by_color: PieceColor, occ: BitBoard) -> BitBoard {
+    let sq_bb = BitBoard::from_index(sq);
+    let queens = state.piece_bb(PieceType::Queen, by_color);
+    (pawn_attacks(sq_bb, by_color.opponent()) & state.piece_bb(PieceType::Pawn, by_color))
+        | (knight_attacks(sq_bb) & state.piece_bb(PieceType::Knight, by_color))
+        | (king_attacks(sq_bb) & state.piece_bb(PieceType::King, by_color))
+        | (bishop_attacks(sq_bb, occ) & (state.piece_bb(PieceType::Bishop, by_color) | queens))
+        | (rook_attacks(sq_bb, occ) & (state.piece_bb(PieceType::Rook, by_color) | queens))
+}
+
+/// Every square attacked by `by_color`, with sliders blocked by `occ`.
+fn attacked_squares(state: &GameState, by_color: PieceColor, occ: BitBoard) -> BitBoard {
+    let mut attacked = BitBoard(0);
+    for pt in PieceType::ALL {
+        for sq in state.piece_bb(pt, by_color) {
+            let sq_bb = BitBoard::from_index(sq);
+            attacked |= match pt {
+                PieceType::Pawn => pawn_attacks(sq_bb, by_color),
+                PieceType::Knight => knight_attacks(sq_bb),
+                PieceType::Bishop => bishop_attacks(sq_bb, occ),
+                PieceType::Rook => rook_attacks(sq_bb, occ),
+                PieceType::Queen => queen_attacks(sq_bb, occ),
+                PieceType::King => king_attacks(sq_bb),
+            };
+        }
+    }
+    attacked
+}
+
+/// For every square, where a piece standing on it may move without exposing
+/// the king: everywhere for unpinned pieces, along the pin ray (up to and
+/// including the pinner) for pinned ones.
+fn pin_rays(state: &GameState, king_sq: u8, us: PieceColor, occ: BitBoard) -> [BitBoard; 64] {
+    let mut rays = [BitBoard(!0); 64];
+    let them = us.opponent();
+    let king = BitBoard::from_index(king_sq);
+    let own = state.pieces(us);
+    let queens = state.piece_bb(PieceType::Queen, them);
+
+    // Enemy sliders that would attack the king if our pieces were see-through
+    let enemy_only = occ & !own;
+    let snipers = (bishop_attacks(king, enemy_only) & (state.piece_bb(PieceType::Bishop, them) | queens))
+        | (rook_attacks(king, enemy_only) & (state.piece_bb(PieceType::Rook, them) | queens));
+
+    for sniper in snipers {
+        let between = BETWEEN[king_sq as usize][sniper as usize];
+        let blockers = between & occ;
+        if blockers.0.count_ones() == 1 && blockers & own != BitBoard(0) {
+            rays[blockers.0.trailing_zeros() as usize] = between | BitBoard::from_index(sniper);
+        }
+    }
+    rays
+}
+
+/// En passant empties two squares of one rank at once, so it is checked by
+/// looking for attackers of the king on the board as it would be after it.
+fn en_passant_is_legal(state: &GameState, mv: Move, king_sq: u8) -> bool {
+    let them = state.side_to_move.opponent();
+    let captured = match state.side_to_move {
+        PieceColor::White => mv.to - 8,
+        PieceColor::Black => mv.to + 8,
+    };
+    let occ = (state.occupancy() & !BitBoard::from_index(mv.from) & !BitBoard::from_index(captured))
+        | BitBoard::from_index(mv.to);
+    attackers_to(state, king_sq, them, occ) & !BitBoard::from_index(captured) == BitBoard(0)
+}
+
+/// Counts the leaf nodes of the legal move tree `depth` plies deep.
+pub fn perft(state: &GameState, depth: u32) -> u64 {
+    perft_in_place(&mut state.clone(), depth)
+}
+
+/// Like `perft`, but broken down by root move, for diffing against a reference engine.
+pub fn perft_divide(state: &GameState, depth: u32) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let mut scratch = state.clone();
+    generate_legal_moves(state)
         .into_iter()
-        .filter(|&mv| !is_in_check(color, &state.apply_move(mv)))
+        .map(|mv| {
+            let undo = scratch.make_move(mv);
+            let nodes = perft_in_place(&mut scratch, depth - 1);
+            scratch.unmake_move(mv, undo);
+            (mv, nodes)
+        })
         .collect()
 }
 
-fn gen_leaper_moves(from: u8, targets: BitBoard, enemy: BitBoard, moves: &mut Vec<Move>) {
-    for to in targets.get_piece_positions() {
+fn perft_in_place(state: &mut GameState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = generate_legal_moves(state);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for mv in moves {
+        let undo = state.make_move(mv);
+        nodes += perft_in_place(state, depth - 1);
+        state.unmake_move(mv, undo);
+    }
+    nodes
+}
+
+fn gen_leaper_moves(from: u8, targets: BitBoard, enemy: BitBoard, moves: &mut MoveList) {
+    for to in targets {
         let flag = if BitBoard::from_index(to) & enemy != BitBoard(0) {
             MoveFlag::Capture
         } else {
@@ -297,8 +603,8 @@ fn gen_pawn_moves(
     color: PieceColor,
     occ: BitBoard,
     enemy: BitBoard,
-    en_passant: Option<u8>,
-    moves: &mut Vec<Move>,
+    state: &GameState,
+    moves: &mut MoveList,
 ) {
     let (push_shift, promo_rank, start_rank, nw_mask, ne_mask): (
         fn(BitBoard) -> BitBoard,
@@ -328,9 +634,9 @@ fn gen_pawn_moves(
 
     // Single push
     let push1 = push_shift(from_bb) & !occ;
-    for to in push1.get_piece_positions() {
+    for to in push1 {
         if BitBoard::from_index(to) & promo != BitBoard(0) {
-            for pt in PROMO_PIECES {
+            for pt in state.promotions.iter() {
                 moves.push(Move { from, to, flag: MoveFlag::Promotion(pt) });
             }
         } else {
@@ -340,7 +646,7 @@ fn gen_pawn_moves(
 
     // Double push from starting rank
     if from_bb & start != BitBoard(0) {
-        for to in (push_shift(push1) & !occ).get_piece_positions() {
+        for to in push_shift(push1) & !occ {
             moves.push(Move { from, to, flag: MoveFlag::DoublePawnPush });
         }
     }
@@ -358,9 +664,9 @@ fn gen_pawn_moves(
             (from_bb >> 9) & ne_mask & enemy,
         ),
     };
-    for to in (left_cap | right_cap).get_piece_positions() {
+    for to in left_cap | right_cap {
         if BitBoard::from_index(to) & promo != BitBoard(0) {
-            for pt in PROMO_PIECES {
+            for pt in state.promotions.iter() {
                 moves.push(Move { from, to, flag: MoveFlag::PromotionCapture(pt) });
             }
         } else {
@@ -369,7 +675,7 @@ fn gen_pawn_moves(
     }
 
     // En passant
-    if let Some(ep_sq) = en_passant {
+    if let Some(ep_sq) = state.en_passant {
         let ep_bb = BitBoard::from_index(ep_sq);
         let ep_attacks = match color {
             PieceColor::White => ((from_bb << 7) & nw_mask) | ((from_bb << 9) & ne_mask),
@@ -381,54 +687,352 @@ fn gen_pawn_moves(
     }
 }
 
+/// Castling works the same in standard chess and Chess960: the king ends on
+/// the g- or c-file with the rook beside it, every square either of them
+/// crosses must be empty but for the two of them, and none of the squares the
+/// king stands on or crosses may be attacked.
 fn gen_castling_moves(
     king_sq: u8,
     color: PieceColor,
     occ: BitBoard,
     state: &GameState,
-    moves: &mut Vec<Move>,
+    moves: &mut MoveList,
 ) {
+    let rank = back_rank(color) * 8;
+    if king_sq / 8 != rank / 8 {
+        return;
+    }
     let enemy = color.opponent();
-    match color {
-        PieceColor::White => {
-            // Kingside: f1 (sq 5) and g1 (sq 6) must be empty; e1, f1, g1 not attacked
-            if state.castling_rights.kingside(PieceColor::White)
-                && occ & BitBoard(0x0000000000000060) == BitBoard(0)
-                && !is_attacked(4, enemy, state)
-                && !is_attacked(5, enemy, state)
-                && !is_attacked(6, enemy, state)
-            {
-                moves.push(Move { from: king_sq, to: 6, flag: MoveFlag::KingsideCastle });
-            }
-            // Queenside: b1 (sq 1), c1 (sq 2), d1 (sq 3) empty; e1, d1, c1 not attacked
-            if state.castling_rights.queenside(PieceColor::White)
-                && occ & BitBoard(0x000000000000000E) == BitBoard(0)
-                && !is_attacked(4, enemy, state)
-                && !is_attacked(3, enemy, state)
-                && !is_attacked(2, enemy, state)
-            {
-                moves.push(Move { from: king_sq, to: 2, flag: MoveFlag::QueensideCastle });
+    // An atomic king can't capture, so it guards nothing
+    let harmless = match state.rules {
+        Rules::Standard => BitBoard(0),
+        Rules::Atomic => state.piece_bb(PieceType::King, enemy),
+    };
+    for (flag, (king_file, _)) in [
+        (MoveFlag::KingsideCastle, KINGSIDE_CASTLE_FILES),
+        (MoveFlag::QueensideCastle, QUEENSIDE_CASTLE_FILES),
+    ] {
+        let Some((rook_from, rook_to)) = state.castling_rook_squares(flag, color) else {
+            continue;
+        };
+        if state.piece_at(rook_from) != Some((PieceType::Rook, color)) {
+            continue;
+        }
+        let king_to = rank + king_file;
+        let king_path = BETWEEN[king_sq as usize][king_to as usize] | BitBoard::from_index(king_to);
+        let rook_path = BETWEEN[rook_from as usize][rook_to as usize] | BitBoard::from_index(rook_to);
+        let movers = BitBoard::from_index(king_sq) | BitBoard::from_index(rook_from);
+        if (king_path | rook_path) & occ & !movers != BitBoard(0) {
+            continue;
+        }
+        // Both movers are see-through: in Chess960 the rook can be what
+        // shields the king's destination from a slider further along the rank
+        let occ = occ & !movers;
+        let safe = (king_path | BitBoard::from_index(king_sq))
+            .into_iter()
+            .all(|sq| attackers_to(state, sq, enemy, occ) & !harmless == BitBoard(0));
+        if safe {
+            moves.push(Move { from: king_sq, to: king_to, flag });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    // The Hyperbola Quintessence implementation the magic tables replaced,
+    // kept as an independent reference for them.
+    fn hq_attacks(occ: u64, sq: u64, mask: u64) -> u64 {
+        let forward = occ & mask;
+        let reverse = forward.reverse_bits();
+        let left = forward.wrapping_sub(sq << 1);
+        let right = reverse.wrapping_sub(sq.reverse_bits() << 1);
+        (left ^ right.reverse_bits()) & mask
+    }
+
+    fn line_mask(sq: i32, dr: i32, df: i32) -> u64 {
+        let (rank, file) = (sq >> 3, sq & 7);
+        let mut mask = 0u64;
+        for step in -7..=7 {
+            let (r, f) = (rank + step * dr, file + step * df);
+            if (0..8).contains(&r) && (0..8).contains(&f) {
+                mask |= 1u64 << (r * 8 + f);
             }
         }
-        PieceColor::Black => {
-            // Kingside: f8 (sq 61) and g8 (sq 62) empty; e8, f8, g8 not attacked
-            if state.castling_rights.kingside(PieceColor::Black)
-                && occ & BitBoard(0x6000000000000000) == BitBoard(0)
-                && !is_attacked(60, enemy, state)
-                && !is_attacked(61, enemy, state)
-                && !is_attacked(62, enemy, state)
-            {
-                moves.push(Move { from: king_sq, to: 62, flag: MoveFlag::KingsideCastle });
+        mask
+    }
+
+    #[test]
+    fn magic_attacks_match_hyperbola_quintessence() {
+        let mut rng = 0x0123456789ABCDEFu64;
+        for sq in 0..64 {
+            let bit = 1u64 << sq;
+            for _ in 0..200 {
+                let occ = next_random(&mut rng) & next_random(&mut rng);
+                let rook = hq_attacks(occ, bit, line_mask(sq, 0, 1))
+                    | hq_attacks(occ, bit, line_mask(sq, 1, 0));
+                let bishop = hq_attacks(occ, bit, line_mask(sq, 1, 1))
+                    | hq_attacks(occ, bit, line_mask(sq, 1, -1));
+                assert_eq!(rook_attacks(BitBoard(bit), BitBoard(occ)), BitBoard(rook));
+                assert_eq!(bishop_attacks(BitBoard(bit), BitBoard(occ)), BitBoard(bishop));
+                assert_eq!(
+                    queen_attacks(BitBoard(bit), BitBoard(occ)),
+                    BitBoard(rook | bishop)
+                );
             }
-            // Queenside: b8 (sq 57), c8 (sq 58), d8 (sq 59) empty; e8, d8, c8 not attacked
-            if state.castling_rights.queenside(PieceColor::Black)
-                && occ & BitBoard(0x0E00000000000000) == BitBoard(0)
-                && !is_attacked(60, enemy, state)
-                && !is_attacked(59, enemy, state)
-                && !is_attacked(58, enemy, state)
-            {
-                moves.push(Move { from: king_sq, to: 58, flag: MoveFlag::QueensideCastle });
+        }
+    }
+
+    // Reference counts from https://www.chessprogramming.org/Perft_Results
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    /// The legality test `generate_legal_moves` used before pins and check
+    /// masks: play every pseudo-legal move and see whether the king hangs.
+    fn filtered_legal_moves(state: &GameState) -> MoveList {
+        let color = state.side_to_move;
+        let mut scratch = state.clone();
+        let mut moves = generate_pseudo_legal_moves(state);
+        moves.retain(|&mv| {
+            let undo = scratch.make_move(mv);
+            let legal = !is_in_check(color, &scratch);
+            scratch.unmake_move(mv, undo);
+            legal
+        });
+        moves
+    }
+
+    fn sorted(moves: MoveList) -> Vec<String> {
+        let mut keys: Vec<String> = moves.iter().map(|mv| format!("{mv:?}")).collect();
+        keys.sort();
+        keys
+    }
+
+    /// Walks the move tree comparing both generators at every node.
+    fn assert_same_moves(state: &mut GameState, depth: u32) {
+        let moves = generate_legal_moves(state);
+        assert_eq!(
+            sorted(moves),
+            sorted(filtered_legal_moves(state)),
+            "{}",
+            state.to_fen()
+        );
+        if depth > 1 {
+            for mv in moves {
+                let undo = state.make_move(mv);
+                assert_same_moves(state, depth - 1);
+                state.unmake_move(mv, undo);
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn legal_generator_matches_filtering() {
+        for fen in [START_FEN, KIWIPETE, POSITION_3, POSITION_4, POSITION_5, POSITION_6] {
+            assert_same_moves(&mut GameState::from_fen(fen).unwrap(), 3);
+        }
+        for fen in [
+            // En passant would uncover a rook on the rank, or a bishop on the diagonal
+            "8/8/8/KPp4r/8/8/8/7k w - c6 0 1",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            // En passant captures the checking pawn
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            // Pinned pieces, double check, and a king in check walking along the ray
+            "4k3/4r3/8/8/4B3/8/4K3/8 w - - 0 1",
+            "4k3/8/8/8/1b6/8/3N4/r3K3 w - - 0 1",
+            "8/8/8/8/8/8/r3K3/8 w - - 0 1",
+        ] {
+            assert_same_moves(&mut GameState::from_fen(fen).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn en_passant_cannot_uncover_check() {
+        let state = GameState::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").unwrap();
+        assert!(
+            generate_legal_moves(&state)
+                .iter()
+                .all(|mv| mv.flag != MoveFlag::EnPassant)
+        );
+    }
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let state = GameState::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&state, depth as u32 + 1), nodes, "{fen} at depth {}", depth + 1);
+        }
+    }
+
+    #[test]
+    fn perft_start_position() {
+        assert_perft(START_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn perft_position_3() {
+        assert_perft(POSITION_3, &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn perft_position_4() {
+        assert_perft(POSITION_4, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn perft_position_5() {
+        assert_perft(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn perft_position_6() {
+        assert_perft(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    // Small positions isolating the rules move generators most often get wrong
+
+    #[test]
+    fn perft_en_passant_discovered_check() {
+        assert_perft("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", &[15, 126, 1928]);
+        assert_perft("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", &[18, 92, 1670]);
+    }
+
+    #[test]
+    fn perft_castling() {
+        assert_perft("5k2/8/8/8/8/8/8/4K2R w K - 0 1", &[15, 66, 1198]);
+        assert_perft("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", &[16, 71, 1286]);
+        assert_perft("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", &[26, 1141]);
+        assert_perft("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", &[44, 1494]);
+    }
+
+    #[test]
+    fn perft_chess960() {
+        assert_perft(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12189, 326672],
+        );
+        assert_perft(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            &[21, 807, 18002, 667366],
+        );
+        assert_perft(
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            &[20, 479, 10471, 273318],
+        );
+        assert_perft(
+            "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+            &[22, 593, 13440, 382958],
+        );
+    }
+
+    #[test]
+    fn chess960_castling_moves_the_rook_it_was_recorded_with() {
+        // King on b1 castling queenside to c1 over its rook on a1, and
+        // kingside with the king and rook swapping places around f1/g1
+        let mut state = GameState::from_fen("4k3/8/8/8/8/8/8/RK4R1 w GA - 0 1").unwrap();
+        let moves = generate_legal_moves(&state);
+        for (flag, fen) in [
+            (MoveFlag::QueensideCastle, "4k3/8/8/8/8/8/8/2KR2R1 b - - 1 1"),
+            (MoveFlag::KingsideCastle, "4k3/8/8/8/8/8/8/R4RK1 b - - 1 1"),
+        ] {
+            let mv = *moves.iter().find(|mv| mv.flag == flag).unwrap();
+            let before = state.clone();
+            let undo = state.make_move(mv);
+            assert_eq!(state.to_fen(), fen);
+            assert_eq!(state.hash, state.compute_hash());
+            state.unmake_move(mv, undo);
+            assert_eq!(state.to_fen(), before.to_fen());
+            assert_eq!(state.hash, before.hash);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_rook_can_shield_the_king() {
+        // Castling queenside would move the b1 rook out of the a1 queen's way
+        let state = GameState::from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+        assert!(generate_legal_moves(&state).iter().all(|mv| mv.flag != MoveFlag::QueensideCastle));
+        // With the queen gone it is legal, the king staying put on c1
+        let state = GameState::from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").unwrap();
+        let castle = generate_legal_moves(&state).into_iter().find(|mv| mv.flag == MoveFlag::QueensideCastle);
+        assert_eq!(castle.map(|mv| (mv.from, mv.to)), Some((2, 2)));
+    }
+
+    #[test]
+    fn perft_promotion() {
+        assert_perft("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", &[11, 133, 1442]);
+        assert_perft("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", &[9, 40, 472]);
+        assert_perft("8/P1k5/K7/8/8/8/8/8 w - - 0 1", &[6, 27, 273]);
+    }
+
+    fn atomic(fen: &str) -> GameState {
+        let mut state = GameState::from_fen(fen).unwrap();
+        state.set_rules(Rules::Atomic);
+        state
+    }
+
+    #[test]
+    fn perft_atomic() {
+        let state = atomic(START_FEN);
+        for (depth, nodes) in [20, 400, 8902, 197326].into_iter().enumerate() {
+            assert_eq!(perft(&state, depth as u32 + 1), nodes, "depth {}", depth + 1);
+        }
+    }
+
+    #[test]
+    fn atomic_kings_neither_capture_nor_check_each_other() {
+        // Only walking away from the rook: the king can't take it
+        let state = atomic("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1");
+        let moves = generate_legal_moves(&state);
+        assert_eq!(moves.len(), 2);
+        assert!(moves.iter().all(|mv| mv.flag == MoveFlag::Quiet));
+
+        // A rook on the rank would check a king standing anywhere but next
+        // to its own king
+        let fen = "8/8/8/8/8/8/R3k3/4K3 b - - 0 1";
+        assert!(!is_in_check(PieceColor::Black, &atomic(fen)));
+        assert!(is_in_check(PieceColor::Black, &GameState::from_fen(fen).unwrap()));
+    }
+
+    #[test]
+    fn atomic_castling_passes_the_enemy_king() {
+        // f1 and g1 are next to the black king, which can't take there
+        let fen = "8/8/8/8/8/8/6k1/4K2R w K - 0 1";
+        let castles = |state: &GameState| {
+            generate_legal_moves(state).iter().any(|mv| mv.flag == MoveFlag::KingsideCastle)
+        };
+        assert!(castles(&atomic(fen)));
+        assert!(!castles(&GameState::from_fen(fen).unwrap()));
+        // Five king moves, nine rook moves and the castle
+        assert_eq!(perft(&atomic(fen), 1), 15);
+    }
+
+    #[test]
+    fn atomic_captures_must_spare_the_own_king() {
+        // Rxd2 would blow up the white king next to d2, leaving Kf1
+        let state = atomic("4k3/8/8/8/8/8/3q4/3RK3 w - - 0 1");
+        let moves: Vec<(u8, u8)> = generate_legal_moves(&state).iter().map(|mv| (mv.from, mv.to)).collect();
+        assert_eq!(moves, [(4, 5)]);
+
+        // Blowing up the enemy king is fine even when in check
+        let state = atomic("3rk3/4q3/8/8/8/8/8/4RK1r w - - 0 1");
+        assert!(generate_legal_moves(&state).contains(&Move { from: 4, to: 52, flag: MoveFlag::Capture }));
+    }
+
+    #[test]
+    fn perft_divide_sums_to_perft() {
+        let state = GameState::from_fen(KIWIPETE).unwrap();
+        let divide = perft_divide(&state, 2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), perft(&state, 2));
+    }
+}
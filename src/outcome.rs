@@ -0,0 +1,262 @@
+use crate::{
+    bitboard::BitBoard,
+    game::{GameState, Rules},
+    movegen::{generate_legal_moves, is_in_check},
+    rendering::{PieceColor, PieceType},
+};
+
+const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOutcome {
+    Checkmate {
+        winner: PieceColor,
+    },
+    /// Won by one of a variant's own rules rather than by checkmate.
+    VariantWin {
+        winner: PieceColor,
+    },
+    Draw(DrawReason),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    InsufficientMaterial,
+    /// 50 moves by each side without a capture or pawn move. Must be claimed.
+    FiftyMoveRule,
+    /// 75 moves by each side without a capture or pawn move.
+    SeventyFiveMoveRule,
+    /// Same position three times. Must be claimed.
+    ThreefoldRepetition,
+    /// Same position five times.
+    FivefoldRepetition,
+}
+
+impl DrawReason {
+    /// Whether the draw ends the game on its own, as opposed to needing a player to claim it.
+    pub fn is_automatic(self) -> bool {
+        !matches!(
+            self,
+            DrawReason::FiftyMoveRule | DrawReason::ThreefoldRepetition
+        )
+    }
+}
+
+impl GameOutcome {
+    pub fn winner(self) -> Option<PieceColor> {
+        match self {
+            GameOutcome::Checkmate { winner } | GameOutcome::VariantWin { winner } => Some(winner),
+            GameOutcome::Draw(_) => None,
+        }
+    }
+}
+
+/// Returns how the game ended, or `None` if it goes on.
+///
+/// `history` holds the hashes of every earlier position of the game, oldest
+/// first, and is used for repetitions. Only draws that end the game by
+/// themselves are reported, see `claimable_draw` for the others.
+pub fn game_outcome(state: &GameState, history: &[u64]) -> Option<GameOutcome> {
+    if state.rules == Rules::Atomic {
+        // Whoever blew up the other king has won
+        for color in PieceColor::ALL {
+            if state.piece_bb(PieceType::King, color) == BitBoard(0) {
+                return Some(GameOutcome::VariantWin {
+                    winner: color.opponent(),
+                });
+            }
+        }
+    }
+    if generate_legal_moves(state).is_empty() {
+        return Some(if is_in_check(state.side_to_move, state) {
+            GameOutcome::Checkmate {
+                winner: state.side_to_move.opponent(),
+            }
+        } else {
+            GameOutcome::Draw(DrawReason::Stalemate)
+        });
+    }
+    if is_insufficient_material(state) {
+        return Some(GameOutcome::Draw(DrawReason::InsufficientMaterial));
+    }
+    if repetitions(state, history) >= 5 {
+        return Some(GameOutcome::Draw(DrawReason::FivefoldRepetition));
+    }
+    if state.halfmove_clock >= 150 {
+        return Some(GameOutcome::Draw(DrawReason::SeventyFiveMoveRule));
+    }
+    None
+}
+
+/// Returns a draw the side to move may claim, if any.
+pub fn claimable_draw(state: &GameState, history: &[u64]) -> Option<DrawReason> {
+    if repetitions(state, history) >= 3 {
+        Some(DrawReason::ThreefoldRepetition)
+    } else if state.halfmove_clock >= 100 {
+        Some(DrawReason::FiftyMoveRule)
+    } else {
+        None
+    }
+}
+
+/// How many times the current position has occurred, itself included.
+pub fn repetitions(state: &GameState, history: &[u64]) -> usize {
+    // Nothing before the last capture or pawn move can repeat
+    let reversible = (state.halfmove_clock as usize).min(history.len());
+    1 + history[history.len() - reversible..]
+        .iter()
+        .filter(|&&hash| hash == state.hash)
+        .count()
+}
+
+/// True when neither side can possibly checkmate: bare kings, a single minor
+/// piece, or only bishops that all stand on the same square color.
+pub fn is_insufficient_material(state: &GameState) -> bool {
+    let bb = |pt| state.piece_bb(pt, PieceColor::White) | state.piece_bb(pt, PieceColor::Black);
+    if bb(PieceType::Pawn) | bb(PieceType::Rook) | bb(PieceType::Queen) != BitBoard(0) {
+        return false;
+    }
+
+    let knights = bb(PieceType::Knight);
+    let bishops = bb(PieceType::Bishop);
+    let minors = (knights | bishops).0.count_ones();
+    if minors <= 1 {
+        return true;
+    }
+
+    knights == BitBoard(0) && (bishops.0 & DARK_SQUARES == 0 || bishops.0 & !DARK_SQUARES == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Move, MoveFlag};
+
+    fn state(fen: &str) -> GameState {
+        GameState::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn checkmate_and_stalemate() {
+        let fools_mate = state("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(
+            game_outcome(&fools_mate, &[]),
+            Some(GameOutcome::Checkmate {
+                winner: PieceColor::Black
+            })
+        );
+        let stalemate = state("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(
+            game_outcome(&stalemate, &[]),
+            Some(GameOutcome::Draw(DrawReason::Stalemate))
+        );
+        assert_eq!(game_outcome(&GameState::default(), &[]), None);
+    }
+
+    #[test]
+    fn atomic_explosion_wins() {
+        let mut state = state("4k3/4q3/8/8/8/8/8/4RK2 w - - 0 1");
+        state.set_rules(Rules::Atomic);
+        assert_eq!(game_outcome(&state, &[]), None);
+        let state = state.apply_move(Move {
+            from: 4,
+            to: 52,
+            flag: MoveFlag::Capture,
+        });
+        assert_eq!(
+            game_outcome(&state, &[]),
+            Some(GameOutcome::VariantWin {
+                winner: PieceColor::White
+            })
+        );
+    }
+
+    #[test]
+    fn move_rules() {
+        let fen = |clock| format!("4k3/8/8/8/8/8/4P3/4K3 w - - {clock} 80");
+        assert_eq!(claimable_draw(&state(&fen(99)), &[]), None);
+        assert_eq!(
+            claimable_draw(&state(&fen(100)), &[]),
+            Some(DrawReason::FiftyMoveRule)
+        );
+        assert_eq!(game_outcome(&state(&fen(100)), &[]), None);
+        assert_eq!(
+            game_outcome(&state(&fen(150)), &[]),
+            Some(GameOutcome::Draw(DrawReason::SeventyFiveMoveRule))
+        );
+        // Checkmate delivered on the 75th move still counts
+        let mate = state("7k/6Q1/6K1/8/8/8/8/8 b - - 150 120");
+        assert_eq!(
+            game_outcome(&mate, &[]).and_then(GameOutcome::winner),
+            Some(PieceColor::White)
+        );
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+        ] {
+            assert!(is_insufficient_material(&state(fen)), "{fen}");
+        }
+        for fen in [
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/3BK3 w - - 0 1",
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+        ] {
+            assert!(!is_insufficient_material(&state(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn repetition_after_a_double_push() {
+        // 1. e4 leaves an en passant square no black pawn can use, so the
+        // position after it is the one reached again by the knight shuffles
+        let mut position = GameState::default();
+        let mut history = Vec::new();
+        let moves = [(12, 28, MoveFlag::DoublePawnPush)].into_iter().chain(
+            [(62, 45), (6, 21), (45, 62), (21, 6)]
+                .repeat(2)
+                .into_iter()
+                .map(|(from, to)| (from, to, MoveFlag::Quiet)),
+        );
+        for (from, to, flag) in moves {
+            history.push(position.hash);
+            position = position.apply_move(Move { from, to, flag });
+        }
+        assert_eq!(repetitions(&position, &history), 3);
+        assert_eq!(
+            claimable_draw(&position, &history),
+            Some(DrawReason::ThreefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn repetition() {
+        let shuffle = [(6, 21), (62, 45), (21, 6), (45, 62)];
+        let mut position = GameState::default();
+        let mut history = Vec::new();
+        for (i, &(from, to)) in shuffle.iter().cycle().take(16).enumerate() {
+            history.push(position.hash);
+            position = position.apply_move(Move {
+                from,
+                to,
+                flag: MoveFlag::Quiet,
+            });
+            match i {
+                7 => assert_eq!(
+                    claimable_draw(&position, &history),
+                    Some(DrawReason::ThreefoldRepetition)
+                ),
+                15 => assert_eq!(
+                    game_outcome(&position, &history),
+                    Some(GameOutcome::Draw(DrawReason::FivefoldRepetition))
+                ),
+                _ => assert_eq!(game_outcome(&position, &history), None),
+            }
+        }
+    }
+}
@@ -0,0 +1,677 @@
+use std::fmt;
+
+use crate::{
+    fen::{FenError, START_FEN},
+    game::{GameState, Move},
+    rendering::PieceColor,
+    san::SanError,
+};
+
+/// Tags every PGN game must carry, in the order they must be written.
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+const LINE_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Ongoing,
+}
+
+impl GameResult {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Ongoing => "*",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<GameResult> {
+        match s {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Ongoing),
+            _ => None,
+        }
+    }
+}
+
+/// A move of the main line with its annotations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: Move,
+    /// Numeric Annotation Glyphs; `!`, `?` and friends are stored as their NAG (1 to 6).
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    /// Tags in file order. Reading does not require the seven tag roster,
+    /// writing fills in whatever is missing.
+    pub tags: Vec<(String, String)>,
+    pub initial_position: GameState,
+    /// Comment placed before the first move.
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedEnd,
+    UnterminatedComment,
+    InvalidTag,
+    UnmatchedParenthesis,
+    InvalidFen(FenError),
+    IllegalMove(SanError),
+}
+
+/// A PGN parsing error and where it happened (1-based).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            PgnErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{c}'"),
+            PgnErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            PgnErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnErrorKind::InvalidTag => write!(f, "invalid tag pair"),
+            PgnErrorKind::UnmatchedParenthesis => write!(f, "unmatched parenthesis"),
+            PgnErrorKind::InvalidFen(e) => write!(f, "{e}"),
+            PgnErrorKind::IllegalMove(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl PgnGame {
+    pub fn new(initial_position: GameState) -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            initial_position,
+            comment: None,
+            moves: Vec::new(),
+            result: GameResult::Ongoing,
+        }
+    }
+
+    /// Builds a game from a bare move history.
+    pub fn from_moves(initial_position: GameState, moves: &[Move], result: GameResult) -> PgnGame {
+        PgnGame {
+            moves: moves
+                .iter()
+                .map(|&mv| PgnMove {
+                    mv,
+                    nags: Vec::new(),
+                    comment: None,
+                })
+                .collect(),
+            result,
+            ..PgnGame::new(initial_position)
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Position reached after the last move.
+    pub fn final_position(&self) -> GameState {
+        self.moves
+            .iter()
+            .fold(self.initial_position.clone(), |state, m| {
+                state.apply_move(m.mv)
+            })
+    }
+
+    /// Parses a single game. Anything after the first game is ignored.
+    pub fn from_pgn(pgn: &str) -> Result<PgnGame, PgnError> {
+        let mut parser = Parser::new(pgn);
+        match parser.next_game()? {
+            Some(game) => Ok(game),
+            None => Err(parser.lexer.error(PgnErrorKind::UnexpectedEnd)),
+        }
+    }
+
+    /// Serializes the game, seven tag roster first.
+    pub fn to_pgn(&self) -> String {
+        let mut out = String::new();
+
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            out.push_str(&format!("[{name} \"{}\"]\n", escape(value)));
+        }
+        let fen = self.initial_position.to_fen();
+        let custom_start = fen != START_FEN;
+        for (name, value) in &self.tags {
+            let managed =
+                SEVEN_TAG_ROSTER.contains(&name.as_str()) || name == "SetUp" || name == "FEN";
+            if !managed {
+                out.push_str(&format!("[{name} \"{}\"]\n", escape(value)));
+            }
+        }
+        if custom_start {
+            out.push_str(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n"));
+        }
+        out.push('\n');
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(brace_comment(comment));
+        }
+        let mut state = self.initial_position.clone();
+        let mut needs_number = true;
+        for m in &self.moves {
+            if state.side_to_move == PieceColor::White {
+                tokens.push(format!("{}.", state.fullmove_number));
+            } else if needs_number {
+                tokens.push(format!("{}...", state.fullmove_number));
+            }
+            tokens.push(m.mv.to_san(&state));
+            tokens.extend(m.nags.iter().map(|nag| format!("${nag}")));
+            needs_number = false;
+            if let Some(comment) = &m.comment {
+                tokens.push(brace_comment(comment));
+                needs_number = true;
+            }
+            state = state.apply_move(m.mv);
+        }
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > LINE_WIDTH {
+                out.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                out.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            out.push_str(&token);
+        }
+        out.push('\n');
+        out
+    }
+}
+
+/// Parses every game of a PGN database.
+pub fn read_pgn_games(pgn: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut parser = Parser::new(pgn);
+    let mut games = Vec::new();
+    while let Some(game) = parser.next_game()? {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+/// Serializes several games, separated by blank lines.
+pub fn write_pgn_games(games: &[PgnGame]) -> String {
+    games
+        .iter()
+        .map(PgnGame::to_pgn)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A `{...}` comment. PGN has no escape for the closing brace, so any in
+/// the text are dropped rather than end the comment early.
+fn brace_comment(text: &str) -> String {
+    format!("{{{}}}", text.replace('}', ""))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// --- Lexer ---
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    MoveNumber,
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    Symbol(String),
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer {
+            chars: input.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn error(&self, kind: PgnErrorKind) -> PgnError {
+        PgnError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                // '%' in the first column escapes the whole line
+                '%' if self.column == 1 => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                c if c.is_whitespace() => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Returns the next token and the position it starts at.
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        self.skip_whitespace();
+        let (line, column) = (self.line, self.column);
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '[' => {
+                self.bump();
+                self.tag()?
+            }
+            '{' => {
+                self.bump();
+                let mut text = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(self.error(PgnErrorKind::UnterminatedComment)),
+                    }
+                }
+                // Line breaks inside comments are only formatting
+                Token::Comment(text.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            ';' => {
+                self.bump();
+                let mut text = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    text.push(c);
+                    self.bump();
+                }
+                Token::Comment(text.trim().to_string())
+            }
+            '(' => {
+                self.bump();
+                Token::VariationStart
+            }
+            ')' => {
+                self.bump();
+                Token::VariationEnd
+            }
+            '*' => {
+                self.bump();
+                Token::Result(GameResult::Ongoing)
+            }
+            '$' => {
+                self.bump();
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                match digits.parse() {
+                    Ok(nag) => Token::Nag(nag),
+                    Err(_) => return Err(self.error(PgnErrorKind::UnexpectedCharacter('$'))),
+                }
+            }
+            '!' | '?' => {
+                let glyph = self.take_while(|c| c == '!' || c == '?');
+                let nag = match glyph.as_str() {
+                    "!" => 1,
+                    "?" => 2,
+                    "!!" => 3,
+                    "??" => 4,
+                    "!?" => 5,
+                    "?!" => 6,
+                    _ => return Err(self.error(PgnErrorKind::UnexpectedCharacter(c))),
+                };
+                Token::Nag(nag)
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let symbol =
+                    self.take_while(|c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(c));
+                if let Some(result) = GameResult::parse(&symbol) {
+                    Token::Result(result)
+                } else if symbol.chars().all(|c| c.is_ascii_digit()) {
+                    self.take_while(|c| c == '.');
+                    Token::MoveNumber
+                } else {
+                    Token::Symbol(symbol)
+                }
+            }
+            c => return Err(self.error(PgnErrorKind::UnexpectedCharacter(c))),
+        };
+        Ok(Some((token, line, column)))
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !pred(c) {
+                break;
+            }
+            s.push(c);
+            self.bump();
+        }
+        s
+    }
+
+    /// Reads the rest of a `[Name "value"]` tag pair, after the bracket.
+    fn tag(&mut self) -> Result<Token, PgnError> {
+        self.skip_whitespace();
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        self.skip_whitespace();
+        if name.is_empty() || self.bump() != Some('"') {
+            return Err(self.error(PgnErrorKind::InvalidTag));
+        }
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some(c) => value.push(c),
+                    None => return Err(self.error(PgnErrorKind::InvalidTag)),
+                },
+                Some('\n') | None => return Err(self.error(PgnErrorKind::InvalidTag)),
+                Some(c) => value.push(c),
+            }
+        }
+        self.skip_whitespace();
+        if self.bump() != Some(']') {
+            return Err(self.error(PgnErrorKind::InvalidTag));
+        }
+        Ok(Token::Tag(name, value))
+    }
+}
+
+// --- Parser ---
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser {
+            lexer: Lexer::new(input),
+        }
+    }
+
+    /// Parses up to and including the next game termination marker.
+    /// Variations are skipped: only the main line is kept.
+    fn next_game(&mut self) -> Result<Option<PgnGame>, PgnError> {
+        let mut game = PgnGame::new(GameState::default());
+        let mut state = game.initial_position.clone();
+        let mut started = false;
+        let mut in_movetext = false;
+        let mut variation_depth = 0;
+
+        while let Some((token, line, column)) = self.lexer.next_token()? {
+            let error = |kind| PgnError { line, column, kind };
+            started = true;
+
+            if variation_depth > 0 {
+                match token {
+                    Token::VariationStart => variation_depth += 1,
+                    Token::VariationEnd => variation_depth -= 1,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match token {
+                Token::Tag(name, value) if !in_movetext => {
+                    if name == "FEN" {
+                        let position = GameState::from_fen(&value)
+                            .map_err(|e| error(PgnErrorKind::InvalidFen(e)))?;
+                        game.initial_position = position.clone();
+                        state = position;
+                    }
+                    game.tags.push((name, value));
+                }
+                Token::Tag(..) => return Err(error(PgnErrorKind::UnexpectedCharacter('['))),
+                Token::Comment(text) => {
+                    in_movetext = true;
+                    let target = match game.moves.last_mut() {
+                        Some(m) => &mut m.comment,
+                        None => &mut game.comment,
+                    };
+                    match target {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(&text);
+                        }
+                        None => *target = Some(text),
+                    }
+                }
+                Token::MoveNumber => in_movetext = true,
+                Token::Nag(nag) => match game.moves.last_mut() {
+                    Some(m) => m.nags.push(nag),
+                    None => return Err(error(PgnErrorKind::UnexpectedCharacter('$'))),
+                },
+                Token::VariationStart => {
+                    if game.moves.is_empty() {
+                        return Err(error(PgnErrorKind::UnmatchedParenthesis));
+                    }
+                    variation_depth = 1;
+                }
+                Token::VariationEnd => return Err(error(PgnErrorKind::UnmatchedParenthesis)),
+                Token::Result(result) => {
+                    game.result = result;
+                    return Ok(Some(game));
+                }
+                Token::Symbol(san) => {
+                    in_movetext = true;
+                    let mv = Move::from_san(&san, &state)
+                        .map_err(|e| error(PgnErrorKind::IllegalMove(e)))?;
+                    state.make_move(mv);
+                    game.moves.push(PgnMove {
+                        mv,
+                        nags: Vec::new(),
+                        comment: None,
+                    });
+                }
+            }
+        }
+
+        if variation_depth > 0 {
+            return Err(self.lexer.error(PgnErrorKind::UnmatchedParenthesis));
+        }
+        if started {
+            // Tolerate a missing termination marker at the very end of the input
+            if let Some(result) = game.tag("Result").and_then(GameResult::parse) {
+                game.result = result;
+            }
+            return Ok(Some(game));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::MoveFlag;
+
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+[Opening "Philidor Defense"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 $6 4. dxe5 Bxf3 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3
+Qe7 8. Nc3 c6 9. Bg5 b5?! (9... Qc7 10. O-O-O) 10. Nxb5! cxb5 11. Bxb5+ Nbd7
+12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1 Qe6 15. Bxd7+ Nxd7 16. Qb8+ {A famous
+queen sacrifice} 16... Nxb8 17. Rd8# 1-0
+"#;
+
+    #[test]
+    fn reads_a_game() {
+        let game = PgnGame::from_pgn(OPERA_GAME).unwrap();
+        assert_eq!(game.tag("White"), Some("Paul Morphy"));
+        assert_eq!(game.tag("Opening"), Some("Philidor Defense"));
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(game.moves.len(), 33);
+        assert_eq!(game.moves[5].nags, vec![6]);
+        assert_eq!(game.moves[17].nags, vec![6]);
+        assert_eq!(game.moves[18].nags, vec![1]);
+        assert_eq!(
+            game.moves[30].comment.as_deref(),
+            Some("A famous queen sacrifice")
+        );
+        assert_eq!(game.moves[22].mv.flag, MoveFlag::QueensideCastle);
+        assert_eq!(
+            game.final_position().to_fen(),
+            "1n1Rkb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2K5 b k - 1 17"
+        );
+    }
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let game = PgnGame::from_pgn(OPERA_GAME).unwrap();
+        let written = game.to_pgn();
+        assert!(written.starts_with("[Event \"Paris\"]\n[Site \"Paris FRA\"]\n"));
+        assert!(
+            written
+                .replace('\n', " ")
+                .contains("16. Qb8+ {A famous queen sacrifice} 16... Nxb8 17. Rd8# 1-0")
+        );
+        assert!(written.lines().all(|line| line.len() <= LINE_WIDTH));
+
+        let reread = PgnGame::from_pgn(&written).unwrap();
+        assert_eq!(reread.tags, game.tags);
+        assert_eq!(reread.moves, game.moves);
+        assert_eq!(reread.result, game.result);
+    }
+
+    #[test]
+    fn comments_never_close_early() {
+        let mut game = PgnGame::from_pgn(OPERA_GAME).unwrap();
+        game.comment = Some("a {nested} note".to_string());
+        game.moves[0].comment = Some("}".to_string());
+        let reread = PgnGame::from_pgn(&game.to_pgn()).unwrap();
+        assert_eq!(reread.comment.as_deref(), Some("a {nested note"));
+        assert_eq!(reread.moves.len(), game.moves.len());
+        assert_eq!(reread.moves[1].mv, game.moves[1].mv);
+    }
+
+    #[test]
+    fn custom_start_position() {
+        let start = GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 12").unwrap();
+        let moves = [
+            Move::from_san("Kd7", &start).unwrap(),
+            Move::from_san(
+                "e4",
+                &start.apply_move(Move::from_san("Kd7", &start).unwrap()),
+            )
+            .unwrap(),
+        ];
+        let game = PgnGame::from_moves(start, &moves, GameResult::Draw);
+        let written = game.to_pgn();
+        assert!(written.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 12\"]\n"));
+        assert!(written.contains("12... Kd7 13. e4 1/2-1/2"));
+        assert!(written.contains("[Event \"?\"]\n"));
+        assert!(written.contains("[Date \"????.??.??\"]\n"));
+
+        let reread = PgnGame::from_pgn(&written).unwrap();
+        assert_eq!(
+            reread.initial_position.to_fen(),
+            game.initial_position.to_fen()
+        );
+        assert_eq!(reread.moves, game.moves);
+    }
+
+    #[test]
+    fn reads_several_games() {
+        let pgn = format!("{OPERA_GAME}\n[Event \"Second\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n1. d4 *");
+        let games = read_pgn_games(&pgn).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[1].result, GameResult::BlackWins);
+        assert_eq!(games[2].result, GameResult::Ongoing);
+        assert_eq!(games[2].moves.len(), 1);
+        assert_eq!(read_pgn_games(&write_pgn_games(&games)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let err = PgnGame::from_pgn("[Event \"?\"]\n\n1. e4 e5\n2. Ke3 Nc6 *")
+            .err()
+            .unwrap();
+        assert_eq!((err.line, err.column), (4, 4));
+        assert!(matches!(
+            err.kind,
+            PgnErrorKind::IllegalMove(SanError::IllegalMove(_))
+        ));
+
+        let err = PgnGame::from_pgn("1. e4 {never closed").err().unwrap();
+        assert_eq!(err.kind, PgnErrorKind::UnterminatedComment);
+
+        let err = PgnGame::from_pgn("[Event \"?\"\n1. e4 *").err().unwrap();
+        assert_eq!(err.kind, PgnErrorKind::InvalidTag);
+    }
+}
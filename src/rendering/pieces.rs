@@ -1,6 +1,12 @@
 use bevy::prelude::*;
 
-use crate::board;
+use crate::{
+    board,
+    fairy::PieceId,
+    four_player::{self, Player},
+    geometry::BoardGeometry,
+    variant::Variant,
+};
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Component)]
 pub enum PieceColor {
@@ -9,6 +15,8 @@ pub enum PieceColor {
 }
 
 impl PieceColor {
+    pub const ALL: [PieceColor; 2] = [PieceColor::White, PieceColor::Black];
+
     pub fn opponent(self) -> PieceColor {
         match self {
             PieceColor::White => PieceColor::Black,
@@ -27,6 +35,42 @@ pub enum PieceType {
     King,
 }
 
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    /// Uppercase letter of the piece, as used by FEN and SAN.
+    pub fn to_char(self) -> char {
+        match self {
+            PieceType::Pawn => 'P',
+            PieceType::Knight => 'N',
+            PieceType::Bishop => 'B',
+            PieceType::Rook => 'R',
+            PieceType::Queen => 'Q',
+            PieceType::King => 'K',
+        }
+    }
+
+    /// Inverse of `to_char`, case-insensitive.
+    pub fn from_char(c: char) -> Option<PieceType> {
+        match c.to_ascii_uppercase() {
+            'P' => Some(PieceType::Pawn),
+            'N' => Some(PieceType::Knight),
+            'B' => Some(PieceType::Bishop),
+            'R' => Some(PieceType::Rook),
+            'Q' => Some(PieceType::Queen),
+            'K' => Some(PieceType::King),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Bundle)]
 pub struct ChessPiece {
     piece: PieceType,
@@ -41,20 +85,10 @@ impl ChessPiece {
         piece: PieceType,
         color: PieceColor,
         position: board::BoardCoordinates,
+        geometry: BoardGeometry,
         asset_server: &Res<AssetServer>,
     ) -> ChessPiece {
-        let path = format!(
-            "pieces/01_classic/{}-{}.png",
-            if color == PieceColor::White { "w" } else { "b" },
-            match piece {
-                PieceType::Pawn => "pawn",
-                PieceType::Knight => "knight",
-                PieceType::Bishop => "bishop",
-                PieceType::Rook => "rook",
-                PieceType::Queen => "queen",
-                PieceType::King => "king",
-            }
-        );
+        let path = sprite_path(piece, if color == PieceColor::White { "w" } else { "b" });
 
         return ChessPiece {
             piece,
@@ -64,11 +98,7 @@ impl ChessPiece {
                 custom_size: Some(Vec2::new(board::SQUARE_SIZE, board::SQUARE_SIZE)),
                 ..default()
             },
-            transform: Transform::from_xyz(
-                (position.col as f32 - board::BOARD_SIZE as f32 / 2.0) * board::SQUARE_SIZE,
-                (position.row as f32 - board::BOARD_SIZE as f32 / 2.0) * board::SQUARE_SIZE,
-                1.,
-            ),
+            transform: Transform::from_translation(position.translation(geometry).extend(1.)),
             pickable: Pickable {
                 should_block_lower: false,
                 ..default()
@@ -76,3 +106,102 @@ impl ChessPiece {
         };
     }
 }
+
+fn sprite_path(piece: PieceType, set: &str) -> String {
+    format!(
+        "pieces/01_classic/{}-{}.png",
+        set,
+        match piece {
+            PieceType::Pawn => "pawn",
+            PieceType::Knight => "knight",
+            PieceType::Bishop => "bishop",
+            PieceType::Rook => "rook",
+            PieceType::Queen => "queen",
+            PieceType::King => "king",
+        }
+    )
+}
+
+/// A piece of four-player chess: the white sprite tinted in its player's colour.
+#[derive(Bundle)]
+pub struct FourPlayerPiece {
+    piece: PieceType,
+    player: Player,
+    sprite: Sprite,
+    transform: Transform,
+    pickable: Pickable,
+}
+
+impl FourPlayerPiece {
+    pub fn new(
+        piece: PieceType,
+        player: Player,
+        position: board::BoardCoordinates,
+        asset_server: &Res<AssetServer>,
+    ) -> FourPlayerPiece {
+        let color = match player {
+            Player::Red => Color::srgb(0.85, 0.2, 0.2),
+            Player::Blue => Color::srgb(0.25, 0.45, 0.95),
+            Player::Yellow => Color::srgb(0.95, 0.8, 0.15),
+            Player::Green => Color::srgb(0.2, 0.7, 0.3),
+        };
+
+        FourPlayerPiece {
+            piece,
+            player,
+            sprite: Sprite {
+                image: asset_server.load(sprite_path(piece, "w")),
+                custom_size: Some(Vec2::new(board::SQUARE_SIZE, board::SQUARE_SIZE)),
+                color,
+                ..default()
+            },
+            transform: Transform::from_translation(four_player::translation(&position).extend(1.)),
+            pickable: Pickable {
+                should_block_lower: false,
+                ..default()
+            },
+        }
+    }
+}
+
+/// Which piece of the variant's roster an entity is.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RosterPiece(pub PieceId);
+
+/// A piece of a variant loaded from a rule file, drawn with the sprite the
+/// file names for it.
+#[derive(Bundle)]
+pub struct VariantPiece {
+    piece: RosterPiece,
+    color: PieceColor,
+    sprite: Sprite,
+    transform: Transform,
+    pickable: Pickable,
+}
+
+impl VariantPiece {
+    pub fn new(
+        id: PieceId,
+        color: PieceColor,
+        position: board::BoardCoordinates,
+        variant: &Variant,
+        asset_server: &Res<AssetServer>,
+    ) -> VariantPiece {
+        VariantPiece {
+            piece: RosterPiece(id),
+            color,
+            sprite: Sprite {
+                image: asset_server.load(variant.sprite_path(id, color)),
+                custom_size: Some(Vec2::new(board::SQUARE_SIZE, board::SQUARE_SIZE)),
+                ..default()
+            },
+            transform: Transform::from_translation(
+                position.translation(variant.geometry()).extend(1.),
+            ),
+            pickable: Pickable {
+                should_block_lower: false,
+                ..default()
+            },
+        }
+    }
+}
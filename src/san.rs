@@ -0,0 +1,369 @@
+use std::fmt;
+
+use crate::{
+    board::BoardCoordinates,
+    game::{GameState, Move, MoveFlag},
+    movegen::{generate_legal_moves, is_in_check},
+    rendering::PieceType,
+};
+
+/// Reason a SAN string could not be turned into a move.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    InvalidSyntax(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "invalid SAN syntax: '{san}'"),
+            SanError::IllegalMove(san) => write!(f, "illegal move: '{san}'"),
+            SanError::AmbiguousMove(san) => write!(f, "ambiguous move: '{san}'"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl Move {
+    /// Formats the move in Standard Algebraic Notation, as played from `state`.
+    ///
+    /// En passant captures are written like any other pawn capture (`exd6`),
+    /// as PGN forbids the optional ` e.p.` suffix. Use `to_san_with_ep` for
+    /// the FIDE style that spells it out.
+    pub fn to_san(&self, state: &GameState) -> String {
+        self.format_san(state, false)
+    }
+
+    /// Like `to_san`, but en passant captures carry the ` e.p.` suffix
+    /// (`exd6 e.p.`), for display outside of PGN.
+    pub fn to_san_with_ep(&self, state: &GameState) -> String {
+        self.format_san(state, true)
+    }
+
+    fn format_san(&self, state: &GameState, en_passant_suffix: bool) -> String {
+        let mut san = match self.flag {
+            MoveFlag::KingsideCastle => "O-O".to_string(),
+            MoveFlag::QueensideCastle => "O-O-O".to_string(),
+            _ => self.san_body(state),
+        };
+        if en_passant_suffix && self.flag == MoveFlag::EnPassant {
+            san.push_str(" e.p.");
+        }
+
+        let next = state.apply_move(*self);
+        if is_in_check(next.side_to_move, &next) {
+            san.push(if generate_legal_moves(&next).is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+
+    /// Parses a SAN move in the context of `state`.
+    ///
+    /// Check and mate markers, annotation glyphs (`!`, `?`) and a trailing
+    /// `e.p.` are ignored; they are not needed to identify the move.
+    pub fn from_san(san: &str, state: &GameState) -> Result<Move, SanError> {
+        let syntax_error = || SanError::InvalidSyntax(san.to_string());
+
+        let trimmed = san.trim();
+        let trimmed = trimmed
+            .strip_suffix("e.p.")
+            .or_else(|| trimmed.strip_suffix("ep"))
+            .unwrap_or(trimmed)
+            .trim_end()
+            .trim_end_matches(['+', '#', '!', '?']);
+
+        let legal = generate_legal_moves(state);
+        let matches: Vec<Move> = match trimmed {
+            "O-O" | "0-0" => legal
+                .into_iter()
+                .filter(|mv| mv.flag == MoveFlag::KingsideCastle)
+                .collect(),
+            "O-O-O" | "0-0-0" => legal
+                .into_iter()
+                .filter(|mv| mv.flag == MoveFlag::QueensideCastle)
+                .collect(),
+            _ => {
+                let pattern = SanPattern::parse(trimmed).ok_or_else(syntax_error)?;
+                legal
+                    .into_iter()
+                    .filter(|mv| pattern.matches(mv, state))
+                    .collect()
+            }
+        };
+
+        match matches.as_slice() {
+            [mv] => Ok(*mv),
+            [] => Err(SanError::IllegalMove(san.to_string())),
+            _ => Err(SanError::AmbiguousMove(san.to_string())),
+        }
+    }
+
+    /// Everything but castling and the check suffix.
+    fn san_body(&self, state: &GameState) -> String {
+        let (pt, _) = state
+            .piece_at(self.from)
+            .expect("to_san: no piece at from square");
+        let from = BoardCoordinates::from_bit(self.from);
+        let is_capture = matches!(
+            self.flag,
+            MoveFlag::Capture | MoveFlag::PromotionCapture(_) | MoveFlag::EnPassant
+        );
+
+        let mut san = String::new();
+        if pt == PieceType::Pawn {
+            if is_capture {
+                san.push((b'a' + from.col) as char);
+            }
+        } else {
+            san.push(pt.to_char());
+
+            // Other pieces of the same kind that could also reach the target
+            let rivals: Vec<BoardCoordinates> = generate_legal_moves(state)
+                .into_iter()
+                .filter(|mv| {
+                    mv.to == self.to
+                        && mv.from != self.from
+                        && state.piece_at(mv.from).map(|(p, _)| p) == Some(pt)
+                })
+                .map(|mv| BoardCoordinates::from_bit(mv.from))
+                .collect();
+            if !rivals.is_empty() {
+                if rivals.iter().all(|c| c.col != from.col) {
+                    san.push((b'a' + from.col) as char);
+                } else if rivals.iter().all(|c| c.row != from.row) {
+                    san.push((b'1' + from.row) as char);
+                } else {
+                    san.push_str(&from.to_string());
+                }
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&BoardCoordinates::from_bit(self.to).to_string());
+
+        if let MoveFlag::Promotion(promo) | MoveFlag::PromotionCapture(promo) = self.flag {
+            san.push('=');
+            san.push(promo.to_char());
+        }
+        san
+    }
+}
+
+/// The constraints a non-castling SAN move puts on the move it denotes.
+struct SanPattern {
+    piece: PieceType,
+    from_col: Option<u8>,
+    from_row: Option<u8>,
+    to: u8,
+    promotion: Option<PieceType>,
+}
+
+impl SanPattern {
+    fn parse(san: &str) -> Option<SanPattern> {
+        let mut chars: Vec<char> = san.chars().collect();
+
+        let piece = match chars.first() {
+            Some(&c) if c.is_ascii_uppercase() => {
+                chars.remove(0);
+                PieceType::from_char(c).filter(|&pt| pt != PieceType::Pawn)?
+            }
+            _ => PieceType::Pawn,
+        };
+
+        // Promotion suffix, with or without the '='
+        let promotion = match chars.last() {
+            Some(&c) if c.is_ascii_uppercase() => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                let promo = PieceType::from_char(c)?;
+                if matches!(promo, PieceType::Pawn | PieceType::King) {
+                    return None;
+                }
+                Some(promo)
+            }
+            _ => None,
+        };
+
+        // The destination square is always the last two characters
+        if chars.len() < 2 {
+            return None;
+        }
+        let to_str: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = BoardCoordinates::from_algebraic(&to_str)?.to_bit();
+
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+
+        let (mut from_col, mut from_row) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if from_col.is_none() && from_row.is_none() => {
+                    from_col = Some(c as u8 - b'a')
+                }
+                '1'..='8' if from_row.is_none() => from_row = Some(c as u8 - b'1'),
+                _ => return None,
+            }
+        }
+
+        Some(SanPattern {
+            piece,
+            from_col,
+            from_row,
+            to,
+            promotion,
+        })
+    }
+
+    fn matches(&self, mv: &Move, state: &GameState) -> bool {
+        let from = BoardCoordinates::from_bit(mv.from);
+        let promotion = match mv.flag {
+            MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) => Some(pt),
+            _ => None,
+        };
+        mv.to == self.to
+            && !matches!(
+                mv.flag,
+                MoveFlag::KingsideCastle | MoveFlag::QueensideCastle
+            )
+            && state.piece_at(mv.from).map(|(pt, _)| pt) == Some(self.piece)
+            && self.from_col.is_none_or(|col| col == from.col)
+            && self.from_row.is_none_or(|row| row == from.row)
+            && promotion == self.promotion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(fen: &str) -> GameState {
+        GameState::from_fen(fen).unwrap()
+    }
+
+    fn san_of(fen: &str, uci_from: &str, uci_to: &str) -> String {
+        let state = state(fen);
+        let from = BoardCoordinates::from_algebraic(uci_from).unwrap().to_bit();
+        let to = BoardCoordinates::from_algebraic(uci_to).unwrap().to_bit();
+        let mv = generate_legal_moves(&state)
+            .into_iter()
+            .find(|mv| mv.from == from && mv.to == to)
+            .unwrap();
+        mv.to_san(&state)
+    }
+
+    #[test]
+    fn formats_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2", "e4"), "e4");
+        assert_eq!(san_of(start, "g1", "f3"), "Nf3");
+
+        // Knights on b8 and f6 can both reach d7
+        let fen = "rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1";
+        assert_eq!(san_of(fen, "b8", "d7"), "Nbd7");
+
+        let castles = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(castles, "e1", "g1"), "O-O");
+        assert_eq!(san_of(castles, "e1", "c1"), "O-O-O");
+
+        assert_eq!(
+            san_of("8/8/8/3pP3/8/8/8/k6K w - d6 0 1", "e5", "d6"),
+            "exd6"
+        );
+        let ep = state("8/8/8/3pP3/8/8/8/k6K w - d6 0 1");
+        let mv = Move::from_san("exd6", &ep).unwrap();
+        assert_eq!(mv.to_san_with_ep(&ep), "exd6 e.p.");
+        assert_eq!(Move::from_san(&mv.to_san_with_ep(&ep), &ep), Ok(mv));
+        // Other moves are unaffected
+        let start = state(start);
+        let e4 = Move::from_san("e4", &start).unwrap();
+        assert_eq!(e4.to_san_with_ep(&start), "e4");
+        assert_eq!(
+            san_of("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "e8"),
+            "e8=Q+"
+        );
+        assert_eq!(
+            san_of(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+                "h5",
+                "f7"
+            ),
+            "Qxf7#"
+        );
+
+        // Rooks on a1 and a5 share a file, so the rank disambiguates
+        assert_eq!(san_of("7k/8/8/R7/8/8/8/R6K w - - 0 1", "a1", "a3"), "R1a3");
+        // Queens on d1, d3 and b3: only the full square is unique
+        assert_eq!(
+            san_of("7k/8/8/8/8/1Q1Q4/8/3Q3K w - - 0 1", "d3", "c2"),
+            "Qd3c2"
+        );
+    }
+
+    #[test]
+    fn round_trips_every_legal_move() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let state = state(fen);
+            for mv in generate_legal_moves(&state) {
+                let san = mv.to_san(&state);
+                assert_eq!(Move::from_san(&san, &state), Ok(mv), "{san} in {fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn parses_lenient_input() {
+        let state = state("8/8/8/3pP3/8/8/8/k6K w - d6 0 1");
+        let ep = Move::from_san("exd6 e.p.", &state).unwrap();
+        assert_eq!(ep.flag, MoveFlag::EnPassant);
+        assert_eq!(Move::from_san("exd6!?", &state), Ok(ep));
+
+        let promo = GameState::from_fen("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            Move::from_san("e8N", &promo).unwrap().flag,
+            MoveFlag::Promotion(PieceType::Knight)
+        );
+        let castles = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(
+            Move::from_san("0-0-0", &castles).unwrap().flag,
+            MoveFlag::QueensideCastle
+        );
+    }
+
+    #[test]
+    fn rejects_bad_moves() {
+        let start = GameState::default();
+        assert_eq!(
+            Move::from_san("e5", &start),
+            Err(SanError::IllegalMove("e5".to_string()))
+        );
+        assert_eq!(
+            Move::from_san("Zf3", &start),
+            Err(SanError::InvalidSyntax("Zf3".to_string()))
+        );
+        assert_eq!(
+            Move::from_san("e9", &start),
+            Err(SanError::InvalidSyntax("e9".to_string()))
+        );
+        let knights = GameState::from_fen("7k/8/8/8/8/8/8/1N3N1K w - - 0 1").unwrap();
+        assert_eq!(
+            Move::from_san("Nd2", &knights),
+            Err(SanError::AmbiguousMove("Nd2".to_string()))
+        );
+    }
+}
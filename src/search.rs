@@ -0,0 +1,960 @@
+use std::{
+    cmp::Reverse,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bitboard::BitBoard,
+    eval::{evaluate, evaluate_wide, piece_value},
+    game::{GameState, Move, MoveFlag, Rules},
+    movegen::{generate_legal_moves, is_in_check},
+    rendering::PieceType,
+    transposition::{Bound, TranspositionTable},
+    wide::{WideGameState, WideMove, en_passant_victim},
+};
+
+/// Score of a checkmate at the root, mates further away score `MATE - plies`.
+pub const MATE: i32 = 30_000;
+pub const MAX_PLY: usize = 128;
+const INFINITY: i32 = 32_000;
+
+/// How often (in nodes) the clock and stop flag are polled.
+const CHECK_INTERVAL: u64 = 2048;
+const HISTORY_MAX: i32 = 500_000;
+
+/// Budget for a search. Every limit that is set applies; with none set the
+/// search runs until stopped through `Searcher::stop_handle`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+}
+
+/// Outcome of the last fully searched iteration. `M` is `WideMove` for the
+/// results of a `WideSearcher`.
+#[derive(Clone, Debug)]
+pub struct SearchResult<M = Move> {
+    pub best_move: Option<M>,
+    /// Centipawns from the side to move's point of view, or a mate score.
+    pub score: i32,
+    pub depth: u32,
+    pub pv: Vec<M>,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl<M> Default for SearchResult<M> {
+    fn default() -> Self {
+        SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            pv: Vec::new(),
+            nodes: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl<M> SearchResult<M> {
+    /// Full moves until mate, negative when the side to move gets mated.
+    pub fn mate_in(&self) -> Option<i32> {
+        if !is_mate_score(self.score) {
+            return None;
+        }
+        let plies = MATE - self.score.abs();
+        let moves = (plies + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY as i32
+}
+
+/// Nodes, clock and stop flag of a running search.
+struct Budget {
+    stop: Arc<AtomicBool>,
+    limits: SearchLimits,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            stop: Arc::new(AtomicBool::new(false)),
+            limits: SearchLimits::default(),
+            start: Instant::now(),
+            nodes: 0,
+            aborted: false,
+        }
+    }
+}
+
+impl Budget {
+    fn reset(&mut self, limits: &SearchLimits) {
+        self.limits = limits.clone();
+        self.start = Instant::now();
+        self.nodes = 0;
+        self.aborted = false;
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if self.limits.nodes.is_some_and(|n| self.nodes >= n) {
+            self.aborted = true;
+        } else if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.aborted = self.stop.load(Ordering::Relaxed)
+                || self
+                    .limits
+                    .movetime
+                    .is_some_and(|t| self.start.elapsed() >= t);
+        }
+        self.aborted
+    }
+}
+
+/// Negamax alpha-beta searcher with iterative deepening and quiescence search.
+///
+/// Move ordering tables (killers, history) live here and persist between the
+/// iterations of a search. The transposition table is shared and outlives
+/// searches.
+pub struct Searcher {
+    budget: Budget,
+    tt: Arc<TranspositionTable>,
+    /// Hashes of the game so far followed by the current search path, for repetitions.
+    hashes: Vec<u64>,
+    pv: Vec<Vec<Move>>,
+    previous_pv: Vec<Move>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Searcher::new()
+    }
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::with_table(Arc::new(TranspositionTable::default()))
+    }
+
+    pub fn with_table(tt: Arc<TranspositionTable>) -> Searcher {
+        Searcher {
+            budget: Budget::default(),
+            tt,
+            hashes: Vec::new(),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            previous_pv: Vec::new(),
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    /// Flag that aborts the running search when set, e.g. from another thread.
+    /// It stays set until cleared, so reset it before starting the next search.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.budget.stop.clone()
+    }
+
+    pub fn table(&self) -> &Arc<TranspositionTable> {
+        &self.tt
+    }
+
+    /// Replaces the transposition table with an empty one of `mb` megabytes.
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(mb));
+    }
+
+    /// Searches `state` within `limits`. `previous_hashes` are the hashes of
+    /// the positions played before it, so that repetitions score as draws.
+    pub fn search(
+        &mut self,
+        state: &GameState,
+        previous_hashes: &[u64],
+        limits: &SearchLimits,
+    ) -> SearchResult {
+        self.search_with(state, previous_hashes, limits, |_| {})
+    }
+
+    /// Like `search`, calling `on_iteration` with the result of every
+    /// completed iteration (for progress output such as UCI `info` lines).
+    pub fn search_with(
+        &mut self,
+        state: &GameState,
+        previous_hashes: &[u64],
+        limits: &SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.budget.reset(limits);
+        self.hashes = previous_hashes.to_vec();
+        self.previous_pv.clear();
+        self.killers = [[None; 2]; MAX_PLY];
+        self.tt.new_search();
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|h| *h = 0);
+
+        let mut state = state.clone();
+        let root_moves = generate_legal_moves(&state);
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            ..SearchResult::default()
+        };
+        if root_moves.is_empty() {
+            result.score = if is_in_check(state.side_to_move, &state) {
+                -MATE
+            } else {
+                0
+            };
+            return result;
+        }
+
+        let max_depth = limits
+            .depth
+            .unwrap_or(u32::MAX)
+            .clamp(1, MAX_PLY as u32 - 1);
+        for depth in 1..=max_depth {
+            let score = self.negamax(&mut state, depth, 0, -INFINITY, INFINITY);
+            if self.budget.aborted {
+                // The root moves searched before the abort were searched in
+                // full, the previous best among them, so a new best found
+                // there is trusted. Its score is not, and is left as it was.
+                if let Some(&best) = self.pv[0].first() {
+                    result.best_move = Some(best);
+                    result.pv = self.pv[0].clone();
+                }
+                break;
+            }
+            self.previous_pv = self.pv[0].clone();
+            result = SearchResult {
+                best_move: self.pv[0].first().copied(),
+                score,
+                depth,
+                pv: self.pv[0].clone(),
+                nodes: self.budget.nodes,
+                elapsed: self.budget.start.elapsed(),
+            };
+            on_iteration(&result);
+            // No point looking deeper once a forced mate fits in the horizon
+            if is_mate_score(score) && MATE - score.abs() <= depth as i32 {
+                break;
+            }
+        }
+
+        result.nodes = self.budget.nodes;
+        result.elapsed = self.budget.start.elapsed();
+        result
+    }
+
+    fn negamax(
+        &mut self,
+        state: &mut GameState,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.budget.should_stop() {
+            return 0;
+        }
+        if ply > 0 && (state.halfmove_clock >= 100 || self.is_repetition(state)) {
+            return 0;
+        }
+
+        let in_check = is_in_check(state.side_to_move, state);
+        // Check extension: don't let a check push the reply over the horizon
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(state, ply, alpha, beta);
+        }
+        self.budget.nodes += 1;
+
+        let entry = self.tt.probe(state.hash, ply);
+        if let Some(entry) = entry {
+            // Never cut at the root, which must come back with a move and PV
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            if ply > 0 && entry.depth >= depth && usable {
+                return entry.score;
+            }
+        }
+        let tt_move = entry.and_then(|e| e.best_move);
+
+        let mut moves = generate_legal_moves(state);
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        self.order_moves(state, &mut moves, ply, tt_move);
+
+        let color = state.side_to_move;
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        self.hashes.push(state.hash);
+        for mv in moves {
+            let undo = state.make_move(mv);
+            let score = -self.negamax(state, depth - 1, ply + 1, -beta, -alpha);
+            state.unmake_move(mv, undo);
+            if self.budget.aborted {
+                break;
+            }
+
+            if score > best {
+                best = score;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                let (head, tail) = self.pv.split_at_mut(ply + 1);
+                head[ply].clear();
+                head[ply].push(mv);
+                head[ply].extend_from_slice(&tail[0]);
+
+                if score >= beta {
+                    if is_quiet(mv) {
+                        self.store_killer(mv, ply);
+                        let h = &mut self.history[color as usize][mv.from as usize][mv.to as usize];
+                        *h = (*h + (depth * depth) as i32).min(HISTORY_MAX);
+                    }
+                    break;
+                }
+            }
+        }
+        self.hashes.pop();
+
+        if !self.budget.aborted {
+            let bound = if best >= beta {
+                Bound::Lower
+            } else if best > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            self.tt
+                .store(state.hash, depth, ply, best, bound, best_move);
+        }
+        best
+    }
+
+    /// Only resolves captures (and queen promotions), so that the static
+    /// evaluation is never taken in the middle of an exchange.
+    fn quiescence(&mut self, state: &mut GameState, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+        if self.budget.should_stop() {
+            return 0;
+        }
+        self.budget.nodes += 1;
+
+        // An atomic capture blew up our king: lost, whatever the material
+        if state.rules == Rules::Atomic
+            && state.piece_bb(PieceType::King, state.side_to_move) == BitBoard(0)
+        {
+            return -MATE + ply as i32;
+        }
+
+        let stand_pat = evaluate(state);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = generate_legal_moves(state);
+        moves.retain(|&mv| !is_quiet(mv));
+        self.order_moves(state, &mut moves, ply, None);
+
+        for mv in moves {
+            let undo = state.make_move(mv);
+            let score = -self.quiescence(state, ply + 1, -beta, -alpha);
+            state.unmake_move(mv, undo);
+            if self.budget.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    fn is_repetition(&self, state: &GameState) -> bool {
+        self.hashes
+            .iter()
+            .rev()
+            .take(state.halfmove_clock as usize)
+            .any(|&h| h == state.hash)
+    }
+
+    fn store_killer(&mut self, mv: Move, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mv) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+    }
+
+    /// Transposition table move first, then the previous PV move, captures by
+    /// MVV-LVA and queen promotions, killers, and finally quiet moves by
+    /// history score.
+    fn order_moves(
+        &self,
+        state: &GameState,
+        moves: &mut [Move],
+        ply: usize,
+        tt_move: Option<Move>,
+    ) {
+        let pv_move = self.previous_pv.get(ply).copied();
+        let killers = self.killers[ply.min(MAX_PLY - 1)];
+        let color = state.side_to_move;
+
+        moves.sort_unstable_by_key(|&mv| {
+            let score = if Some(mv) == tt_move {
+                3_000_000
+            } else if Some(mv) == pv_move {
+                2_000_000
+            } else if !is_quiet(mv) {
+                1_000_000 + mvv_lva(state, mv)
+            } else if Some(mv) == killers[0] {
+                900_000
+            } else if Some(mv) == killers[1] {
+                800_000
+            } else {
+                self.history[color as usize][mv.from as usize][mv.to as usize]
+            };
+            Reverse(score)
+        });
+    }
+}
+
+/// Alpha-beta search of a `WideGameState`, for variants whose pieces only
+/// the roster knows. It is `Searcher` without what needs a Zobrist hash or
+/// 64 squares: no transposition table and no killer or history tables, with
+/// `evaluate_wide` as its evaluation and `WideGameState::position_hash`
+/// telling repetitions. Goal squares and
+/// stalemate wins from the position's `WinRules` score as mates.
+pub struct WideSearcher {
+    budget: Budget,
+    /// Hashes of the game so far followed by the current search path.
+    hashes: Vec<u64>,
+    pv: Vec<Vec<WideMove>>,
+    previous_pv: Vec<WideMove>,
+}
+
+impl Default for WideSearcher {
+    fn default() -> Self {
+        WideSearcher::new()
+    }
+}
+
+impl WideSearcher {
+    pub fn new() -> WideSearcher {
+        WideSearcher {
+            budget: Budget::default(),
+            hashes: Vec::new(),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+            previous_pv: Vec::new(),
+        }
+    }
+
+    /// Flag that aborts the running search when set, as for `Searcher`.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.budget.stop.clone()
+    }
+
+    /// A searcher aborted by `stop`, e.g. another searcher's stop handle, so
+    /// that one flag stops either.
+    pub fn with_stop_handle(stop: Arc<AtomicBool>) -> WideSearcher {
+        WideSearcher {
+            budget: Budget {
+                stop,
+                ..Budget::default()
+            },
+            ..WideSearcher::new()
+        }
+    }
+
+    /// Searches `state` within `limits`. `previous_hashes` are the
+    /// `position_hash`es of the positions played before it, as for
+    /// `Searcher::search`.
+    pub fn search(
+        &mut self,
+        state: &WideGameState,
+        previous_hashes: &[u64],
+        limits: &SearchLimits,
+    ) -> SearchResult<WideMove> {
+        self.search_with(state, previous_hashes, limits, |_| {})
+    }
+
+    /// Like `search`, calling `on_iteration` with the result of every
+    /// completed iteration.
+    pub fn search_with(
+        &mut self,
+        state: &WideGameState,
+        previous_hashes: &[u64],
+        limits: &SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult<WideMove>),
+    ) -> SearchResult<WideMove> {
+        self.budget.reset(limits);
+        self.hashes = previous_hashes.to_vec();
+        self.previous_pv.clear();
+
+        let mut state = state.clone();
+        let root_moves = state.generate_legal_moves();
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            ..SearchResult::default()
+        };
+        if root_moves.is_empty() {
+            result.score = if state.is_in_check(state.side_to_move) || state.wins().stalemate_wins {
+                -MATE
+            } else {
+                0
+            };
+            return result;
+        }
+
+        let max_depth = limits
+            .depth
+            .unwrap_or(u32::MAX)
+            .clamp(1, MAX_PLY as u32 - 1);
+        for depth in 1..=max_depth {
+            let score = self.negamax(&mut state, depth, 0, -INFINITY, INFINITY);
+            if self.budget.aborted {
+                // Trusted as in `Searcher::search_with`
+                if let Some(&best) = self.pv[0].first() {
+                    result.best_move = Some(best);
+                    result.pv = self.pv[0].clone();
+                }
+                break;
+            }
+            self.previous_pv = self.pv[0].clone();
+            result = SearchResult {
+                best_move: self.pv[0].first().copied(),
+                score,
+                depth,
+                pv: self.pv[0].clone(),
+                nodes: self.budget.nodes,
+                elapsed: self.budget.start.elapsed(),
+            };
+            on_iteration(&result);
+            if is_mate_score(score) && MATE - score.abs() <= depth as i32 {
+                break;
+            }
+        }
+
+        result.nodes = self.budget.nodes;
+        result.elapsed = self.budget.start.elapsed();
+        result
+    }
+
+    fn negamax(
+        &mut self,
+        state: &mut WideGameState,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.budget.should_stop() {
+            return 0;
+        }
+        // The opponent's royal piece has reached a goal square
+        if state.reached_goal(state.side_to_move.opponent()) {
+            return -MATE + ply as i32;
+        }
+        let hash = state.position_hash();
+        if ply > 0 && (state.halfmove_clock >= 100 || self.is_repetition(state, hash)) {
+            return 0;
+        }
+
+        let in_check = state.is_in_check(state.side_to_move);
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(state, ply, alpha, beta);
+        }
+        self.budget.nodes += 1;
+
+        let mut moves = state.generate_legal_moves();
+        if moves.is_empty() {
+            return if in_check || state.wins().stalemate_wins {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+        self.order_moves(state, &mut moves, ply);
+
+        let mut best = -INFINITY;
+        self.hashes.push(hash);
+        for mv in moves {
+            let undo = state.make_move(mv);
+            let score = -self.negamax(state, depth - 1, ply + 1, -beta, -alpha);
+            state.unmake_move(mv, undo);
+            if self.budget.aborted {
+                break;
+            }
+
+            best = best.max(score);
+            if score > alpha {
+                alpha = score;
+                let (head, tail) = self.pv.split_at_mut(ply + 1);
+                head[ply].clear();
+                head[ply].push(mv);
+                head[ply].extend_from_slice(&tail[0]);
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+        self.hashes.pop();
+        best
+    }
+
+    fn quiescence(
+        &mut self,
+        state: &mut WideGameState,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.budget.should_stop() {
+            return 0;
+        }
+        self.budget.nodes += 1;
+
+        if state.reached_goal(state.side_to_move.opponent()) {
+            return -MATE + ply as i32;
+        }
+
+        // In check there is no standing pat: every evasion is searched, and
+        // having none is mate
+        let in_check = state.is_in_check(state.side_to_move);
+        if !in_check || ply >= MAX_PLY - 1 {
+            let stand_pat = evaluate_wide(state);
+            if stand_pat >= beta || ply >= MAX_PLY - 1 {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut moves = state.generate_legal_moves();
+        if in_check && moves.is_empty() {
+            return -MATE + ply as i32;
+        }
+        if !in_check {
+            moves.retain(|&mv| !is_quiet_wide(mv));
+        }
+        self.order_moves(state, &mut moves, ply);
+
+        for mv in moves {
+            let undo = state.make_move(mv);
+            let score = -self.quiescence(state, ply + 1, -beta, -alpha);
+            state.unmake_move(mv, undo);
+            if self.budget.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    fn is_repetition(&self, state: &WideGameState, hash: u64) -> bool {
+        self.hashes
+            .iter()
+            .rev()
+            .take(state.halfmove_clock as usize)
+            .any(|&h| h == hash)
+    }
+
+    /// The previous PV move first, then captures and promotions by what
+    /// they win, then quiet moves.
+    fn order_moves(&self, state: &WideGameState, moves: &mut [WideMove], ply: usize) {
+        let pv_move = self.previous_pv.get(ply).copied();
+        let value = |sq: u8| {
+            state
+                .piece_at(sq)
+                .map_or(0, |(id, _)| state.roster().get(id).value)
+        };
+        moves.sort_unstable_by_key(|&mv| {
+            let score = if Some(mv) == pv_move {
+                2_000_000
+            } else if !is_quiet_wide(mv) {
+                let victim = match mv.flag {
+                    MoveFlag::EnPassant => value(en_passant_victim(state.geometry(), mv)),
+                    _ => value(mv.to),
+                };
+                let promotion = match mv.flag {
+                    MoveFlag::Promotion(id) | MoveFlag::PromotionCapture(id) => {
+                        state.roster().get(id).value
+                    }
+                    _ => 0,
+                };
+                1_000_000 + 10 * (victim + promotion) - value(mv.from)
+            } else {
+                0
+            };
+            Reverse(score)
+        });
+    }
+}
+
+/// Quiet moves neither capture nor promote to a queen.
+fn is_quiet(mv: Move) -> bool {
+    !matches!(
+        mv.flag,
+        MoveFlag::Capture
+            | MoveFlag::EnPassant
+            | MoveFlag::PromotionCapture(_)
+            | MoveFlag::Promotion(PieceType::Queen)
+    )
+}
+
+/// Quiet moves of a `WideGameState` neither capture nor promote; with no
+/// queen to tell apart, every promotion counts.
+fn is_quiet_wide(mv: WideMove) -> bool {
+    !matches!(
+        mv.flag,
+        MoveFlag::Capture
+            | MoveFlag::EnPassant
+            | MoveFlag::PromotionCapture(_)
+            | MoveFlag::Promotion(_)
+    )
+}
+
+/// Most Valuable Victim first, Least Valuable Attacker as tie-break.
+fn mvv_lva(state: &GameState, mv: Move) -> i32 {
+    let attacker = state.piece_at(mv.from).map_or(0, |(pt, _)| piece_value(pt));
+    let victim = match mv.flag {
+        MoveFlag::EnPassant => piece_value(PieceType::Pawn),
+        _ => state.piece_at(mv.to).map_or(0, |(pt, _)| piece_value(pt)),
+    };
+    let promotion = match mv.flag {
+        MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) => piece_value(pt),
+        _ => 0,
+    };
+    10 * (victim + promotion) - attacker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(fen: &str, depth: u32) -> SearchResult {
+        let state = GameState::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        };
+        Searcher::new().search(&state, &[], &limits)
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let result = best(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+            3,
+        );
+        assert_eq!(result.best_move.unwrap().to_uci(), "h5f7");
+        assert_eq!(result.score, MATE - 1);
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        let result = best("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 4);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a6");
+        assert_eq!(result.mate_in(), Some(2));
+        assert_eq!(result.pv.len(), 3);
+    }
+
+    #[test]
+    fn wins_hanging_material() {
+        let result = best("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d1d5");
+        assert!(result.score > 300);
+    }
+
+    #[test]
+    fn quiescence_sees_atomic_explosions() {
+        // Rxe7 loses the rook for the queen but blows up the king next to it
+        let mut state = GameState::from_fen("4k3/4q3/8/8/8/8/8/4RK2 w - - 0 1").unwrap();
+        state.set_rules(Rules::Atomic);
+        let mut searcher = Searcher::new();
+        let score = searcher.quiescence(&mut state, 0, -INFINITY, INFINITY);
+        assert_eq!(score, MATE - 1);
+    }
+
+    #[test]
+    fn shared_table_speeds_up_research() {
+        let state = GameState::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        )
+        .unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let tt = Arc::new(TranspositionTable::new(1));
+        let first = Searcher::with_table(tt.clone()).search(&state, &[], &limits);
+        let second = Searcher::with_table(tt).search(&state, &[], &limits);
+        assert!(second.nodes < first.nodes);
+        assert_eq!(second.best_move, first.best_move);
+    }
+
+    #[test]
+    fn respects_node_limit() {
+        let limits = SearchLimits {
+            nodes: Some(2_000),
+            ..SearchLimits::default()
+        };
+        let result = Searcher::new().search(&GameState::default(), &[], &limits);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 2_000);
+    }
+
+    #[test]
+    fn respects_movetime() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        };
+        let result = Searcher::new().search(&GameState::default(), &[], &limits);
+        assert!(result.best_move.is_some());
+        assert!(result.elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn no_move_in_terminal_positions() {
+        let mated = best(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            3,
+        );
+        assert_eq!(mated.best_move, None);
+        assert_eq!(mated.score, -MATE);
+        let stalemate = best("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(stalemate.best_move, None);
+        assert_eq!(stalemate.score, 0);
+    }
+
+    #[test]
+    fn avoids_stalemating_when_winning() {
+        // Qf7 stalemates, anything sensible keeps the win
+        let result = best("7k/8/6K1/8/8/8/8/5Q2 w - - 0 1", 4);
+        assert_ne!(result.best_move.unwrap().to_uci(), "f1f7");
+        assert!(result.score > 0);
+    }
+
+    #[test]
+    fn wide_search_plays_fairy_pieces() {
+        use crate::{fairy::Roster, geometry::BoardGeometry};
+
+        let g = BoardGeometry::CAPABLANCA;
+        let position = |fen| WideGameState::from_fen_with_roster(fen, g, Roster::capablanca());
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+
+        // The archbishop's knight jump mates from b6
+        let state = position("k9/2K7/10/3A6/10/10/10/10 w - - 0 1").unwrap();
+        let result = WideSearcher::new().search(&state, &[], &limits);
+        assert_eq!(result.best_move.unwrap().to, g.square(1, 5));
+        assert_eq!(result.mate_in(), Some(1));
+
+        // ... and takes a loose chancellor
+        let state = position("k9/10/10/5c4/10/4A5/10/K9 w - - 0 1").unwrap();
+        let result = WideSearcher::new().search(&state, &[], &limits);
+        assert_eq!(result.best_move.unwrap().to, g.square(5, 4));
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn wide_search_avoids_repeating_a_won_position() {
+        use crate::geometry::BoardGeometry;
+
+        let state =
+            WideGameState::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 10 30", BoardGeometry::STANDARD)
+                .unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let first = WideSearcher::new().search(&state, &[], &limits);
+        let favourite = first.best_move.unwrap();
+
+        // Had the favourite's position come up before, it would be a draw
+        let repeated = state.apply_move(favourite).position_hash();
+        let result = WideSearcher::new().search(&state, &[repeated], &limits);
+        assert_ne!(result.best_move, Some(favourite));
+        assert!(result.score > 500, "{}", result.score);
+    }
+
+    #[test]
+    fn wide_quiescence_does_not_stand_pat_in_check() {
+        use crate::geometry::BoardGeometry;
+
+        // Qxf7 is mate, found only by searching the check it gives
+        let mut state = WideGameState::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+            BoardGeometry::STANDARD,
+        )
+        .unwrap();
+        let mut searcher = WideSearcher::new();
+        let score = searcher.quiescence(&mut state, 0, -INFINITY, INFINITY);
+        assert_eq!(score, MATE - 1);
+    }
+
+    #[test]
+    fn wide_search_wins_by_stalemate_where_the_rules_say_so() {
+        use crate::{
+            fairy::Roster,
+            geometry::BoardGeometry,
+            wide::{WideGameState, WinRules},
+        };
+
+        let roster = Roster::standard();
+        let wins = WinRules {
+            stalemate_wins: true,
+            ..WinRules::default()
+        };
+        let empty = WideGameState::with_rules(
+            BoardGeometry::STANDARD,
+            roster.clone(),
+            roster.promotions(),
+            None,
+            wins,
+        );
+        let state = empty.with_fen("k7/8/2Q5/8/8/8/8/K7 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            WideSearcher::new().search(&state, &[], &limits).mate_in(),
+            Some(1)
+        );
+    }
+}
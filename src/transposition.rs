@@ -0,0 +1,363 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use crate::{
+    game::{Move, MoveFlag},
+    rendering::PieceType,
+    search::{MATE, MAX_PLY},
+};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 65536;
+
+/// How the stored score relates to the true score of the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high: the true score is at least this.
+    Lower,
+    /// The search failed low: the true score is at most this.
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    /// Mate scores are relative to the probing node, as the search expects.
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+/// One slot. The key is stored XORed with the data so that an entry torn by
+/// two threads writing at once fails verification instead of being trusted
+/// ("lockless hashing").
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Two slots sharing one index: the first keeps the deepest (or most recent
+/// search's) result, the second is always overwritten. Deep results survive
+/// long searches while shallow ones near the leaves still get cached.
+#[derive(Default)]
+struct Bucket {
+    depth_preferred: Slot,
+    always: Slot,
+}
+
+/// Fixed-size hash table of search results keyed by Zobrist hash. All methods
+/// take `&self`, so one table can be shared between search threads.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+// Layout of the packed data word
+const MOVE_SHIFT: u32 = 0;
+const SCORE_SHIFT: u32 = 16;
+const DEPTH_SHIFT: u32 = 32;
+const BOUND_SHIFT: u32 = 40;
+const GENERATION_SHIFT: u32 = 48;
+
+impl TranspositionTable {
+    /// A table using at most `mb` megabytes (and always at least one bucket).
+    pub fn new(mb: usize) -> TranspositionTable {
+        let count = (mb.min(MAX_HASH_MB) * 1024 * 1024 / size_of::<Bucket>()).max(1);
+        TranspositionTable {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn size_mb(&self) -> usize {
+        self.buckets.len() * size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            for slot in [&bucket.depth_preferred, &bucket.always] {
+                slot.key.store(0, Ordering::Relaxed);
+                slot.data.store(0, Ordering::Relaxed);
+            }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the entries of earlier searches as stale, letting new results
+    /// replace them regardless of depth.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, hash: u64, ply: usize) -> Option<TtEntry> {
+        let bucket = self.bucket(hash);
+        [&bucket.depth_preferred, &bucket.always]
+            .into_iter()
+            .find_map(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                if data == 0 || slot.key.load(Ordering::Relaxed) ^ data != hash {
+                    return None;
+                }
+                let mut entry = unpack(data);
+                entry.score = score_from_tt(entry.score, ply);
+                Some(entry)
+            })
+    }
+
+    /// Stores a search result for the position `hash`, found `ply` plies
+    /// from the root.
+    pub fn store(
+        &self,
+        hash: u64,
+        depth: u32,
+        ply: usize,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        let bucket = self.bucket(hash);
+        let generation = self.generation.load(Ordering::Relaxed);
+
+        let preferred = &bucket.depth_preferred;
+        let old = preferred.data.load(Ordering::Relaxed);
+        let same_position = old != 0 && preferred.key.load(Ordering::Relaxed) ^ old == hash;
+        let replace_preferred = old == 0
+            || same_position
+            || (old >> GENERATION_SHIFT) as u8 != generation
+            || depth >= (old >> DEPTH_SHIFT & 0xFF) as u32;
+
+        let slot = if replace_preferred {
+            preferred
+        } else {
+            &bucket.always
+        };
+
+        // A result without a move (failed low) shouldn't erase a known good move
+        let best_move = best_move.or_else(|| {
+            let data = slot.data.load(Ordering::Relaxed);
+            (data != 0 && slot.key.load(Ordering::Relaxed) ^ data == hash)
+                .then(|| decode_move(data as u16))
+                .flatten()
+        });
+
+        let data = pack(best_move, score_to_tt(score, ply), depth, bound, generation);
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// Permille of slots holding a result of the current search, sampled
+    /// from the start of the table (the UCI `hashfull` figure).
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = &self.buckets[..self.buckets.len().min(500)];
+        let used = sample
+            .iter()
+            .flat_map(|b| [&b.depth_preferred, &b.always])
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && (data >> GENERATION_SHIFT) as u8 == generation
+            })
+            .count();
+        used * 1000 / (sample.len() * 2)
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        // Multiply-shift maps the hash onto any table length without a modulo
+        let index = ((hash as u128 * self.buckets.len() as u128) >> 64) as usize;
+        &self.buckets[index]
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(DEFAULT_HASH_MB)
+    }
+}
+
+/// Mate scores are stored relative to the node rather than the root, so that
+/// they stay correct when the position is reached at another ply.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -(MATE - MAX_PLY as i32) {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+fn pack(best_move: Option<Move>, score: i32, depth: u32, bound: Bound, generation: u8) -> u64 {
+    let bound = match bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    (best_move.map_or(0, encode_move) as u64) << MOVE_SHIFT
+        | (score as i16 as u16 as u64) << SCORE_SHIFT
+        | (depth.min(255) as u64) << DEPTH_SHIFT
+        | bound << BOUND_SHIFT
+        | (generation as u64) << GENERATION_SHIFT
+}
+
+fn unpack(data: u64) -> TtEntry {
+    TtEntry {
+        best_move: decode_move((data >> MOVE_SHIFT) as u16),
+        score: (data >> SCORE_SHIFT) as u16 as i16 as i32,
+        depth: (data >> DEPTH_SHIFT & 0xFF) as u32,
+        bound: match data >> BOUND_SHIFT & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            _ => Bound::Upper,
+        },
+    }
+}
+
+const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+/// 6 bits from, 6 bits to, 4 bits flag. Zero (a1a1) is never a real move and
+/// stands for "no move".
+fn encode_move(mv: Move) -> u16 {
+    let flag = match mv.flag {
+        MoveFlag::Quiet => 0,
+        MoveFlag::DoublePawnPush => 1,
+        MoveFlag::KingsideCastle => 2,
+        MoveFlag::QueensideCastle => 3,
+        MoveFlag::Capture => 4,
+        MoveFlag::EnPassant => 5,
+        MoveFlag::Promotion(pt) => 8 + promotion_index(pt),
+        MoveFlag::PromotionCapture(pt) => 12 + promotion_index(pt),
+    };
+    mv.from as u16 | (mv.to as u16) << 6 | flag << 12
+}
+
+fn promotion_index(pt: PieceType) -> u16 {
+    PROMOTION_PIECES
+        .iter()
+        .position(|&p| p == pt)
+        .expect("promotion to a pawn or king") as u16
+}
+
+fn decode_move(bits: u16) -> Option<Move> {
+    if bits == 0 {
+        return None;
+    }
+    let flag = match bits >> 12 {
+        0 => MoveFlag::Quiet,
+        1 => MoveFlag::DoublePawnPush,
+        2 => MoveFlag::KingsideCastle,
+        3 => MoveFlag::QueensideCastle,
+        4 => MoveFlag::Capture,
+        5 => MoveFlag::EnPassant,
+        f @ 8..=11 => MoveFlag::Promotion(PROMOTION_PIECES[f as usize - 8]),
+        f @ 12..=15 => MoveFlag::PromotionCapture(PROMOTION_PIECES[f as usize - 12]),
+        _ => return None,
+    };
+    Some(Move {
+        from: (bits & 0x3F) as u8,
+        to: (bits >> 6 & 0x3F) as u8,
+        flag,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::GameState, movegen::generate_legal_moves};
+
+    #[test]
+    fn moves_survive_encoding() {
+        for fen in [
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/3pP3/8/8/8/k6K w - d6 0 1",
+        ] {
+            let state = GameState::from_fen(fen).unwrap();
+            for mv in generate_legal_moves(&state) {
+                assert_eq!(decode_move(encode_move(mv)), Some(mv));
+            }
+        }
+    }
+
+    #[test]
+    fn stores_and_probes() {
+        let tt = TranspositionTable::new(1);
+        let mv = Move {
+            from: 12,
+            to: 28,
+            flag: MoveFlag::DoublePawnPush,
+        };
+        assert_eq!(tt.probe(42, 0), None);
+
+        tt.store(42, 7, 0, -123, Bound::Lower, Some(mv));
+        assert_eq!(
+            tt.probe(42, 0),
+            Some(TtEntry {
+                best_move: Some(mv),
+                score: -123,
+                depth: 7,
+                bound: Bound::Lower,
+            })
+        );
+        assert_eq!(tt.probe(43, 0), None);
+
+        // A fail-low without a move keeps the move already known
+        tt.store(42, 8, 0, -200, Bound::Upper, None);
+        assert_eq!(tt.probe(42, 0).unwrap().best_move, Some(mv));
+
+        tt.clear();
+        assert_eq!(tt.probe(42, 0), None);
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        let tt = TranspositionTable::new(1);
+        // Mate in 3 plies from a node 5 plies deep is mate in 8 from the root
+        tt.store(1, 4, 5, MATE - 8, Bound::Exact, None);
+        assert_eq!(tt.probe(1, 5).unwrap().score, MATE - 8);
+        // Reached 2 plies from the root, the same node is mate in 5
+        assert_eq!(tt.probe(1, 2).unwrap().score, MATE - 5);
+
+        tt.store(2, 4, 3, -MATE + 6, Bound::Exact, None);
+        assert_eq!(tt.probe(2, 1).unwrap().score, -MATE + 4);
+    }
+
+    #[test]
+    fn replacement_keeps_deep_entries() {
+        // A single bucket, so every hash collides
+        let tt = TranspositionTable::new(0);
+        tt.store(1, 10, 0, 5, Bound::Exact, None);
+        tt.store(2, 3, 0, 6, Bound::Exact, None);
+        tt.store(3, 2, 0, 7, Bound::Exact, None);
+        assert_eq!(tt.probe(1, 0).unwrap().depth, 10);
+        assert_eq!(tt.probe(2, 0), None);
+        assert_eq!(tt.probe(3, 0).unwrap().depth, 2);
+
+        // Entries from an older search give way even to shallow results
+        tt.new_search();
+        tt.store(4, 1, 0, 8, Bound::Exact, None);
+        assert_eq!(tt.probe(1, 0), None);
+        assert_eq!(tt.probe(4, 0).unwrap().depth, 1);
+    }
+
+    #[test]
+    fn sized_in_megabytes() {
+        assert_eq!(TranspositionTable::new(4).size_mb(), 4);
+        assert_eq!(TranspositionTable::new(0).buckets.len(), 1);
+    }
+}
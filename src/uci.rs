@@ -0,0 +1,232 @@
+mod client;
+mod engine;
+
+pub use self::client::*;
+pub use self::engine::*;
+
+use std::fmt;
+
+use crate::{
+    board::BoardCoordinates,
+    game::{GameState, Move, MoveFlag},
+    movegen::generate_legal_moves,
+    rendering::PieceType,
+    wide::{WideGameState, WideMove},
+};
+
+/// Reason a UCI coordinate move (`e2e4`, `e7e8q`) could not be turned into a move.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UciMoveError {
+    InvalidSyntax(String),
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciMoveError::InvalidSyntax(s) => write!(f, "invalid UCI move syntax: '{s}'"),
+            UciMoveError::IllegalMove(s) => write!(f, "illegal move: '{s}'"),
+        }
+    }
+}
+
+impl std::error::Error for UciMoveError {}
+
+/// Formats the move in UCI long algebraic notation. Castling is written as the
+/// king's two-square move (`e1g1`).
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            BoardCoordinates::from_bit(self.from),
+            BoardCoordinates::from_bit(self.to)
+        )?;
+        if let MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) = self.flag {
+            write!(f, "{}", pt.to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl Move {
+    pub fn to_uci(&self) -> String {
+        self.to_string()
+    }
+
+    /// UCI notation as used in `UCI_Chess960` mode, where castling is written
+    /// as the king taking its own rook (`e1h1`): a Chess960 king's destination
+    /// alone may be ambiguous, or the square it already stands on.
+    pub fn to_uci_chess960(&self, state: &GameState) -> String {
+        match state.castling_rook_squares(self.flag, state.side_to_move) {
+            Some((rook, _)) => format!(
+                "{}{}",
+                BoardCoordinates::from_bit(self.from),
+                BoardCoordinates::from_bit(rook)
+            ),
+            None => self.to_string(),
+        }
+    }
+
+    /// Parses a UCI coordinate move, recovering its flag from the legal moves of `state`.
+    /// Castling is accepted both as the king's move and as the king taking its
+    /// rook, as Chess960 GUIs send it.
+    pub fn from_uci(uci: &str, state: &GameState) -> Result<Move, UciMoveError> {
+        let syntax_error = || UciMoveError::InvalidSyntax(uci.to_string());
+
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(syntax_error());
+        }
+        let from = BoardCoordinates::from_algebraic(&uci[0..2]).ok_or_else(syntax_error)?;
+        let to = BoardCoordinates::from_algebraic(&uci[2..4]).ok_or_else(syntax_error)?;
+        let promotion = match uci[4..].chars().next() {
+            None => None,
+            Some(c @ ('q' | 'r' | 'b' | 'n')) => PieceType::from_char(c),
+            Some(_) => return Err(syntax_error()),
+        };
+
+        generate_legal_moves(state)
+            .into_iter()
+            .find(|mv| {
+                let mv_promotion = match mv.flag {
+                    MoveFlag::Promotion(pt) | MoveFlag::PromotionCapture(pt) => Some(pt),
+                    _ => None,
+                };
+                let rook = state
+                    .castling_rook_squares(mv.flag, state.side_to_move)
+                    .map(|(rook, _)| rook);
+                mv.from == from.to_bit()
+                    && (mv.to == to.to_bit() || rook == Some(to.to_bit()))
+                    && mv_promotion == promotion
+            })
+            .ok_or_else(|| UciMoveError::IllegalMove(uci.to_string()))
+    }
+}
+
+impl WideMove {
+    /// UCI notation on a board of any size: squares as `BoardCoordinates`
+    /// names them (`j10`), promotions by the lowercase letter of the roster
+    /// piece. Castling is written as the royal piece's own move.
+    pub fn to_uci_in(&self, state: &WideGameState) -> String {
+        let g = state.geometry();
+        let mut uci = format!(
+            "{}{}",
+            BoardCoordinates::from_square(self.from, g),
+            BoardCoordinates::from_square(self.to, g)
+        );
+        if let MoveFlag::Promotion(id) | MoveFlag::PromotionCapture(id) = self.flag {
+            uci.push(state.roster().get(id).letter.to_ascii_lowercase());
+        }
+        uci
+    }
+
+    /// Reads `to_uci_in` notation back, as one of the legal moves of `state`.
+    pub fn from_uci_in(uci: &str, state: &WideGameState) -> Result<WideMove, UciMoveError> {
+        state
+            .generate_legal_moves()
+            .into_iter()
+            .find(|mv| mv.to_uci_in(state) == uci)
+            .ok_or_else(|| UciMoveError::IllegalMove(uci.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_move_flags() {
+        let start = GameState::default();
+        assert_eq!(
+            Move::from_uci("e2e4", &start),
+            Ok(Move {
+                from: 12,
+                to: 28,
+                flag: MoveFlag::DoublePawnPush
+            })
+        );
+        assert_eq!(
+            Move::from_uci("g1f3", &start).unwrap().flag,
+            MoveFlag::Quiet
+        );
+
+        let castles = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(
+            Move::from_uci("e8g8", &castles).unwrap().flag,
+            MoveFlag::KingsideCastle
+        );
+        assert_eq!(
+            Move::from_uci("e8c8", &castles).unwrap().flag,
+            MoveFlag::QueensideCastle
+        );
+
+        let ep = GameState::from_fen("8/8/8/3pP3/8/8/8/k6K w - d6 0 1").unwrap();
+        assert_eq!(
+            Move::from_uci("e5d6", &ep).unwrap().flag,
+            MoveFlag::EnPassant
+        );
+
+        let promo = GameState::from_fen("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            Move::from_uci("e7d8n", &promo).unwrap().flag,
+            MoveFlag::PromotionCapture(PieceType::Knight)
+        );
+    }
+
+    #[test]
+    fn round_trips_every_legal_move() {
+        let state =
+            GameState::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1")
+                .unwrap();
+        for mv in generate_legal_moves(&state) {
+            assert_eq!(Move::from_uci(&mv.to_uci(), &state), Ok(mv));
+        }
+    }
+
+    #[test]
+    fn chess960_castling_is_the_king_taking_its_rook() {
+        let state = GameState::from_fen("4k3/8/8/8/8/8/8/RK4R1 w GA - 0 1").unwrap();
+        let castles: Vec<Move> = generate_legal_moves(&state)
+            .into_iter()
+            .filter(|mv| {
+                matches!(
+                    mv.flag,
+                    MoveFlag::KingsideCastle | MoveFlag::QueensideCastle
+                )
+            })
+            .collect();
+        let notation: Vec<String> = castles
+            .iter()
+            .map(|mv| mv.to_uci_chess960(&state))
+            .collect();
+        assert_eq!(notation, ["b1g1", "b1a1"]);
+        for (mv, uci) in castles.iter().zip(&notation) {
+            assert_eq!(Move::from_uci(uci, &state), Ok(*mv));
+        }
+        // The king's plain move to c1 keeps its standard notation
+        assert_eq!(
+            Move::from_uci("b1c1", &state).unwrap().flag,
+            MoveFlag::Quiet
+        );
+    }
+
+    #[test]
+    fn rejects_bad_moves() {
+        let start = GameState::default();
+        for bad in ["", "e2", "e2e4e", "e2e9", "i2i4", "e2e4x", "e2e4q"] {
+            assert!(Move::from_uci(bad, &start).is_err(), "{bad}");
+        }
+        assert_eq!(
+            Move::from_uci("e2e5", &start),
+            Err(UciMoveError::IllegalMove("e2e5".to_string()))
+        );
+        let promo = GameState::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(Move::from_uci("a7a8", &promo).is_err());
+        assert_eq!(
+            promo
+                .apply_move(Move::from_uci("a7a8q", &promo).unwrap())
+                .to_fen(),
+            "Q3k3/8/8/8/8/8/8/4K3 b - - 0 1"
+        );
+    }
+}
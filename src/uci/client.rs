@@ -0,0 +1,556 @@
+use std::{
+    ffi::OsStr,
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        Mutex,
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::ecs::resource::Resource;
+
+use crate::{
+    fen::START_FEN,
+    game::{GameState, Move},
+    history::GameHistory,
+};
+
+/// How long an engine gets to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long an engine gets to exit after `quit` before it is killed.
+const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum UciClientError {
+    Io(io::Error),
+    /// The engine didn't answer in time; names what we were waiting for.
+    Timeout(&'static str),
+    /// The engine closed its output (it exited or crashed).
+    Disconnected,
+}
+
+impl fmt::Display for UciClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciClientError::Io(err) => write!(f, "engine I/O error: {err}"),
+            UciClientError::Timeout(what) => write!(f, "timed out waiting for '{what}'"),
+            UciClientError::Disconnected => write!(f, "engine disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for UciClientError {}
+
+impl From<io::Error> for UciClientError {
+    fn from(err: io::Error) -> Self {
+        UciClientError::Io(err)
+    }
+}
+
+/// Arguments of a `go` command. Times are for White and Black.
+#[derive(Clone, Debug, Default)]
+pub struct GoOptions {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+}
+
+impl GoOptions {
+    fn to_command(&self) -> String {
+        let mut command = "go".to_string();
+        let mut push = |name: &str, value: Option<u128>| {
+            if let Some(value) = value {
+                command.push_str(&format!(" {name} {value}"));
+            }
+        };
+        push("depth", self.depth.map(u128::from));
+        push("nodes", self.nodes.map(u128::from));
+        push("movetime", self.movetime.map(|t| t.as_millis()));
+        push("wtime", self.wtime.map(|t| t.as_millis()));
+        push("btime", self.btime.map(|t| t.as_millis()));
+        push("winc", self.winc.map(|t| t.as_millis()));
+        push("binc", self.binc.map(|t| t.as_millis()));
+        push("movestogo", self.movestogo.map(u128::from));
+        if self.infinite {
+            command.push_str(" infinite");
+        }
+        command
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineScore {
+    Centipawns(i32),
+    /// Moves until mate, negative when the engine is getting mated.
+    Mate(i32),
+}
+
+/// The fields of an `info` line we understand. Everything is optional; an
+/// engine sends whatever it likes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<EngineScore>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<Duration>,
+    /// The legal prefix of the principal variation.
+    pub pv: Vec<Move>,
+    pub string: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineEvent {
+    Info(EngineInfo),
+    /// `None` if the engine had no legal move to play (`bestmove 0000`).
+    BestMove {
+        best: Option<Move>,
+        ponder: Option<Move>,
+    },
+}
+
+/// A UCI engine running as a child process.
+///
+/// Output is read on a background thread, so `poll` never blocks and can be
+/// called every frame from a Bevy system; the blocking helpers are for
+/// library and command-line use.
+#[derive(Resource)]
+pub struct UciClient {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Mutex<Receiver<String>>,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// Raw `option` declarations, e.g. `name Hash type spin default 16 min 1 max 1024`.
+    pub options: Vec<String>,
+    /// Position sent with the last `set_position`, used to read moves back.
+    position: GameState,
+}
+
+impl UciClient {
+    /// Starts `program` and performs the `uci`/`isready` handshake.
+    pub fn spawn<S: AsRef<OsStr>>(program: S, args: &[S]) -> Result<UciClient, UciClientError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = UciClient {
+            child,
+            stdin,
+            lines: Mutex::new(receiver),
+            name: None,
+            author: None,
+            options: Vec::new(),
+            position: GameState::default(),
+        };
+        client.handshake()?;
+        Ok(client)
+    }
+
+    fn handshake(&mut self) -> Result<(), UciClientError> {
+        self.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = self.recv_until(deadline, "uciok")?;
+            let line = line.trim();
+            if line == "uciok" {
+                break;
+            } else if let Some(name) = line.strip_prefix("id name ") {
+                self.name = Some(name.to_string());
+            } else if let Some(author) = line.strip_prefix("id author ") {
+                self.author = Some(author.to_string());
+            } else if let Some(option) = line.strip_prefix("option ") {
+                self.options.push(option.to_string());
+            }
+        }
+        self.wait_ready()
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), UciClientError> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Sends `isready` and waits for `readyok`, dropping whatever comes before.
+    pub fn wait_ready(&mut self) -> Result<(), UciClientError> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.recv_until(deadline, "readyok")?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), UciClientError> {
+        match value {
+            Some(value) => self.send(&format!("setoption name {name} value {value}")),
+            None => self.send(&format!("setoption name {name}")),
+        }
+    }
+
+    pub fn new_game(&mut self) -> Result<(), UciClientError> {
+        self.send("ucinewgame")?;
+        self.wait_ready()
+    }
+
+    /// Sends the game as its initial position plus the moves played since,
+    /// so the engine sees the history it needs for repetitions.
+    pub fn set_position(
+        &mut self,
+        initial: &GameState,
+        moves: &[Move],
+    ) -> Result<(), UciClientError> {
+        let fen = initial.to_fen();
+        let mut command = if fen == START_FEN {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {fen}")
+        };
+        let mut position = initial.clone();
+        if !moves.is_empty() {
+            command.push_str(" moves");
+            for mv in moves {
+                command.push_str(&format!(" {mv}"));
+                position = position.apply_move(*mv);
+            }
+        }
+        self.send(&command)?;
+        self.position = position;
+        Ok(())
+    }
+
+    /// `set_position` for the moves leading to the history's current position.
+    pub fn set_history(&mut self, history: &GameHistory) -> Result<(), UciClientError> {
+        self.set_position(history.initial(), history.played_moves())
+    }
+
+    pub fn go(&mut self, options: &GoOptions) -> Result<(), UciClientError> {
+        self.send(&options.to_command())
+    }
+
+    pub fn stop(&mut self) -> Result<(), UciClientError> {
+        self.send("stop")
+    }
+
+    /// Next event if one has arrived, without blocking. Lines that are not
+    /// `info` or `bestmove` are skipped.
+    pub fn poll(&mut self) -> Result<Option<EngineEvent>, UciClientError> {
+        loop {
+            let line = match self
+                .lines
+                .get_mut()
+                .expect("line receiver poisoned")
+                .try_recv()
+            {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(UciClientError::Disconnected),
+            };
+            if let Some(event) = self.parse_event(&line) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Waits for the result of the running search, handing each `info` to
+    /// `on_info`. Without a timeout it waits as long as the engine thinks.
+    pub fn wait_best_move(
+        &mut self,
+        timeout: Option<Duration>,
+        mut on_info: impl FnMut(&EngineInfo),
+    ) -> Result<(Option<Move>, Option<Move>), UciClientError> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let line = match deadline {
+                Some(deadline) => self.recv_until(deadline, "bestmove")?,
+                None => self
+                    .lines
+                    .get_mut()
+                    .expect("line receiver poisoned")
+                    .recv()
+                    .map_err(|_| UciClientError::Disconnected)?,
+            };
+            match self.parse_event(&line) {
+                Some(EngineEvent::Info(info)) => on_info(&info),
+                Some(EngineEvent::BestMove { best, ponder }) => return Ok((best, ponder)),
+                None => {}
+            }
+        }
+    }
+
+    /// Sends `quit` and waits briefly for the engine to exit, killing it otherwise.
+    pub fn quit(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn recv_until(
+        &mut self,
+        deadline: Instant,
+        waiting_for: &'static str,
+    ) -> Result<String, UciClientError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self
+            .lines
+            .get_mut()
+            .expect("line receiver poisoned")
+            .recv_timeout(timeout)
+        {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(UciClientError::Timeout(waiting_for)),
+            Err(RecvTimeoutError::Disconnected) => Err(UciClientError::Disconnected),
+        }
+    }
+
+    fn parse_event(&self, line: &str) -> Option<EngineEvent> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next()? {
+            "info" => Some(EngineEvent::Info(parse_info(tokens, &self.position))),
+            "bestmove" => {
+                let best = tokens
+                    .next()
+                    .and_then(|uci| Move::from_uci(uci, &self.position).ok());
+                let ponder = match (best, tokens.next(), tokens.next()) {
+                    (Some(best), Some("ponder"), Some(uci)) => {
+                        Move::from_uci(uci, &self.position.apply_move(best)).ok()
+                    }
+                    _ => None,
+                };
+                Some(EngineEvent::BestMove { best, ponder })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for UciClient {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            self.shutdown();
+        }
+    }
+}
+
+fn parse_info<'a>(mut tokens: impl Iterator<Item = &'a str>, position: &GameState) -> EngineInfo {
+    let mut info = EngineInfo::default();
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => info.depth = tokens.next().and_then(|t| t.parse().ok()),
+            "seldepth" => info.seldepth = tokens.next().and_then(|t| t.parse().ok()),
+            "multipv" => info.multipv = tokens.next().and_then(|t| t.parse().ok()),
+            "nodes" => info.nodes = tokens.next().and_then(|t| t.parse().ok()),
+            "nps" => info.nps = tokens.next().and_then(|t| t.parse().ok()),
+            "time" => {
+                info.time = tokens
+                    .next()
+                    .and_then(|t| t.parse().ok())
+                    .map(Duration::from_millis)
+            }
+            "score" => {
+                info.score = match (tokens.next(), tokens.next().and_then(|t| t.parse().ok())) {
+                    (Some("cp"), Some(cp)) => Some(EngineScore::Centipawns(cp)),
+                    (Some("mate"), Some(moves)) => Some(EngineScore::Mate(moves)),
+                    _ => None,
+                }
+            }
+            // Both run to the end of the line
+            "pv" => {
+                let mut state = position.clone();
+                for uci in tokens.by_ref() {
+                    let Ok(mv) = Move::from_uci(uci, &state) else {
+                        break;
+                    };
+                    state = state.apply_move(mv);
+                    info.pv.push(mv);
+                }
+            }
+            "string" => info.string = Some(tokens.by_ref().collect::<Vec<_>>().join(" ")),
+            _ => {}
+        }
+    }
+    info
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Stand-in engine: answers the handshake, echoes the last position back
+    /// as an `info string` and always plays 1.e4 (or ...e5 after it).
+    const SCRIPT: &str = r#"
+        while read -r line; do
+            case "$line" in
+                uci)
+                    echo "id name Stand-in"
+                    echo "id author Test Suite"
+                    echo "option name Hash type spin default 1 min 1 max 8"
+                    echo "uciok" ;;
+                isready) echo "readyok" ;;
+                position*) position="$line" ;;
+                go*)
+                    echo "info string $position"
+                    echo "info string $line"
+                    case "$position" in
+                        *e2e4) echo "info depth 2 score mate -3 pv e7e5 g1f3"
+                               echo "bestmove e7e5" ;;
+                        *) echo "info depth 1 seldepth 3 score cp 25 nodes 40 nps 4000 time 10 pv e2e4 e7e5 e1e8"
+                           echo "bestmove e2e4 ponder e7e5" ;;
+                    esac ;;
+                quit) exit 0 ;;
+            esac
+        done
+    "#;
+
+    fn stand_in() -> UciClient {
+        UciClient::spawn("sh", &["-c", SCRIPT]).unwrap()
+    }
+
+    #[test]
+    fn handshake_reads_identity() {
+        let client = stand_in();
+        assert_eq!(client.name.as_deref(), Some("Stand-in"));
+        assert_eq!(client.author.as_deref(), Some("Test Suite"));
+        assert_eq!(
+            client.options,
+            ["name Hash type spin default 1 min 1 max 8"]
+        );
+        client.quit();
+    }
+
+    #[test]
+    fn sends_position_and_reads_best_move() {
+        let mut client = stand_in();
+        client.set_position(&GameState::default(), &[]).unwrap();
+        client
+            .go(&GoOptions {
+                depth: Some(4),
+                movetime: Some(Duration::from_millis(250)),
+                ..GoOptions::default()
+            })
+            .unwrap();
+
+        let mut infos = Vec::new();
+        let (best, ponder) = client
+            .wait_best_move(Some(Duration::from_secs(5)), |info| {
+                infos.push(info.clone())
+            })
+            .unwrap();
+        assert_eq!(best.map(|mv| mv.to_uci()).as_deref(), Some("e2e4"));
+        assert_eq!(ponder.map(|mv| mv.to_uci()).as_deref(), Some("e7e5"));
+
+        assert_eq!(infos[0].string.as_deref(), Some("position startpos"));
+        assert_eq!(infos[1].string.as_deref(), Some("go depth 4 movetime 250"));
+        let search = &infos[2];
+        assert_eq!(search.depth, Some(1));
+        assert_eq!(search.seldepth, Some(3));
+        assert_eq!(search.score, Some(EngineScore::Centipawns(25)));
+        assert_eq!(search.nodes, Some(40));
+        assert_eq!(search.time, Some(Duration::from_millis(10)));
+        // The illegal third move cuts the PV short
+        assert_eq!(search.pv.len(), 2);
+    }
+
+    #[test]
+    fn sends_game_history_and_polls() {
+        let mut client = stand_in();
+        client.new_game().unwrap();
+        let mut history = GameHistory::new(
+            GameState::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2")
+                .unwrap(),
+        );
+        history.push(Move::from_uci("e2e4", history.current()).unwrap());
+        client.set_history(&history).unwrap();
+        client
+            .go(&GoOptions {
+                infinite: true,
+                ..GoOptions::default()
+            })
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = Vec::new();
+        while !matches!(events.last(), Some(EngineEvent::BestMove { .. })) {
+            assert!(Instant::now() < deadline, "no bestmove from stand-in");
+            match client.poll().unwrap() {
+                Some(event) => events.push(event),
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        assert_eq!(
+            events[0],
+            EngineEvent::Info(EngineInfo {
+                string: Some(
+                    "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2 moves e2e4"
+                        .to_string()
+                ),
+                ..EngineInfo::default()
+            })
+        );
+        let EngineEvent::Info(search) = &events[2] else {
+            panic!()
+        };
+        assert_eq!(search.score, Some(EngineScore::Mate(-3)));
+        assert_eq!(
+            search.pv.iter().map(Move::to_uci).collect::<Vec<_>>(),
+            ["e7e5", "g1f3"]
+        );
+        let EngineEvent::BestMove { best, ponder } = events[3] else {
+            panic!()
+        };
+        assert_eq!(best.map(|mv| mv.to_uci()).as_deref(), Some("e7e5"));
+        assert_eq!(ponder, None);
+    }
+
+    #[test]
+    fn reports_missing_engine() {
+        assert!(matches!(
+            UciClient::spawn("/nonexistent/engine", &[]),
+            Err(UciClientError::Io(_))
+        ));
+        // A program that exits without speaking UCI: depending on timing the
+        // `uci` write hits a closed pipe or the output just ends
+        assert!(matches!(
+            UciClient::spawn("true", &[]),
+            Err(UciClientError::Disconnected | UciClientError::Io(_))
+        ));
+    }
+}
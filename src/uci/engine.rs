@@ -0,0 +1,780 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    game::{GameState, Move, Rules},
+    rendering::PieceColor,
+    search::{SearchLimits, SearchResult, Searcher, WideSearcher},
+    transposition::{DEFAULT_HASH_MB, MAX_HASH_MB},
+    variant::Variant,
+    wide::{WideGameState, WideMove},
+};
+
+pub const ENGINE_NAME: &str = "Enhanced Chess";
+pub const ENGINE_AUTHOR: &str = "Hedgestock";
+
+/// Kept in hand when playing on the clock, for I/O and GUI lag.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+/// Moves the remaining time is spread over when the GUI doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Runs a UCI session: reads commands from `input` until `quit` or end of
+/// input, writing responses to `output`.
+///
+/// Searches run on a background thread so that `stop` and `isready` are
+/// answered while thinking. At end of input a running search is allowed to
+/// finish (or stopped, if it is `go infinite`) so that scripted sessions
+/// still get their `bestmove`.
+pub fn run(input: impl BufRead, output: impl Write + Send + 'static) -> io::Result<()> {
+    run_session(input, Engine::new(Box::new(output), None))
+}
+
+/// Like `run`, also offering `variant` as a value of the `UCI_Variant`
+/// option. Positions and moves are then read under its rules, with squares
+/// and promotions written as `WideMove::to_uci_in` writes them.
+pub fn run_variant(
+    input: impl BufRead,
+    output: impl Write + Send + 'static,
+    variant: Variant,
+) -> io::Result<()> {
+    run_session(input, Engine::new(Box::new(output), Some(variant)))
+}
+
+fn run_session(input: impl BufRead, mut engine: Engine) -> io::Result<()> {
+    for line in input.lines() {
+        if !engine.handle(&line?)? {
+            return Ok(());
+        }
+    }
+    engine.finish_search(false);
+    Ok(())
+}
+
+struct Engine {
+    out: Output,
+    position: GameState,
+    /// Hashes of the positions before `position`, or before
+    /// `variant_position` while it is played, for repetition detection.
+    previous_hashes: Vec<u64>,
+    /// `None` while a search thread owns it.
+    searcher: Option<Searcher>,
+    search: Option<SearchThread>,
+    stop: Arc<AtomicBool>,
+    /// `UCI_Chess960`: castling is written as the king taking its rook.
+    chess960: bool,
+    /// `UCI_Variant`: what `position` is played under, e.g. atomic chess.
+    rules: Rules,
+    /// Rules given on the command line, selectable with `UCI_Variant`.
+    variant: Option<Variant>,
+    /// The position under `variant`'s rules while `UCI_Variant` selects it,
+    /// in place of `position`.
+    variant_position: Option<WideGameState>,
+}
+
+struct SearchThread {
+    /// Gives back the searcher it took; variant searches borrow none.
+    handle: JoinHandle<Option<Searcher>>,
+    infinite: bool,
+}
+
+impl Engine {
+    fn new(out: Box<dyn Write + Send>, variant: Option<Variant>) -> Engine {
+        let searcher = Searcher::new();
+        Engine {
+            out: Arc::new(Mutex::new(out)),
+            position: GameState::default(),
+            previous_hashes: Vec::new(),
+            stop: searcher.stop_handle(),
+            searcher: Some(searcher),
+            search: None,
+            chess960: false,
+            rules: Rules::Standard,
+            variant,
+            variant_position: None,
+        }
+    }
+
+    /// Handles one command line. Returns `false` on `quit`.
+    fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match command {
+            "uci" => {
+                send(&self.out, &format!("id name {ENGINE_NAME}"))?;
+                send(&self.out, &format!("id author {ENGINE_AUTHOR}"))?;
+                send(
+                    &self.out,
+                    &format!(
+                        "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}"
+                    ),
+                )?;
+                send(&self.out, "option name Clear Hash type button")?;
+                send(
+                    &self.out,
+                    "option name UCI_Chess960 type check default false",
+                )?;
+                let mut variants = "var chess var atomic".to_string();
+                if let Some(variant) = &self.variant {
+                    variants.push_str(&format!(" var {}", uci_variant_name(variant)));
+                }
+                send(
+                    &self.out,
+                    &format!("option name UCI_Variant type combo default chess {variants}"),
+                )?;
+                send(&self.out, "uciok")?;
+            }
+            "isready" => send(&self.out, "readyok")?,
+            "ucinewgame" => {
+                self.finish_search(true);
+                self.searcher().table().clear();
+                self.position = GameState::default();
+                self.position.set_rules(self.rules);
+                self.previous_hashes.clear();
+                if let (Some(variant), Some(position)) = (&self.variant, &mut self.variant_position)
+                {
+                    *position = variant.start_position();
+                }
+            }
+            "setoption" => self.set_option(&args)?,
+            "position" => self.set_position(&args)?,
+            "go" => self.go(&args),
+            "stop" => self.finish_search(true),
+            "quit" => {
+                self.finish_search(true);
+                return Ok(false);
+            }
+            // `debug`, `register` and `ponderhit` need no answer
+            "debug" | "register" | "ponderhit" => {}
+            _ => send(&self.out, &format!("info string unknown command: {line}"))?,
+        }
+        Ok(true)
+    }
+
+    fn searcher(&mut self) -> &mut Searcher {
+        self.searcher
+            .as_mut()
+            .expect("searcher is only lent out while a search is running")
+    }
+
+    /// `setoption name <id> [value <x>]`, where the name may contain spaces.
+    fn set_option(&mut self, args: &[&str]) -> io::Result<()> {
+        if args.first() != Some(&"name") {
+            return send(&self.out, "info string invalid setoption command");
+        }
+        // Found past `name`, so the slice below never runs backwards
+        let value_at = args.iter().position(|&t| t == "value");
+        let name = args[1..value_at.unwrap_or(args.len())].join(" ");
+        let value = value_at.map(|i| args[i + 1..].join(" "));
+
+        self.finish_search(true);
+        match (name.to_ascii_lowercase().as_str(), value) {
+            ("hash", Some(value)) => match value.parse::<usize>() {
+                Ok(mb) => self.searcher().set_hash_size(mb.clamp(1, MAX_HASH_MB)),
+                Err(_) => send(
+                    &self.out,
+                    &format!("info string invalid Hash value: {value}"),
+                )?,
+            },
+            ("clear hash", _) => self.searcher().table().clear(),
+            ("uci_chess960", Some(value)) => self.chess960 = value.eq_ignore_ascii_case("true"),
+            ("uci_variant", Some(value)) => self.set_variant(&value)?,
+            _ => send(&self.out, &format!("info string unknown option: {name}"))?,
+        }
+        Ok(())
+    }
+
+    /// `UCI_Variant`: `chess`, `atomic` or the variant given on the command
+    /// line. Starts over from its start position.
+    fn set_variant(&mut self, value: &str) -> io::Result<()> {
+        let name = value.to_ascii_lowercase();
+        let rules = match name.as_str() {
+            "chess" => Rules::Standard,
+            "atomic" => Rules::Atomic,
+            _ => match &self.variant {
+                Some(variant) if name == uci_variant_name(variant) => {
+                    self.variant_position = Some(variant.start_position());
+                    self.previous_hashes.clear();
+                    return Ok(());
+                }
+                _ => return send(&self.out, &format!("info string unknown variant: {value}")),
+            },
+        };
+        self.variant_position = None;
+        self.rules = rules;
+        self.position = GameState::default();
+        self.position.set_rules(rules);
+        self.previous_hashes.clear();
+        Ok(())
+    }
+
+    /// `position (startpos | fen <fen>) [moves <move>...]`. A command with
+    /// a bad FEN or move changes nothing.
+    fn set_position(&mut self, args: &[&str]) -> io::Result<()> {
+        let moves_at = args.iter().position(|&t| t == "moves");
+        let setup = &args[..moves_at.unwrap_or(args.len())];
+        if self.variant_position.is_some() {
+            return self.set_variant_position(args, setup, moves_at);
+        }
+
+        let mut position = match setup {
+            ["startpos"] => GameState::default(),
+            ["fen", fen @ ..] => match GameState::from_fen(&fen.join(" ")) {
+                Ok(state) => state,
+                Err(err) => return send(&self.out, &format!("info string invalid FEN: {err}")),
+            },
+            _ => {
+                return send(
+                    &self.out,
+                    &format!("info string invalid position command: {}", args.join(" ")),
+                );
+            }
+        };
+
+        position.set_rules(self.rules);
+        let mut previous_hashes = Vec::new();
+        for uci in moves_at.map_or(&[][..], |i| &args[i + 1..]) {
+            match Move::from_uci(uci, &position) {
+                Ok(mv) => {
+                    previous_hashes.push(position.hash);
+                    position = position.apply_move(mv);
+                }
+                Err(err) => return send(&self.out, &format!("info string {err}")),
+            }
+        }
+        self.position = position;
+        self.previous_hashes = previous_hashes;
+        Ok(())
+    }
+
+    /// `set_position` under the loaded variant's rules.
+    fn set_variant_position(
+        &mut self,
+        args: &[&str],
+        setup: &[&str],
+        moves_at: Option<usize>,
+    ) -> io::Result<()> {
+        let variant = self.variant.as_ref().expect("selected with UCI_Variant");
+        let mut position = match setup {
+            ["startpos"] => variant.start_position(),
+            ["fen", fen @ ..] => match variant.position(&fen.join(" ")) {
+                Ok(state) => state,
+                Err(err) => return send(&self.out, &format!("info string invalid FEN: {err}")),
+            },
+            _ => {
+                return send(
+                    &self.out,
+                    &format!("info string invalid position command: {}", args.join(" ")),
+                );
+            }
+        };
+
+        let mut previous_hashes = Vec::new();
+        for uci in moves_at.map_or(&[][..], |i| &args[i + 1..]) {
+            match WideMove::from_uci_in(uci, &position) {
+                Ok(mv) => {
+                    previous_hashes.push(position.position_hash());
+                    position.make_move(mv);
+                }
+                Err(err) => return send(&self.out, &format!("info string {err}")),
+            }
+        }
+        self.variant_position = Some(position);
+        self.previous_hashes = previous_hashes;
+        Ok(())
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        self.finish_search(true);
+        if let Some(position) = &self.variant_position {
+            let (limits, infinite) = parse_go(args, position.side_to_move);
+            let state = position.clone();
+            self.go_variant(state, self.previous_hashes.clone(), limits, infinite);
+            return;
+        }
+        let (limits, infinite) = parse_go(args, self.position.side_to_move);
+
+        let mut searcher = self.searcher.take().expect("no search running");
+        let state = self.position.clone();
+        let previous_hashes = self.previous_hashes.clone();
+        let out = self.out.clone();
+        let stop = self.stop.clone();
+        let chess960 = self.chess960;
+        stop.store(false, Ordering::Relaxed);
+
+        let handle = thread::spawn(move || {
+            let table = searcher.table().clone();
+            let line = |moves: &[Move]| uci_line(&state, moves, chess960);
+            let result = searcher.search_with(&state, &previous_hashes, &limits, |result| {
+                let _ = send(
+                    &out,
+                    &info_line(result, table.hashfull(), &line(&result.pv)),
+                );
+            });
+            // `go infinite` may not answer before being told to stop
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let _ = send(&out, &bestmove_line(&result, line));
+            Some(searcher)
+        });
+        self.search = Some(SearchThread { handle, infinite });
+    }
+
+    /// `go` for a position of the loaded variant, searched by a
+    /// `WideSearcher` that `stop` reaches as well.
+    fn go_variant(
+        &mut self,
+        state: WideGameState,
+        previous_hashes: Vec<u64>,
+        limits: SearchLimits,
+        infinite: bool,
+    ) {
+        let out = self.out.clone();
+        let stop = self.stop.clone();
+        stop.store(false, Ordering::Relaxed);
+
+        let handle = thread::spawn(move || {
+            let mut searcher = WideSearcher::with_stop_handle(stop.clone());
+            let line = |moves: &[WideMove]| wide_uci_line(&state, moves);
+            let result = searcher.search_with(&state, &previous_hashes, &limits, |result| {
+                // Variant searches keep no transposition table
+                let _ = send(&out, &info_line(result, 0, &line(&result.pv)));
+            });
+            while infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let _ = send(&out, &bestmove_line(&result, line));
+            None
+        });
+        self.search = Some(SearchThread { handle, infinite });
+    }
+
+    /// Waits for the running search, if any, and takes its searcher back.
+    /// With `stop` (or for an infinite search) it is interrupted first.
+    fn finish_search(&mut self, stop: bool) {
+        if let Some(search) = self.search.take() {
+            if stop || search.infinite {
+                self.stop.store(true, Ordering::Relaxed);
+            }
+            if let Some(searcher) = search.handle.join().expect("search thread panicked") {
+                self.searcher = Some(searcher);
+            }
+        }
+    }
+}
+
+/// Turns the arguments of `go` into search limits, and whether the search is
+/// `infinite` (or `ponder`), i.e. only ends on `stop`.
+fn parse_go(args: &[&str], side_to_move: PieceColor) -> (SearchLimits, bool) {
+    let mut limits = SearchLimits::default();
+    let mut infinite = false;
+    let (mut time, mut increment, mut moves_to_go) = (None, Duration::ZERO, None);
+
+    let mut tokens = args.iter();
+    while let Some(&token) = tokens.next() {
+        let mut number = || tokens.next().and_then(|t| t.parse::<u64>().ok());
+        match token {
+            "depth" => limits.depth = number().map(|d| d as u32),
+            "nodes" => limits.nodes = number(),
+            "movetime" => limits.movetime = number().map(Duration::from_millis),
+            "wtime" | "btime" => {
+                let ours = (token == "wtime") == (side_to_move == PieceColor::White);
+                if let Some(ms) = number().filter(|_| ours) {
+                    time = Some(Duration::from_millis(ms));
+                }
+            }
+            "winc" | "binc" => {
+                let ours = (token == "winc") == (side_to_move == PieceColor::White);
+                if let Some(ms) = number().filter(|_| ours) {
+                    increment = Duration::from_millis(ms);
+                }
+            }
+            "movestogo" => moves_to_go = number().map(|n| n.max(1) as u32),
+            "infinite" | "ponder" => infinite = true,
+            _ => {}
+        }
+    }
+
+    if let Some(time) = time
+        && limits.movetime.is_none()
+        && !infinite
+    {
+        let budget = time / moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO) + increment * 3 / 4;
+        let available = time.saturating_sub(MOVE_OVERHEAD);
+        limits.movetime = Some(budget.min(available).max(Duration::from_millis(1)));
+    }
+    (limits, infinite)
+}
+
+/// `UCI_Variant` value of `variant`: its name in lowercase, without spaces
+/// or punctuation.
+fn uci_variant_name(variant: &Variant) -> String {
+    variant
+        .name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// `pv` is the principal variation already in UCI notation.
+fn info_line<M>(result: &SearchResult<M>, hashfull: usize, pv: &[String]) -> String {
+    let score = match result.mate_in() {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {}", result.score),
+    };
+    let millis = result.elapsed.as_millis() as u64;
+    let nps = result.nodes * 1000 / millis.max(1);
+    format!(
+        "info depth {} score {score} nodes {} nps {nps} time {millis} hashfull {hashfull} pv {}",
+        result.depth,
+        result.nodes,
+        pv.join(" ")
+    )
+}
+
+/// `uci_line` writes a line of moves from the searched position.
+fn bestmove_line<M: Copy + PartialEq>(
+    result: &SearchResult<M>,
+    uci_line: impl Fn(&[M]) -> Vec<String>,
+) -> String {
+    match (result.best_move, result.pv.get(1)) {
+        (Some(best), Some(&ponder)) if result.pv[0] == best => {
+            let line = uci_line(&[best, ponder]);
+            format!("bestmove {} ponder {}", line[0], line[1])
+        }
+        (Some(best), _) => format!("bestmove {}", uci_line(&[best])[0]),
+        // UCI's null move, for positions without any legal move
+        (None, _) => "bestmove 0000".to_string(),
+    }
+}
+
+/// A line of moves played from `state`, in UCI notation. Chess960 castling
+/// notation depends on the position each move is played in.
+fn uci_line(state: &GameState, moves: &[Move], chess960: bool) -> Vec<String> {
+    let mut state = state.clone();
+    moves
+        .iter()
+        .map(|&mv| {
+            let uci = if chess960 {
+                mv.to_uci_chess960(&state)
+            } else {
+                mv.to_uci()
+            };
+            state.make_move(mv);
+            uci
+        })
+        .collect()
+}
+
+/// `uci_line` for a position of a loaded variant.
+fn wide_uci_line(state: &WideGameState, moves: &[WideMove]) -> Vec<String> {
+    let mut state = state.clone();
+    moves
+        .iter()
+        .map(|&mv| {
+            let uci = mv.to_uci_in(&state);
+            state.make_move(mv);
+            uci
+        })
+        .collect()
+}
+
+fn send(out: &Output, line: &str) -> io::Result<()> {
+    let mut out = out.lock().expect("output lock poisoned");
+    writeln!(out, "{line}")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects everything the engine writes.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<String> {
+            let output = self.0.lock().unwrap();
+            String::from_utf8_lossy(&output)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    fn session(script: &str) -> Vec<String> {
+        let capture = Capture::default();
+        run(script.as_bytes(), capture.clone()).unwrap();
+        capture.lines()
+    }
+
+    /// A session with the variant of rule file `ron` loaded.
+    fn variant_session(ron: &str, script: &str) -> Vec<String> {
+        let variant = Variant::from_ron(ron).unwrap();
+        let capture = Capture::default();
+        run_variant(script.as_bytes(), capture.clone(), variant).unwrap();
+        capture.lines()
+    }
+
+    /// A session with Capablanca chess loaded as the variant.
+    fn capablanca_session(script: &str) -> Vec<String> {
+        variant_session(include_str!("../../assets/variants/capablanca.ron"), script)
+    }
+
+    #[test]
+    fn handshake() {
+        let lines = session("uci\nisready\nquit\n");
+        assert!(lines[0].starts_with("id name "));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("option name Hash type spin"))
+        );
+        assert_eq!(lines[lines.len() - 2], "uciok");
+        assert_eq!(lines[lines.len() - 1], "readyok");
+    }
+
+    #[test]
+    fn searches_position_with_moves() {
+        // After 1.e4 e5 2.Bc4 Nc6 3.Qh5 Nf6?? White mates on f7
+        let lines = session(
+            "position startpos moves e2e4 e7e5 f1c4 b8c6 d1h5 g8f6\n\
+             go depth 3\n",
+        );
+        assert!(lines.iter().any(|l| l.starts_with("info depth 1 ")));
+        assert!(lines.iter().any(|l| l.contains("score mate 1")));
+        assert_eq!(lines.last().unwrap(), "bestmove h5f7");
+    }
+
+    #[test]
+    fn searches_fen_position() {
+        let lines = session("position fen 4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1\ngo depth 3\n");
+        assert!(lines.last().unwrap().starts_with("bestmove d1d5"));
+    }
+
+    #[test]
+    fn writes_chess960_castling_in_chess960_mode() {
+        let state = GameState::from_fen("4k3/8/8/8/8/8/8/RK4R1 w GA - 0 1").unwrap();
+        let castle = Move::from_uci("b1a1", &state).unwrap();
+        assert_eq!(uci_line(&state, &[castle], false), ["b1c1"]);
+        assert_eq!(uci_line(&state, &[castle], true), ["b1a1"]);
+
+        let lines = session(
+            "setoption name UCI_Chess960 value true
+             position fen 4k3/8/8/8/8/8/8/RK4R1 w GA - 0 1 moves b1a1
+             go depth 1
+",
+        );
+        assert!(
+            !lines.iter().any(|l| l.starts_with("info string")),
+            "{lines:?}"
+        );
+        assert!(lines.last().unwrap().starts_with("bestmove"));
+    }
+
+    #[test]
+    fn plays_the_loaded_variant() {
+        let lines = capablanca_session("uci\n");
+        assert!(
+            lines.contains(
+                &"option name UCI_Variant type combo default chess var chess var atomic var capablanca"
+                    .to_string()
+            )
+        );
+
+        // The archbishop's knight jump mates from b6
+        let lines = capablanca_session(
+            "setoption name UCI_Variant value capablanca\n\
+             position fen k9/2K7/10/3A6/10/10/10/10 w - - 0 1\n\
+             go depth 3\n",
+        );
+        assert!(lines.iter().any(|l| l.contains("score mate 1")));
+        assert_eq!(lines.last().unwrap(), "bestmove d5b6");
+
+        let lines = capablanca_session(
+            "setoption name UCI_Variant value capablanca\n\
+             position startpos moves j2j4 a7a5\n\
+             go depth 2\n\
+             setoption name UCI_Variant value chess\n\
+             position startpos moves j2j4\n\
+             setoption name UCI_Variant value shogi\n",
+        );
+        assert!(lines.iter().any(|l| l.starts_with("bestmove ")));
+        // Back in orthodox chess there is no j-file
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("info string invalid UCI move syntax"))
+        );
+        assert!(lines.contains(&"info string unknown variant: shogi".to_string()));
+    }
+
+    #[test]
+    fn plays_king_of_the_hill() {
+        // Kd4 wins on the spot, ahead of taking the rook
+        let lines = variant_session(
+            include_str!("../../assets/variants/king_of_the_hill.ron"),
+            "setoption name UCI_Variant value kingofthehill\n\
+             position fen k7/8/8/8/8/2K5/1r6/8 w - - 0 1\n\
+             go depth 2\n",
+        );
+        assert!(
+            lines.iter().any(|l| l.contains("score mate 1")),
+            "{lines:?}"
+        );
+        assert_eq!(lines.last().unwrap(), "bestmove c3d4");
+    }
+
+    #[test]
+    fn plays_atomic_chess() {
+        // Taking the queen blows up the king next to it
+        let position = "position fen 4k3/4q3/8/8/8/8/8/4RK2 w - - 0 1\ngo depth 2\n";
+        let lines = session(&format!(
+            "setoption name UCI_Variant value atomic\n{position}"
+        ));
+        assert!(lines.iter().any(|l| l.contains("score mate 1")));
+        assert_eq!(lines.last().unwrap(), "bestmove e1e7");
+
+        let lines = session(&format!(
+            "setoption name UCI_Variant value atomic\n\
+             setoption name UCI_Variant value chess\n{position}"
+        ));
+        assert_ne!(lines.last().unwrap(), "bestmove e1e7");
+    }
+
+    #[test]
+    fn stops_infinite_search() {
+        let lines = session("position startpos\ngo infinite\nisready\nstop\n");
+        assert!(lines.contains(&"readyok".to_string()));
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        assert_eq!(
+            lines.iter().filter(|l| l.starts_with("bestmove")).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn plays_on_the_clock() {
+        let lines = session("position startpos moves e2e4\ngo wtime 10 btime 300 binc 10\n");
+        let bestmove = lines.last().unwrap();
+        assert!(bestmove.starts_with("bestmove "));
+        let mv = bestmove.split_whitespace().nth(1).unwrap();
+        let state =
+            GameState::default().apply_move(Move::from_uci("e2e4", &GameState::default()).unwrap());
+        assert!(Move::from_uci(mv, &state).is_ok());
+    }
+
+    #[test]
+    fn reports_game_over_and_bad_input() {
+        let lines = session(
+            "setoption name Hash value 2\n\
+             setoption name Clear Hash\n\
+             setoption name Nonsense value 1\n\
+             position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1\n\
+             go depth 2\n\
+             position startpos moves e2e5\n\
+             position fen not a fen\n",
+        );
+        assert!(lines.contains(&"info string unknown option: Nonsense".to_string()));
+        assert!(lines.contains(&"bestmove 0000".to_string()));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("info string illegal move"))
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("info string invalid FEN"))
+        );
+    }
+
+    #[test]
+    fn rejected_position_changes_nothing() {
+        let lines = session(
+            "position fen 4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1\n\
+             position startpos moves e2e4 e7e5 e1e3\n\
+             go depth 3\n",
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("info string illegal move"))
+        );
+        assert!(lines.last().unwrap().starts_with("bestmove d1d5"));
+
+        let lines = capablanca_session(
+            "setoption name UCI_Variant value capablanca\n\
+             position fen k9/2K7/10/3A6/10/10/10/10 w - - 0 1\n\
+             position startpos moves j2j4 a7a5 j4j6\n\
+             go depth 3\n",
+        );
+        assert!(lines.iter().any(|l| l.starts_with("info string ")));
+        assert_eq!(lines.last().unwrap(), "bestmove d5b6");
+    }
+
+    #[test]
+    fn survives_malformed_setoption() {
+        let lines = session("setoption value 5\nsetoption\nisready\n");
+        assert_eq!(
+            lines,
+            [
+                "info string invalid setoption command",
+                "info string invalid setoption command",
+                "readyok"
+            ]
+        );
+    }
+
+    #[test]
+    fn allocates_time() {
+        let (limits, infinite) = parse_go(&["wtime", "60000", "btime", "1000"], PieceColor::White);
+        assert!(!infinite);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(2000)));
+
+        let (limits, _) = parse_go(
+            &[
+                "wtime",
+                "1000",
+                "btime",
+                "60000",
+                "binc",
+                "1000",
+                "movestogo",
+                "10",
+            ],
+            PieceColor::Black,
+        );
+        assert_eq!(limits.movetime, Some(Duration::from_millis(6750)));
+
+        let (limits, _) = parse_go(&["depth", "5", "nodes", "1000"], PieceColor::White);
+        assert_eq!(limits.depth, Some(5));
+        assert_eq!(limits.nodes, Some(1000));
+        assert_eq!(limits.movetime, None);
+    }
+}
@@ -0,0 +1,477 @@
+use std::{collections::HashSet, fmt, fs, io, path::Path};
+
+use bevy::ecs::resource::Resource;
+use serde::Deserialize;
+
+use crate::{
+    betza::BetzaError,
+    bitboard::WideBitBoard,
+    board::BoardCoordinates,
+    eval::piece_value,
+    fairy::{MoveComponent, MoveMode, Movement, PieceDef, PieceId, Roster},
+    fen::FenError,
+    game::{GameState, Promotions},
+    geometry::BoardGeometry,
+    outcome::{DrawReason, GameOutcome},
+    rendering::{PieceColor, PieceType},
+    wide::{CastlingFiles, WideGameState, WinRules},
+};
+
+/// Where the app looks for rules when none are given.
+pub const DEFAULT_RULES: &str = "assets/variants/standard.ron";
+
+/// A variant as written in a rule file, before any of it is checked.
+#[derive(Clone, Debug, Deserialize)]
+pub struct VariantRules {
+    pub name: String,
+    pub width: u8,
+    pub height: u8,
+    pub start_fen: String,
+    pub pieces: Vec<PieceRules>,
+    /// Letters of the pieces a pawn may promote to. Every piece that is
+    /// neither a pawn nor royal when left out.
+    #[serde(default)]
+    pub promotion: Option<Vec<char>>,
+    /// Where king and rook land when castling. No castling when left out.
+    #[serde(default)]
+    pub castling: Option<CastlingFiles>,
+    /// Ways to win besides checkmate.
+    #[serde(default)]
+    pub wins: Vec<WinCondition>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PieceRules {
+    pub name: String,
+    pub letter: char,
+    pub betza: String,
+    /// `set/name` under `assets/pieces`, e.g. `01_classic/knight` for
+    /// `assets/pieces/01_classic/w-knight.png` and its black twin. Only its
+    /// form is checked on loading; a missing image shows up when drawn.
+    pub sprite: String,
+    #[serde(default)]
+    pub royal: bool,
+    #[serde(default)]
+    pub pawn: bool,
+    /// Worth in centipawns. Left out, a standard piece takes its usual value
+    /// and any other piece `PieceDef::estimate_value`.
+    #[serde(default)]
+    pub value: Option<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum WinCondition {
+    /// A side left without a legal move while out of check loses instead of
+    /// drawing.
+    Stalemate,
+    /// A side wins by moving a royal piece onto one of these squares.
+    ReachSquares(Vec<String>),
+}
+
+/// Reason a rule file was rejected.
+#[derive(Debug)]
+pub enum VariantError {
+    Io(io::Error),
+    /// Malformed RON, with the line and column in the message.
+    Parse(String),
+    Board {
+        width: u8,
+        height: u8,
+    },
+    Betza {
+        letter: char,
+        error: BetzaError,
+    },
+    DuplicateLetter(char),
+    Sprite(String),
+    UnknownPromotion(char),
+    /// Promotion to a royal piece, to a pawn, or to the same piece twice.
+    InvalidPromotion(char),
+    NoPromotions,
+    CastlingFile(u8),
+    /// Castling is played but no piece is lettered `R` to castle with.
+    NoCastlingRook,
+    Square(String),
+    Fen(FenError),
+}
+
+impl fmt::Display for VariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariantError::Io(err) => write!(f, "cannot read rules: {err}"),
+            VariantError::Parse(msg) => write!(f, "invalid rules: {msg}"),
+            VariantError::Board { width, height } => {
+                write!(f, "unsupported board size {width}x{height}")
+            }
+            VariantError::Betza { letter, error } => write!(f, "piece '{letter}': {error}"),
+            VariantError::DuplicateLetter(letter) => {
+                write!(f, "more than one piece lettered '{letter}'")
+            }
+            VariantError::Sprite(sprite) => {
+                write!(f, "sprite '{sprite}' is not of the form set/name")
+            }
+            VariantError::UnknownPromotion(letter) => {
+                write!(f, "promotion to unknown piece '{letter}'")
+            }
+            VariantError::InvalidPromotion(letter) => {
+                write!(
+                    f,
+                    "promotion to '{letter}', a royal piece, a pawn or a repeat"
+                )
+            }
+            VariantError::NoPromotions => write!(f, "promotion list is empty"),
+            VariantError::CastlingFile(file) => write!(f, "castling to file {file}, off the board"),
+            VariantError::NoCastlingRook => write!(f, "castling without a piece lettered 'R'"),
+            VariantError::Square(name) => write!(f, "invalid square '{name}'"),
+            VariantError::Fen(err) => write!(f, "invalid start position: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for VariantError {}
+
+impl From<FenError> for VariantError {
+    fn from(err: FenError) -> VariantError {
+        VariantError::Fen(err)
+    }
+}
+
+/// A variant ready to be played: its checked rules compiled into an engine
+/// position and the art to draw it with.
+#[derive(Resource, Clone)]
+pub struct Variant {
+    pub name: String,
+    start: WideGameState,
+    /// Indexed by `PieceId`.
+    sprites: Vec<String>,
+    castling: Option<CastlingFiles>,
+}
+
+impl Variant {
+    /// Reads and checks the rule file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Variant, VariantError> {
+        Variant::from_ron(&fs::read_to_string(path).map_err(VariantError::Io)?)
+    }
+
+    pub fn from_ron(ron: &str) -> Result<Variant, VariantError> {
+        let rules = ron::from_str(ron).map_err(|err| VariantError::Parse(err.to_string()))?;
+        Variant::from_rules(rules)
+    }
+
+    pub fn from_rules(rules: VariantRules) -> Result<Variant, VariantError> {
+        let geometry =
+            BoardGeometry::new(rules.width, rules.height).ok_or(VariantError::Board {
+                width: rules.width,
+                height: rules.height,
+            })?;
+
+        let mut roster = Roster::default();
+        let mut sprites = Vec::new();
+        for piece in rules.pieces {
+            let def =
+                PieceDef::from_betza(&piece.name, piece.letter, &piece.betza).map_err(|error| {
+                    VariantError::Betza {
+                        letter: piece.letter,
+                        error,
+                    }
+                })?;
+            if !piece.sprite.contains('/') {
+                return Err(VariantError::Sprite(piece.sprite));
+            }
+            let mut def = PieceDef {
+                royal: piece.royal,
+                pawn: piece.pawn,
+                ..def
+            };
+            def.value = piece
+                .value
+                .or_else(|| standard_type(&def).map(piece_value))
+                .unwrap_or(def.value);
+            roster
+                .add(def)
+                .ok_or(VariantError::DuplicateLetter(piece.letter))?;
+            sprites.push(piece.sprite);
+        }
+
+        let promotions = match rules.promotion {
+            Some(letters) if letters.is_empty() => return Err(VariantError::NoPromotions),
+            Some(letters) => {
+                let mut promotions = Vec::new();
+                for letter in letters {
+                    let id = roster
+                        .id_of(letter)
+                        .ok_or(VariantError::UnknownPromotion(letter))?;
+                    let def = roster.get(id);
+                    if def.royal || def.pawn || promotions.contains(&id) {
+                        return Err(VariantError::InvalidPromotion(letter));
+                    }
+                    promotions.push(id);
+                }
+                promotions
+            }
+            None => roster.promotions(),
+        };
+        if let Some(files) = rules.castling {
+            let (a, b) = files.kingside;
+            let (c, d) = files.queenside;
+            if let Some(&file) = [a, b, c, d].iter().find(|&&file| file >= geometry.width) {
+                return Err(VariantError::CastlingFile(file));
+            }
+            if roster.id_of('R').is_none() {
+                return Err(VariantError::NoCastlingRook);
+            }
+        }
+
+        let mut wins = WinRules::default();
+        for win in rules.wins {
+            match win {
+                WinCondition::Stalemate => wins.stalemate_wins = true,
+                WinCondition::ReachSquares(names) => {
+                    for name in names {
+                        let coordinates = BoardCoordinates::from_algebraic_in(&name, geometry)
+                            .ok_or(VariantError::Square(name))?;
+                        wins.goal |= WideBitBoard::from_index(coordinates.to_square(geometry));
+                    }
+                }
+            }
+        }
+
+        let empty = WideGameState::with_rules(geometry, roster, promotions, rules.castling, wins);
+        Ok(Variant {
+            name: rules.name,
+            start: empty.with_fen(&rules.start_fen)?,
+            sprites,
+            castling: rules.castling,
+        })
+    }
+
+    pub fn geometry(&self) -> BoardGeometry {
+        self.start.geometry()
+    }
+
+    pub fn roster(&self) -> &Roster {
+        self.start.roster()
+    }
+
+    pub fn start_position(&self) -> WideGameState {
+        self.start.clone()
+    }
+
+    /// The standard piece a roster piece plays as, if it is one: lettered
+    /// like it and moving, promoting and being royal exactly as it does.
+    pub fn standard_piece(&self, id: PieceId) -> Option<PieceType> {
+        standard_type(self.roster().get(id))
+    }
+
+    /// The start position as a `GameState`, if this variant is chess on the
+    /// 8x8 board with the standard pieces, castling and ways to win, so that
+    /// `Searcher` and UCI engines can play it. Its promotion rules carry
+    /// over.
+    pub fn orthodox_start(&self) -> Option<GameState> {
+        if self.geometry() != BoardGeometry::STANDARD
+            || self.castling != CastlingFiles::for_width(8)
+            || self.start.wins() != WinRules::default()
+            || self
+                .roster()
+                .ids()
+                .any(|id| self.standard_piece(id).is_none())
+        {
+            return None;
+        }
+        let mut state = GameState::from_fen(&self.start.to_fen()).ok()?;
+        let promotions = self.start.promotions().iter();
+        state.promotions = Promotions::new(promotions.filter_map(|&id| self.standard_piece(id)))?;
+        Some(state)
+    }
+
+    /// A position of this variant given as FEN.
+    pub fn position(&self, fen: &str) -> Result<WideGameState, FenError> {
+        self.start.with_fen(fen)
+    }
+
+    /// Asset path of the sprite for piece `id` of `color`.
+    pub fn sprite_path(&self, id: PieceId, color: PieceColor) -> String {
+        let (set, name) = self.sprites[id as usize]
+            .rsplit_once('/')
+            .expect("checked when loaded");
+        let prefix = if color == PieceColor::White { "w" } else { "b" };
+        format!("pieces/{set}/{prefix}-{name}.png")
+    }
+
+    /// Returns how the game ended under this variant's rules, or `None` if
+    /// it goes on.
+    pub fn outcome(&self, state: &WideGameState) -> Option<GameOutcome> {
+        let mover = state.side_to_move.opponent();
+        if state.reached_goal(mover) {
+            return Some(GameOutcome::VariantWin { winner: mover });
+        }
+
+        if state.generate_legal_moves().is_empty() {
+            return Some(if state.is_in_check(state.side_to_move) {
+                GameOutcome::Checkmate { winner: mover }
+            } else if state.wins().stalemate_wins {
+                GameOutcome::VariantWin { winner: mover }
+            } else {
+                GameOutcome::Draw(DrawReason::Stalemate)
+            });
+        }
+        if state.halfmove_clock >= 150 {
+            return Some(GameOutcome::Draw(DrawReason::SeventyFiveMoveRule));
+        }
+        None
+    }
+}
+
+/// The standard piece `def` plays as, if it is one.
+fn standard_type(def: &PieceDef) -> Option<PieceType> {
+    let pt = PieceType::from_char(def.letter)?;
+    let standard = PieceDef::standard(pt);
+    (moves(&def.components) == moves(&standard.components)
+        && def.royal == standard.royal
+        && def.pawn == standard.pawn)
+        .then_some(pt)
+}
+
+/// Every (offset, movement, mode) of `components`, however Betza grouped
+/// them.
+fn moves(components: &[MoveComponent]) -> HashSet<((i32, i32), Movement, MoveMode)> {
+    components
+        .iter()
+        .flat_map(|c| c.offsets.iter().map(|&offset| (offset, c.movement, c.mode)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate_legal_moves;
+
+    const STANDARD: &str = include_str!("../assets/variants/standard.ron");
+    const CAPABLANCA: &str = include_str!("../assets/variants/capablanca.ron");
+    const KING_OF_THE_HILL: &str = include_str!("../assets/variants/king_of_the_hill.ron");
+
+    #[test]
+    fn bundled_variants_play_like_the_built_in_rosters() {
+        let standard = Variant::from_ron(STANDARD).unwrap();
+        assert_eq!(standard.geometry(), BoardGeometry::STANDARD);
+        assert_eq!(standard.start_position().perft(3), 8902);
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(
+            standard.position(castling).unwrap().perft(2),
+            WideGameState::from_fen(castling, BoardGeometry::STANDARD)
+                .unwrap()
+                .perft(2)
+        );
+
+        assert_eq!(
+            standard.orthodox_start().unwrap().hash,
+            GameState::default().hash
+        );
+
+        let capablanca = Variant::from_ron(CAPABLANCA).unwrap();
+        assert!(capablanca.orthodox_start().is_none());
+        assert_eq!(capablanca.geometry(), BoardGeometry::CAPABLANCA);
+        assert_eq!(capablanca.start_position().perft(2), 784);
+        let archbishop = capablanca.roster().id_of('A').unwrap();
+        assert_eq!(capablanca.roster().get(archbishop).value, 825);
+        let knight = capablanca.roster().id_of('N').unwrap();
+        assert_eq!(
+            capablanca.roster().get(knight).value,
+            piece_value(PieceType::Knight)
+        );
+        assert_eq!(
+            capablanca.sprite_path(archbishop, PieceColor::Black),
+            "pieces/02_medieval/b-archbishop.png"
+        );
+    }
+
+    #[test]
+    fn promotion_follows_the_rules() {
+        let mut rules: VariantRules = ron::from_str(STANDARD).unwrap();
+        rules.start_fen = "k7/4P3/8/8/8/8/8/K7 w - - 0 1".to_string();
+        rules.promotion = Some(vec!['Q', 'N']);
+        let variant = Variant::from_rules(rules.clone()).unwrap();
+        // Two promotions and three king moves
+        assert_eq!(variant.start_position().generate_legal_moves().len(), 5);
+
+        let orthodox = variant.orthodox_start().unwrap();
+        assert_eq!(generate_legal_moves(&orthodox).len(), 5);
+
+        rules.promotion = Some(vec!['X']);
+        assert!(matches!(
+            Variant::from_rules(rules),
+            Err(VariantError::UnknownPromotion('X'))
+        ));
+    }
+
+    #[test]
+    fn king_of_the_hill_is_won_in_the_centre() {
+        let variant = Variant::from_ron(KING_OF_THE_HILL).unwrap();
+        assert_eq!(variant.outcome(&variant.start_position()), None);
+        assert!(variant.orthodox_start().is_none());
+
+        let mut state = variant.position("k7/8/8/8/8/3K4/8/8 w - - 0 1").unwrap();
+        let to_centre = state
+            .generate_legal_moves()
+            .into_iter()
+            .find(|mv| mv.to == 27)
+            .unwrap();
+        state.make_move(to_centre);
+        assert_eq!(
+            variant.outcome(&state),
+            Some(GameOutcome::VariantWin {
+                winner: PieceColor::White
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        let err = Variant::from_ron(&STANDARD.replace("\"fmWfcF\"", "\"fmWfcX\""))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "piece 'P': unknown atom 'X' at position 5");
+
+        let err = Variant::from_ron("(name: \"broken\", width: 8,\n height: )")
+            .err()
+            .unwrap();
+        assert!(matches!(err, VariantError::Parse(_)));
+        assert!(err.to_string().contains("2:"), "{err}");
+
+        let err = Variant::from_ron(&STANDARD.replace("width: 8", "width: 20"))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            VariantError::Board {
+                width: 20,
+                height: 8
+            }
+        ));
+
+        let mut rules: VariantRules = ron::from_str(STANDARD).unwrap();
+        for letters in [vec!['K'], vec!['Q', 'P'], vec!['Q', 'R', 'q']] {
+            rules.promotion = Some(letters);
+            let err = Variant::from_rules(rules.clone()).err().unwrap();
+            assert!(matches!(err, VariantError::InvalidPromotion(_)), "{err}");
+        }
+        rules.promotion = Some(Vec::new());
+        assert!(matches!(
+            Variant::from_rules(rules.clone()),
+            Err(VariantError::NoPromotions)
+        ));
+
+        // Castling with the rook renamed away
+        rules.promotion = None;
+        rules.start_fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_string();
+        for piece in &mut rules.pieces {
+            if piece.letter == 'R' {
+                piece.letter = 'T';
+            }
+        }
+        assert!(matches!(
+            Variant::from_rules(rules),
+            Err(VariantError::NoCastlingRook)
+        ));
+    }
+}
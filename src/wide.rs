@@ -0,0 +1,918 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use bevy::platform::collections::HashMap;
+use serde::Deserialize;
+
+use crate::{
+    bitboard::WideBitBoard,
+    fairy::{CompiledPiece, PieceId, Reach, Roster},
+    fen::{FenError, FenRecord},
+    game::{CastlingRights, CastlingSides, Move, MoveFlag},
+    geometry::{BoardGeometry, MAX_SQUARES},
+    rendering::PieceColor,
+};
+
+/// A move of a `WideGameState`, promoting to a piece of its roster.
+pub type WideMove = Move<PieceId>;
+
+/// Files the king and rook land on when castling, as (king, rook).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct CastlingFiles {
+    pub kingside: (u8, u8),
+    pub queenside: (u8, u8),
+}
+
+impl CastlingFiles {
+    /// Next to the corner on the kingside and on the c- and d-files on the
+    /// queenside, which on a 10-file board puts a castled king on i1 as in
+    /// Capablanca chess. `None` on boards too narrow to hold those files.
+    pub fn for_width(width: u8) -> Option<CastlingFiles> {
+        (width >= 4).then(|| CastlingFiles {
+            kingside: (width - 2, width - 3),
+            queenside: (2, 3),
+        })
+    }
+}
+
+/// Ways to win a `WideGameState` besides checkmate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WinRules {
+    /// Squares that win for a royal piece landing on them.
+    pub goal: WideBitBoard,
+    /// Stalemating the opponent wins rather than draws.
+    pub stalemate_wins: bool,
+}
+
+/// A roster compiled for one geometry along with the rules that go with
+/// it, computed once and shared by every position played on it.
+struct PieceTables {
+    roster: Roster,
+    /// Indexed by `PieceId`.
+    pieces: Vec<CompiledPiece>,
+    promotions: Vec<PieceId>,
+    /// The piece lettered `R`, which castles with the royal piece.
+    castling_rook: Option<PieceId>,
+    /// `None` where castling is not played.
+    castling_files: Option<CastlingFiles>,
+    wins: WinRules,
+}
+
+/// A position on a board of any size up to 128 squares: the counterpart of
+/// `GameState` for variants played off the 8x8 board (Capablanca, Grand,
+/// Courier...), with pieces taken from a `Roster` of definitions rather
+/// than the six `PieceType`s.
+///
+/// Magic bitboards only exist for 64 squares, so sliders walk their rays
+/// instead, and legality is checked by making and unmaking each move on a
+/// scratch copy. `WideSearcher` searches it, slower than `Searcher` does a
+/// `GameState` but with any piece the roster defines.
+#[derive(Clone)]
+pub struct WideGameState {
+    geometry: BoardGeometry,
+    tables: Arc<PieceTables>,
+    /// Bitboards indexed by `[PieceId][PieceColor as usize]`.
+    pieces: Vec<[WideBitBoard; 2]>,
+    color_occupancy: [WideBitBoard; 2],
+    mailbox: [Option<(PieceId, PieceColor)>; MAX_SQUARES],
+    pub side_to_move: PieceColor,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<u8>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl WideGameState {
+    /// An empty board of the given size with the standard pieces, White to move.
+    pub fn empty(geometry: BoardGeometry) -> WideGameState {
+        WideGameState::with_roster(geometry, Roster::standard())
+    }
+
+    /// An empty board of the given size played with `roster`, White to move.
+    /// Pawns promote to anything neither a pawn nor royal, castling follows
+    /// `CastlingFiles::for_width` where the board is wide enough, and only
+    /// checkmate wins.
+    pub fn with_roster(geometry: BoardGeometry, roster: Roster) -> WideGameState {
+        let promotions = roster.promotions();
+        let castling = CastlingFiles::for_width(geometry.width);
+        WideGameState::with_rules(geometry, roster, promotions, castling, WinRules::default())
+    }
+
+    /// An empty board of the given size played with `roster`, pawns promoting
+    /// to `promotions`, castling to `castling` if given and won as `wins`
+    /// says as well as by checkmate, White to move.
+    pub fn with_rules(
+        geometry: BoardGeometry,
+        roster: Roster,
+        promotions: Vec<PieceId>,
+        castling: Option<CastlingFiles>,
+        wins: WinRules,
+    ) -> WideGameState {
+        WideGameState {
+            geometry,
+            pieces: vec![[WideBitBoard::EMPTY; 2]; roster.len()],
+            tables: Arc::new(PieceTables {
+                pieces: roster
+                    .ids()
+                    .map(|id| CompiledPiece::new(roster.get(id), geometry))
+                    .collect(),
+                promotions,
+                castling_rook: roster.id_of('R'),
+                castling_files: castling,
+                wins,
+                roster,
+            }),
+            color_occupancy: [WideBitBoard::EMPTY; 2],
+            mailbox: [None; MAX_SQUARES],
+            side_to_move: PieceColor::White,
+            castling_rights: CastlingRights(HashMap::from([
+                (PieceColor::White, CastlingSides::default()),
+                (PieceColor::Black, CastlingSides::default()),
+            ])),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    /// Parses a FEN record for a board of the given size. Empty-square counts
+    /// past 9 take several digits (`10/10/...` on a 10x10 board).
+    pub fn from_fen(fen: &str, geometry: BoardGeometry) -> Result<WideGameState, FenError> {
+        WideGameState::from_fen_with_roster(fen, geometry, Roster::standard())
+    }
+
+    /// Parses a FEN record whose letters name pieces of `roster`.
+    pub fn from_fen_with_roster(
+        fen: &str,
+        geometry: BoardGeometry,
+        roster: Roster,
+    ) -> Result<WideGameState, FenError> {
+        WideGameState::with_roster(geometry, roster).with_fen(fen)
+    }
+
+    /// The position of a FEN record, played on this position's board with
+    /// its pieces and rules.
+    pub fn with_fen(&self, fen: &str) -> Result<WideGameState, FenError> {
+        let record = FenRecord::parse(fen, self.geometry)?;
+        let board = record.pieces(|letter| self.roster().id_of(letter))?;
+        let mut state = WideGameState {
+            pieces: vec![[WideBitBoard::EMPTY; 2]; self.roster().len()],
+            color_occupancy: [WideBitBoard::EMPTY; 2],
+            mailbox: [None; MAX_SQUARES],
+            ..self.clone()
+        };
+        for (sq, piece) in board.into_iter().enumerate() {
+            if let Some((id, pc)) = piece {
+                state.put_piece(id, pc, sq as u8);
+            }
+        }
+        state.side_to_move = record.side_to_move;
+        state.castling_rights = record.castling_rights;
+        state.en_passant = record.en_passant;
+        state.halfmove_clock = record.halfmove_clock;
+        state.fullmove_number = record.fullmove_number;
+        Ok(state)
+    }
+
+    pub fn to_fen(&self) -> String {
+        FenRecord {
+            geometry: self.geometry,
+            board: self.mailbox[..self.geometry.squares()]
+                .iter()
+                .map(|piece| piece.map(|(id, pc)| (self.roster().get(id).letter, pc)))
+                .collect(),
+            side_to_move: self.side_to_move,
+            castling_rights: self.castling_rights.clone(),
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+        }
+        .write()
+    }
+
+    /// A hash of everything that makes two positions the same for
+    /// repetitions: pieces, side to move, castling rights and en passant
+    /// square. Computed from scratch, unlike `GameState`'s Zobrist hash.
+    pub fn position_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.mailbox[..self.geometry.squares()].hash(&mut hasher);
+        self.side_to_move.hash(&mut hasher);
+        for color in PieceColor::ALL {
+            self.castling_rights.kingside_file(color).hash(&mut hasher);
+            self.castling_rights.queenside_file(color).hash(&mut hasher);
+        }
+        self.en_passant.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[inline]
+    pub fn geometry(&self) -> BoardGeometry {
+        self.geometry
+    }
+
+    #[inline]
+    pub fn roster(&self) -> &Roster {
+        &self.tables.roster
+    }
+
+    /// Pieces pawns promote to, in the order moves are generated.
+    pub fn promotions(&self) -> &[PieceId] {
+        &self.tables.promotions
+    }
+
+    /// Ways to win besides checkmate.
+    pub fn wins(&self) -> WinRules {
+        self.tables.wins
+    }
+
+    /// Whether a royal piece of `color` stands on a goal square, winning
+    /// the game.
+    pub fn reached_goal(&self, color: PieceColor) -> bool {
+        let goal = self.tables.wins.goal;
+        !goal.is_empty()
+            && self
+                .roster()
+                .ids()
+                .filter(|&id| self.roster().get(id).royal)
+                .any(|id| !(self.piece_bb(id, color) & goal).is_empty())
+    }
+
+    #[inline]
+    pub fn piece_bb(&self, id: PieceId, color: PieceColor) -> WideBitBoard {
+        self.pieces[id as usize][color as usize]
+    }
+
+    #[inline]
+    pub fn pieces(&self, color: PieceColor) -> WideBitBoard {
+        self.color_occupancy[color as usize]
+    }
+
+    #[inline]
+    pub fn occupancy(&self) -> WideBitBoard {
+        self.color_occupancy[0] | self.color_occupancy[1]
+    }
+
+    #[inline]
+    pub fn piece_at(&self, sq: u8) -> Option<(PieceId, PieceColor)> {
+        self.mailbox[sq as usize]
+    }
+
+    /// Puts a piece on an empty square, for setting up positions.
+    pub fn put_piece(&mut self, id: PieceId, pc: PieceColor, sq: u8) {
+        assert!(
+            (sq as usize) < self.geometry.squares(),
+            "put_piece: square {sq} is off the board"
+        );
+        assert!(
+            self.piece_at(sq).is_none(),
+            "put_piece: square {sq} is occupied"
+        );
+        self.toggle_piece(id, pc, sq);
+    }
+
+    /// Takes whatever stands on `sq` off the board.
+    pub fn remove_piece(&mut self, sq: u8) -> Option<(PieceId, PieceColor)> {
+        let piece = self.piece_at(sq)?;
+        self.toggle_piece(piece.0, piece.1, sq);
+        Some(piece)
+    }
+
+    /// Row `color`'s pieces start on and castle along.
+    #[inline]
+    pub fn back_rank(&self, color: PieceColor) -> u8 {
+        match color {
+            PieceColor::White => 0,
+            PieceColor::Black => self.geometry.height - 1,
+        }
+    }
+
+    /// Files the king and rook land on when castling with `flag`.
+    fn castling_files(&self, flag: MoveFlag<PieceId>) -> Option<(u8, u8)> {
+        let files = self.tables.castling_files?;
+        match flag {
+            MoveFlag::KingsideCastle => Some(files.kingside),
+            MoveFlag::QueensideCastle => Some(files.queenside),
+            _ => None,
+        }
+    }
+
+    /// Rook origin and destination for a castling move by `color`, `None` for
+    /// any other move or a side that can no longer castle.
+    pub fn castling_rook_squares(
+        &self,
+        flag: MoveFlag<PieceId>,
+        color: PieceColor,
+    ) -> Option<(u8, u8)> {
+        let (_, rook_to) = self.castling_files(flag)?;
+        let file = match flag {
+            MoveFlag::KingsideCastle => self.castling_rights.kingside_file(color)?,
+            _ => self.castling_rights.queenside_file(color)?,
+        };
+        let row = self.back_rank(color);
+        Some((
+            self.geometry.square(file, row),
+            self.geometry.square(rook_to, row),
+        ))
+    }
+
+    /// Where a piece `id` of `color` standing on `sq` can move and capture,
+    /// lines blocked by `occ`.
+    #[inline]
+    pub fn reach(&self, id: PieceId, color: PieceColor, sq: u8, occ: WideBitBoard) -> Reach {
+        self.tables.pieces[id as usize].reach(color, sq, occ)
+    }
+
+    /// Pieces of `by_color` attacking `sq`, with lines blocked by `occ`.
+    ///
+    /// Fairy pieces need not attack symmetrically (a grasshopper's victim
+    /// cannot see it back), so each piece is asked where it captures.
+    pub fn attackers_to(&self, sq: u8, by_color: PieceColor, occ: WideBitBoard) -> WideBitBoard {
+        let mut attackers = WideBitBoard::EMPTY;
+        for id in self.roster().ids() {
+            for from in self.piece_bb(id, by_color) & occ {
+                if self.reach(id, by_color, from, occ).captures.contains(sq) {
+                    attackers |= WideBitBoard::from_index(from);
+                }
+            }
+        }
+        attackers
+    }
+
+    pub fn is_attacked(&self, sq: u8, by_color: PieceColor) -> bool {
+        !self.attackers_to(sq, by_color, self.occupancy()).is_empty()
+    }
+
+    /// Whether any royal piece of `color` is attacked.
+    pub fn is_in_check(&self, color: PieceColor) -> bool {
+        self.roster()
+            .ids()
+            .filter(|&id| self.roster().get(id).royal)
+            .flat_map(|id| self.piece_bb(id, color))
+            .any(|sq| self.is_attacked(sq, color.opponent()))
+    }
+
+    /// Generates all pseudo-legal moves for the side to move.
+    pub fn generate_pseudo_legal_moves(&self) -> Vec<WideMove> {
+        let color = self.side_to_move;
+        let occ = self.occupancy();
+        let enemy = self.pieces(color.opponent());
+        let mut moves = Vec::new();
+
+        for id in self.roster().ids() {
+            let def = self.roster().get(id);
+            for from in self.piece_bb(id, color) {
+                let reach = self.reach(id, color, from, occ);
+                if def.pawn {
+                    self.gen_pawn_moves(from, reach, &mut moves);
+                    continue;
+                }
+                for to in reach.moves & !occ {
+                    moves.push(Move {
+                        from,
+                        to,
+                        flag: MoveFlag::Quiet,
+                    });
+                }
+                for to in reach.captures & enemy {
+                    moves.push(Move {
+                        from,
+                        to,
+                        flag: MoveFlag::Capture,
+                    });
+                }
+                if def.royal {
+                    self.gen_castling_moves(from, &mut moves);
+                }
+            }
+        }
+        moves
+    }
+
+    /// Generates all legal moves for the side to move.
+    pub fn generate_legal_moves(&self) -> Vec<WideMove> {
+        let color = self.side_to_move;
+        let mut moves = self.generate_pseudo_legal_moves();
+        let mut scratch = self.clone();
+        moves.retain(|&mv| {
+            let undo = scratch.make_move(mv);
+            let legal = !scratch.is_in_check(color);
+            scratch.unmake_move(mv, undo);
+            legal
+        });
+        moves
+    }
+
+    /// A pawn's own moves, promoting on the last rank, plus the double step
+    /// from its second rank and en passant.
+    fn gen_pawn_moves(&self, from: u8, reach: Reach, moves: &mut Vec<WideMove>) {
+        let color = self.side_to_move;
+        let g = self.geometry;
+        let (forward, start_row, last_row) = match color {
+            PieceColor::White => (1, 1, g.height - 1),
+            PieceColor::Black => (-1, g.height - 2, 0),
+        };
+        let occ = self.occupancy();
+        let enemy = self.pieces(color.opponent());
+
+        let push = |moves: &mut Vec<WideMove>, to: u8, capture: bool| {
+            if g.row(to) == last_row {
+                for &id in &self.tables.promotions {
+                    let flag = if capture {
+                        MoveFlag::PromotionCapture(id)
+                    } else {
+                        MoveFlag::Promotion(id)
+                    };
+                    moves.push(Move { from, to, flag });
+                }
+            } else {
+                let flag = if capture {
+                    MoveFlag::Capture
+                } else {
+                    MoveFlag::Quiet
+                };
+                moves.push(Move { from, to, flag });
+            }
+        };
+
+        for to in reach.moves & !occ {
+            push(moves, to, false);
+        }
+        if g.row(from) == start_row
+            && let Some(one) = g.offset(from, 0, forward).filter(|&sq| !occ.contains(sq))
+            && let Some(two) = g.offset(one, 0, forward).filter(|&sq| !occ.contains(sq))
+        {
+            moves.push(Move {
+                from,
+                to: two,
+                flag: MoveFlag::DoublePawnPush,
+            });
+        }
+        for to in reach.captures {
+            if enemy.contains(to) {
+                push(moves, to, true);
+            } else if self.en_passant == Some(to) && !occ.contains(to) {
+                moves.push(Move {
+                    from,
+                    to,
+                    flag: MoveFlag::EnPassant,
+                });
+            }
+        }
+    }
+
+    /// Castling as in `movegen`: every square the king and rook cross must
+    /// be empty but for the two of them, and the king's squares unattacked.
+    fn gen_castling_moves(&self, king_sq: u8, moves: &mut Vec<WideMove>) {
+        let color = self.side_to_move;
+        let g = self.geometry;
+        let row = self.back_rank(color);
+        let Some(rook) = self.tables.castling_rook else {
+            return;
+        };
+        if g.row(king_sq) != row {
+            return;
+        }
+        // Every square from `a` to `b`, both included
+        let span = |a: u8, b: u8| {
+            (g.col(a).min(g.col(b))..=g.col(a).max(g.col(b)))
+                .fold(WideBitBoard::EMPTY, |bb, col| {
+                    bb | WideBitBoard::from_index(g.square(col, row))
+                })
+        };
+
+        for flag in [MoveFlag::KingsideCastle, MoveFlag::QueensideCastle] {
+            let Some((rook_from, rook_to)) = self.castling_rook_squares(flag, color) else {
+                continue;
+            };
+            if self.piece_at(rook_from) != Some((rook, color)) {
+                continue;
+            }
+            let (king_file, _) = self.castling_files(flag).expect("castling flag");
+            let king_to = g.square(king_file, row);
+            let movers = WideBitBoard::from_index(king_sq) | WideBitBoard::from_index(rook_from);
+            let occ = self.occupancy() & !movers;
+            if !((span(king_sq, king_to) | span(rook_from, rook_to)) & occ).is_empty() {
+                continue;
+            }
+            let safe = span(king_sq, king_to)
+                .into_iter()
+                .all(|sq| self.attackers_to(sq, color.opponent(), occ).is_empty());
+            if safe {
+                moves.push(Move {
+                    from: king_sq,
+                    to: king_to,
+                    flag,
+                });
+            }
+        }
+    }
+
+    /// Returns the position after `mv`, leaving `self` untouched.
+    pub fn apply_move(&self, mv: WideMove) -> WideGameState {
+        let mut state = self.clone();
+        state.make_move(mv);
+        state
+    }
+
+    /// Plays `mv` in place, following `GameState::make_move`. The returned
+    /// `WideUndo` takes it back through `unmake_move`.
+    pub fn make_move(&mut self, mv: WideMove) -> WideUndo {
+        let (moving_id, moving_pc) = self
+            .piece_at(mv.from)
+            .expect("make_move: no piece at from square");
+        let enemy = moving_pc.opponent();
+        let g = self.geometry;
+        let moving = self.roster().get(moving_id);
+        let (royal, pawn) = (moving.royal, moving.pawn);
+
+        let mut undo = WideUndo {
+            moved: moving_id,
+            captured: None,
+            castling: [
+                (moving_pc, self.castling_rights.sides(moving_pc)),
+                (enemy, self.castling_rights.sides(enemy)),
+            ],
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+        };
+
+        let castling_rook = self.castling_rook_squares(mv.flag, moving_pc);
+        self.toggle_piece(moving_id, moving_pc, mv.from);
+        let rook = self.tables.castling_rook;
+        if let (Some((rf, _)), Some(rook)) = (castling_rook, rook) {
+            self.toggle_piece(rook, moving_pc, rf);
+        }
+
+        match mv.flag {
+            MoveFlag::Capture | MoveFlag::PromotionCapture(_) => {
+                undo.captured = self.remove_piece(mv.to).map(|(id, _)| id);
+            }
+            MoveFlag::EnPassant => {
+                let victim = en_passant_victim(g, mv);
+                undo.captured = self.remove_piece(victim).map(|(id, _)| id);
+            }
+            _ => {}
+        }
+
+        let placed_id = match mv.flag {
+            MoveFlag::Promotion(id) | MoveFlag::PromotionCapture(id) => id,
+            _ => moving_id,
+        };
+        self.toggle_piece(placed_id, moving_pc, mv.to);
+        if let (Some((_, rt)), Some(rook)) = (castling_rook, rook) {
+            self.toggle_piece(rook, moving_pc, rt);
+        }
+
+        self.en_passant = (mv.flag == MoveFlag::DoublePawnPush).then(|| (mv.from + mv.to) / 2);
+
+        if royal {
+            self.castling_rights.revoke_all(moving_pc);
+        }
+        for sq in [mv.from, mv.to] {
+            for color in PieceColor::ALL {
+                if g.row(sq) == self.back_rank(color) {
+                    self.castling_rights.revoke_file(color, g.col(sq));
+                }
+            }
+        }
+
+        self.side_to_move = enemy;
+        if pawn || undo.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if moving_pc == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+
+        undo
+    }
+
+    /// Takes back `mv`, which must be the last move made with `make_move`.
+    pub fn unmake_move(&mut self, mv: WideMove, undo: WideUndo) {
+        let moving_pc = self.side_to_move.opponent();
+        let enemy = self.side_to_move;
+        let (placed_id, _) = self
+            .piece_at(mv.to)
+            .expect("unmake_move: no piece at destination square");
+
+        // Rights first: they say which rook a castling move took along
+        for (color, sides) in undo.castling {
+            self.castling_rights.0.insert(color, sides);
+        }
+        let castling_rook = self.castling_rook_squares(mv.flag, moving_pc);
+        let rook = self.tables.castling_rook;
+        if let (Some((_, rt)), Some(rook)) = (castling_rook, rook) {
+            self.toggle_piece(rook, moving_pc, rt);
+        }
+
+        self.toggle_piece(placed_id, moving_pc, mv.to);
+        if let Some(captured) = undo.captured {
+            let victim = if mv.flag == MoveFlag::EnPassant {
+                en_passant_victim(self.geometry, mv)
+            } else {
+                mv.to
+            };
+            self.toggle_piece(captured, enemy, victim);
+        }
+        self.toggle_piece(undo.moved, moving_pc, mv.from);
+        if let (Some((rf, _)), Some(rook)) = (castling_rook, rook) {
+            self.toggle_piece(rook, moving_pc, rf);
+        }
+
+        if moving_pc == PieceColor::Black {
+            self.fullmove_number -= 1;
+        }
+        self.side_to_move = moving_pc;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+    }
+
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep.
+    pub fn perft(&self, depth: u32) -> u64 {
+        self.clone().perft_in_place(depth)
+    }
+
+    fn perft_in_place(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.generate_legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = self.make_move(mv);
+            nodes += self.perft_in_place(depth - 1);
+            self.unmake_move(mv, undo);
+        }
+        nodes
+    }
+
+    #[inline]
+    fn toggle_piece(&mut self, id: PieceId, pc: PieceColor, sq: u8) {
+        let bit = WideBitBoard::from_index(sq);
+        let bb = &mut self.pieces[id as usize][pc as usize];
+        *bb ^= bit;
+        self.mailbox[sq as usize] = bb.contains(sq).then_some((id, pc));
+        self.color_occupancy[pc as usize] ^= bit;
+    }
+}
+
+/// What `WideGameState::make_move` destroys and `unmake_move` needs to put
+/// back.
+#[derive(Clone, Copy, Debug)]
+pub struct WideUndo {
+    /// The piece that moved, before any promotion.
+    moved: PieceId,
+    captured: Option<PieceId>,
+    castling: [(PieceColor, CastlingSides); 2],
+    en_passant: Option<u8>,
+    halfmove_clock: u32,
+}
+
+/// Square of the pawn taken by an en passant capture.
+#[inline]
+pub(crate) fn en_passant_victim(g: BoardGeometry, mv: WideMove) -> u8 {
+    g.square(g.col(mv.to), g.row(mv.from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fairy::PieceDef, fen::START_FEN, game::GameState, movegen::perft, rendering::PieceType,
+    };
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const CAPABLANCA_FIDE: &str =
+        "rnbqkbbnrr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNBQKBBNRR w - - 0 1";
+
+    #[test]
+    fn matches_the_8x8_engine_on_the_standard_board() {
+        for fen in [
+            START_FEN,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            let wide = WideGameState::from_fen(fen, BoardGeometry::STANDARD).unwrap();
+            assert_eq!(wide.to_fen(), GameState::from_fen(fen).unwrap().to_fen());
+            let narrow = GameState::from_fen(fen).unwrap();
+            for depth in 1..=3 {
+                assert_eq!(
+                    wide.perft(depth),
+                    perft(&narrow, depth),
+                    "{fen} at depth {depth}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_fen_for_wider_and_taller_boards() {
+        let state = WideGameState::from_fen(CAPABLANCA_FIDE, BoardGeometry::CAPABLANCA).unwrap();
+        assert_eq!(state.to_fen(), CAPABLANCA_FIDE);
+        assert_eq!(
+            state.piece_at(9),
+            Some((PieceType::Rook as PieceId, PieceColor::White))
+        );
+
+        let grand = "r8r/1nbqkcabn1/pppppppppp/10/10/10/10/PPPPPPPPPP/1NBQKCABN1/R8R w - - 0 1";
+        assert!(matches!(
+            WideGameState::from_fen(grand, BoardGeometry::GRAND),
+            Err(FenError::PiecePlacement(_))
+        ));
+        let grand = "r8r/1nbqkbbn2/pppppppppp/10/10/10/10/PPPPPPPPPP/1NBQKBBN2/R8R b - j3 0 1";
+        let state = WideGameState::from_fen(grand, BoardGeometry::GRAND).unwrap();
+        assert_eq!(state.en_passant, Some(BoardGeometry::GRAND.square(9, 2)));
+        assert_eq!(state.to_fen(), grand);
+        assert!(WideGameState::from_fen(CAPABLANCA_FIDE, BoardGeometry::GRAND).is_err());
+    }
+
+    #[test]
+    fn pieces_move_across_the_whole_board() {
+        let g = BoardGeometry::COURIER;
+        let state = WideGameState::from_fen("6k5/12/12/12/12/12/12/R10K w - - 0 1", g).unwrap();
+        let rook_moves = state
+            .generate_legal_moves()
+            .iter()
+            .filter(|mv| mv.from == 0)
+            .count();
+        // Ten squares along the first rank up to the king, seven up the a-file
+        assert_eq!(rook_moves, 17);
+        assert!(
+            state
+                .generate_legal_moves()
+                .iter()
+                .any(|mv| mv.to == g.square(0, 7))
+        );
+    }
+
+    #[test]
+    fn pawns_promote_on_the_last_rank_of_tall_boards() {
+        let g = BoardGeometry::GRAND;
+        let state = WideGameState::from_fen("10/P9/10/10/10/10/10/10/10/K8k w - - 0 1", g).unwrap();
+        let promotions = state
+            .generate_legal_moves()
+            .into_iter()
+            .filter(|mv| matches!(mv.flag, MoveFlag::Promotion(_)))
+            .count();
+        assert_eq!(promotions, 4);
+    }
+
+    #[test]
+    fn castles_to_the_i_file_on_a_capablanca_board() {
+        let g = BoardGeometry::CAPABLANCA;
+        let state =
+            WideGameState::from_fen("r4k3r/10/10/10/10/10/10/R4K3R w KQkq - 0 1", g).unwrap();
+        let kingside = state
+            .generate_legal_moves()
+            .into_iter()
+            .find(|mv| mv.flag == MoveFlag::KingsideCastle)
+            .unwrap();
+        let after = state.apply_move(kingside);
+        assert_eq!(after.to_fen(), "r4k3r/10/10/10/10/10/10/R6RK1 b kq - 1 1");
+
+        // Too narrow to castle on at all
+        let narrow = BoardGeometry::new(2, 8).unwrap();
+        assert_eq!(CastlingFiles::for_width(narrow.width), None);
+        let state = WideGameState::from_fen("k1/2/2/2/2/2/2/K1 w - - 0 1", narrow).unwrap();
+        assert_eq!(state.generate_legal_moves().len(), 3);
+    }
+
+    #[test]
+    fn en_passant_on_a_ten_rank_board() {
+        let g = BoardGeometry::GRAND;
+        let state =
+            WideGameState::from_fen("k9/5p4/10/6P3/10/10/10/10/10/K9 b - - 0 1", g).unwrap();
+        let push = Move {
+            from: g.square(5, 8),
+            to: g.square(5, 6),
+            flag: MoveFlag::DoublePawnPush,
+        };
+        assert!(state.generate_legal_moves().contains(&push));
+
+        // The pawn skips f8 and lands next to White's pawn on g7
+        let state = state.apply_move(push);
+        assert_eq!(state.to_fen(), "k9/10/10/5pP3/10/10/10/10/10/K9 w - f8 0 2");
+        let capture = Move {
+            from: g.square(6, 6),
+            to: g.square(5, 7),
+            flag: MoveFlag::EnPassant,
+        };
+        assert!(state.generate_legal_moves().contains(&capture));
+        assert_eq!(
+            state.apply_move(capture).to_fen(),
+            "k9/10/5P4/10/10/10/10/10/10/K9 b - - 0 2"
+        );
+    }
+
+    #[test]
+    fn unmake_restores_position() {
+        fn check_unmake(state: &mut WideGameState, depth: u32) {
+            if depth == 0 {
+                return;
+            }
+            let fen = state.to_fen();
+            for mv in state.generate_legal_moves() {
+                let undo = state.make_move(mv);
+                check_unmake(state, depth - 1);
+                state.unmake_move(mv, undo);
+                assert_eq!(state.to_fen(), fen, "unmaking {mv:?}");
+            }
+        }
+
+        let capablanca = Roster::capablanca();
+        for (fen, g) in [
+            (KIWIPETE, BoardGeometry::STANDARD),
+            (
+                "r3k3cr/pPp1p1pppp/1a8/3pPp4/10/10/PPP2PPPPP/RA2K3CR w KQkq f6 0 1",
+                BoardGeometry::CAPABLANCA,
+            ),
+        ] {
+            let mut state =
+                WideGameState::from_fen_with_roster(fen, g, capablanca.clone()).unwrap();
+            check_unmake(&mut state, 2);
+        }
+    }
+
+    #[test]
+    fn capablanca_pieces_come_from_their_definitions() {
+        let fen = "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+        let g = BoardGeometry::CAPABLANCA;
+        assert!(WideGameState::from_fen(fen, g).is_err());
+        let state = WideGameState::from_fen_with_roster(fen, g, Roster::capablanca()).unwrap();
+        assert_eq!(state.to_fen(), fen);
+        assert_eq!(state.perft(1), 28);
+        assert_eq!(state.perft(2), 784);
+        assert_eq!(state.perft(3), 25228);
+    }
+
+    #[test]
+    fn pawns_promote_to_fairy_pieces() {
+        let g = BoardGeometry::CAPABLANCA;
+        let state = WideGameState::from_fen_with_roster(
+            "10/P9/10/10/10/10/10/K8k w - - 0 1",
+            g,
+            Roster::capablanca(),
+        )
+        .unwrap();
+        let promotions: Vec<PieceId> = state
+            .generate_legal_moves()
+            .into_iter()
+            .filter_map(|mv| match mv.flag {
+                MoveFlag::Promotion(id) => Some(id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(promotions.len(), 6);
+        let chancellor = state.roster().id_of('C').unwrap();
+        let mv = Move {
+            from: g.square(0, 6),
+            to: g.square(0, 7),
+            flag: MoveFlag::Promotion(chancellor),
+        };
+        assert_eq!(
+            state.apply_move(mv).to_fen(),
+            "C9/10/10/10/10/10/10/K8k b - - 0 1"
+        );
+    }
+
+    #[test]
+    fn cannons_check_only_over_a_screen() {
+        let mut roster = Roster::standard();
+        roster.add(PieceDef::cannon()).unwrap();
+        let g = BoardGeometry::STANDARD;
+        let screened = WideGameState::from_fen_with_roster(
+            "4k3/8/4n3/8/8/8/8/K3O3 b - - 0 1",
+            g,
+            roster.clone(),
+        )
+        .unwrap();
+        assert!(screened.is_in_check(PieceColor::Black));
+        // The screen may step aside, and the cannon then no longer attacks
+        assert!(
+            screened
+                .generate_legal_moves()
+                .iter()
+                .any(|mv| mv.from == g.square(4, 5))
+        );
+
+        // Without a screen the pawn on e7 is out of reach, though e2-e6 are not
+        let open =
+            WideGameState::from_fen_with_roster("k7/4p3/8/8/8/8/8/K3O3 w - - 0 1", g, roster)
+                .unwrap();
+        let cannon_moves: Vec<WideMove> = open
+            .generate_legal_moves()
+            .into_iter()
+            .filter(|mv| mv.from == g.square(4, 0))
+            .collect();
+        assert!(cannon_moves.iter().all(|mv| mv.flag == MoveFlag::Quiet));
+        assert!(cannon_moves.iter().any(|mv| mv.to == g.square(4, 5)));
+    }
+}
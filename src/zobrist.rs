@@ -0,0 +1,195 @@
+use std::sync::LazyLock;
+
+use crate::{
+    bitboard::BitBoard,
+    game::{CastlingRights, GameState, Rules},
+    movegen::pawn_attacks,
+    rendering::{PieceColor, PieceType},
+};
+
+/// Random keys XORed together to form a position's 64-bit Zobrist hash.
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 2]; 6],
+    black_to_move: u64,
+    castling: [[u64; 2]; 2],
+    en_passant_file: [u64; 8],
+    atomic: u64,
+}
+
+/// Keys are generated from a fixed seed, so hashes are stable across runs and
+/// can be stored (e.g. to index game databases).
+pub static ZOBRIST: LazyLock<ZobristKeys> = LazyLock::new(|| {
+    let mut seed = 0x5EED_C0DE_CAFE_F00Du64;
+    let mut next = || splitmix64(&mut seed);
+
+    let mut pieces = [[[0u64; 64]; 2]; 6];
+    for per_color in pieces.iter_mut() {
+        for per_square in per_color.iter_mut() {
+            for key in per_square.iter_mut() {
+                *key = next();
+            }
+        }
+    }
+    let black_to_move = next();
+    let castling = [[next(), next()], [next(), next()]];
+    let en_passant_file = std::array::from_fn(|_| next());
+    // Drawn last so that the keys above stay what they always were
+    let atomic = next();
+
+    ZobristKeys { pieces, black_to_move, castling, en_passant_file, atomic }
+});
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl ZobristKeys {
+    #[inline]
+    pub fn piece(&self, pt: PieceType, pc: PieceColor, sq: u8) -> u64 {
+        self.pieces[pt as usize][pc as usize][sq as usize]
+    }
+
+    #[inline]
+    pub fn side(&self) -> u64 {
+        self.black_to_move
+    }
+
+    #[inline]
+    pub fn en_passant(&self, sq: u8) -> u64 {
+        self.en_passant_file[(sq % 8) as usize]
+    }
+
+    /// Key of the rules a position is played under, 0 for standard chess:
+    /// the same pieces make a different position under other rules.
+    #[inline]
+    pub fn rules(&self, rules: Rules) -> u64 {
+        match rules {
+            Rules::Standard => 0,
+            Rules::Atomic => self.atomic,
+        }
+    }
+
+    /// Combined key of every castling right still available.
+    pub fn castling(&self, rights: &CastlingRights) -> u64 {
+        let mut key = 0;
+        for color in PieceColor::ALL {
+            if rights.kingside(color) {
+                key ^= self.castling[color as usize][0];
+            }
+            if rights.queenside(color) {
+                key ^= self.castling[color as usize][1];
+            }
+        }
+        key
+    }
+}
+
+impl GameState {
+    /// Computes the Zobrist hash from scratch. `apply_move` keeps `hash` up to
+    /// date incrementally, this is for freshly built positions and for checking.
+    pub fn compute_hash(&self) -> u64 {
+        let keys = &*ZOBRIST;
+        let mut hash = 0;
+
+        for (pt, pc, bb) in self.bitboards() {
+            for sq in bb {
+                hash ^= keys.piece(pt, pc, sq);
+            }
+        }
+        if self.side_to_move == PieceColor::Black {
+            hash ^= keys.side();
+        }
+        hash ^= keys.castling(&self.castling_rights);
+        hash ^= keys.rules(self.rules);
+        hash ^ self.en_passant_key()
+    }
+
+    /// Key of the en passant square, or 0 when no pawn of the side to move
+    /// could take there. As in Polyglot, a double push nobody can answer en
+    /// passant leaves the hash as it would be without it, so that the
+    /// position can repeat.
+    pub(crate) fn en_passant_key(&self) -> u64 {
+        let Some(sq) = self.en_passant else {
+            return 0;
+        };
+        let us = self.side_to_move;
+        let capturers = pawn_attacks(BitBoard::from_index(sq), us.opponent());
+        if capturers & self.piece_bb(PieceType::Pawn, us) == BitBoard(0) {
+            0
+        } else {
+            ZOBRIST.en_passant(sq)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        game::{Move, MoveFlag},
+        movegen::generate_legal_moves,
+    };
+
+    use super::*;
+
+    fn check_incremental(state: &GameState, depth: u32) {
+        assert_eq!(state.hash, state.compute_hash(), "{}", state.to_fen());
+        if depth == 0 {
+            return;
+        }
+        for mv in generate_legal_moves(state) {
+            check_incremental(&state.apply_move(mv), depth - 1);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_recompute() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            check_incremental(&GameState::from_fen(fen).unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn transpositions_share_a_hash() {
+        let start = GameState::default();
+        let shuffle = [(6, 21), (62, 45), (21, 6), (45, 62)];
+        let state = shuffle.iter().fold(start.clone(), |state, &(from, to)| {
+            state.apply_move(Move { from, to, flag: MoveFlag::Quiet })
+        });
+        assert_eq!(state.hash, start.hash);
+    }
+
+    #[test]
+    fn hash_covers_side_castling_and_en_passant() {
+        let hash = |fen| GameState::from_fen(fen).unwrap().hash;
+        let base = hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq - 0 1");
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R b KQkq - 0 1"));
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w Kkq - 0 1"));
+        assert_ne!(base, hash("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1"));
+        // Only when a pawn can take en passant
+        assert_eq!(
+            hash("r3k2r/8/8/3p4/8/8/8/R3K2R w KQkq d6 0 1"),
+            hash("r3k2r/8/8/3p4/8/8/8/R3K2R w KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn hash_covers_rules() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let standard = GameState::from_fen(fen).unwrap();
+        let mut atomic = standard.clone();
+        atomic.set_rules(Rules::Atomic);
+        assert_ne!(atomic.hash, standard.hash);
+        check_incremental(&atomic, 3);
+
+        atomic.set_rules(Rules::Standard);
+        assert_eq!(atomic.hash, standard.hash);
+    }
+}